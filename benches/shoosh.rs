@@ -0,0 +1,109 @@
+//! Benchmarks of the hot paths, run with `cargo bench`.
+
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use shoosh::{
+	detector::Detector,
+	processor::{Processor, VolumeCap, VolumeCapSettings},
+	ringbuffer::RingBuffer,
+	weighting::Weighting,
+};
+
+/// Appending a chunk to a ring buffer, against cloning and copying it into the
+/// same amount of storage.
+fn append(c: &mut Criterion) {
+	let samples = (0..64).map(|i| i as f32).collect::<Vec<_>>();
+	let mut group = c.benchmark_group("append");
+
+	let mut storage = vec![0.0f32; 128];
+	let mut offset = 0;
+	group.bench_function("clone_from_slice", |b| {
+		b.iter(|| {
+			offset = (offset + samples.len()) % storage.len();
+			storage[offset..offset + samples.len()].clone_from_slice(black_box(&samples));
+		})
+	});
+	group.bench_function("copy_from_slice", |b| {
+		b.iter(|| {
+			offset = (offset + samples.len()) % storage.len();
+			storage[offset..offset + samples.len()].copy_from_slice(black_box(&samples));
+		})
+	});
+
+	let mut buffer = RingBuffer::<f32>::new(128);
+	group.bench_function("RingBuffer::append", |b| b.iter(|| buffer.append(black_box(&samples))));
+
+	group.finish();
+}
+
+/// The peak detector against a plain fold, over a default chunk and a whole
+/// block as read from the server.
+fn peak(c: &mut Criterion) {
+	let mut group = c.benchmark_group("peak");
+
+	for len in [64, 4096] {
+		let samples = (0..len).map(|i| (i as f32 * 0.1).sin()).collect::<Vec<_>>();
+
+		group.bench_with_input(BenchmarkId::new("fold", len), &samples, |b, samples| {
+			b.iter(|| {
+				black_box(samples)
+					.iter()
+					.fold(0.0, |a: f32, &b| f32::max(a, b.abs()))
+			})
+		});
+		group.bench_with_input(BenchmarkId::new("Detector::Peak", len), &samples, |b, samples| {
+			b.iter(|| Detector::Peak.level(black_box(samples)))
+		});
+	}
+
+	group.finish();
+}
+
+/// A volume cap processing a chunk, against the per-entry weight fold the
+/// average was computed with before the weights were precomputed.
+///
+/// The fold only updates the average, while `process` also applies the gain
+/// to the chunk.
+fn volume_cap(c: &mut Criterion) {
+	let input = (0..64).map(|i| (i as f32 * 0.1).sin()).collect::<Vec<_>>();
+	let mut output = vec![0.0; input.len()];
+	let mut group = c.benchmark_group("volume_cap");
+
+	for weighting in [Weighting::Linear, Weighting::Exponential {
+		decay: Weighting::DEFAULT_DECAY,
+	}] {
+		let settings = VolumeCapSettings {
+			volume_cap: 0.5,
+			weighting,
+			..Default::default()
+		};
+		let window = settings.window;
+
+		// both start from a full window of the level of `input`
+		let mut history = RingBuffer::filled(window, Detector::Peak.level(&input));
+		group.bench_function(BenchmarkId::new("fold", weighting.name()), |b| {
+			b.iter(|| {
+				history.push(Detector::Peak.level(black_box(&input)));
+				history
+					.iter()
+					.enumerate()
+					.map(|(i, v)| v * weighting.weight(i, window))
+					.sum::<f32>() / weighting.normalization(window)
+			})
+		});
+
+		let mut volume_cap = VolumeCap::new(&settings);
+		for _ in 0..window {
+			volume_cap.process(&input, &mut output);
+		}
+		group.bench_function(BenchmarkId::new("VolumeCap::process", weighting.name()), |b| {
+			b.iter(|| volume_cap.process(black_box(&input), &mut output))
+		});
+	}
+
+	group.finish();
+}
+
+criterion_group!(benches, append, peak, volume_cap);
+criterion_main!(benches);
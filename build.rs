@@ -0,0 +1,18 @@
+use std::process::Command;
+
+fn main() {
+	println!("cargo:rerun-if-changed=.git/HEAD");
+	println!("cargo:rerun-if-changed=.git/refs");
+
+	// builds outside a git checkout, such as from a crate tarball, have no hash
+	let hash = Command::new("git")
+		.args(["rev-parse", "--short", "HEAD"])
+		.output()
+		.ok()
+		.filter(|output| output.status.success())
+		.and_then(|output| String::from_utf8(output.stdout).ok());
+
+	if let Some(hash) = hash {
+		println!("cargo:rustc-env=SHOOSH_GIT_HASH={}", hash.trim());
+	}
+}
@@ -0,0 +1,193 @@
+//! The stream I/O the processing loops run against, implemented by pulseaudio
+//! streams, PipeWire streams with the `pipewire` feature and, for tests, by
+//! canned buffers so the loops run without a server.
+
+use std::str::FromStr;
+
+use pulse::{
+	error::PAErr,
+	stream::{PeekResult, SeekMode, Stream},
+};
+
+/// The server API sessions run against.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+	/// The pulseaudio API, which PipeWire also serves
+	Pulse,
+	/// The native PipeWire API, only available with the `pipewire` feature
+	#[cfg_attr(not(feature = "pipewire"), allow(dead_code))]
+	Pipewire,
+}
+
+impl Backend {
+	/// Names accepted by `from_str`.
+	pub const NAMES: [&'static str; 2] = ["pulse", "pipewire"];
+
+	/// Returns the name `from_str` accepts for this backend.
+	pub fn name(self) -> &'static str {
+		match self {
+			Self::Pulse => "pulse",
+			Self::Pipewire => "pipewire",
+		}
+	}
+}
+
+impl FromStr for Backend {
+	type Err = String;
+
+	fn from_str(name: &str) -> Result<Self, String> {
+		match name {
+			"pulse" => Ok(Self::Pulse),
+			#[cfg(feature = "pipewire")]
+			"pipewire" => Ok(Self::Pipewire),
+			#[cfg(not(feature = "pipewire"))]
+			"pipewire" => Err("the pipewire backend needs the pipewire feature".to_string()),
+			_ => Err(format!(
+				"invalid backend '{name}', expected one of: {}",
+				Self::NAMES.join(", ")
+			)),
+		}
+	}
+}
+
+/// The recording side of a session.
+pub trait Capture {
+	/// Returns the next fragment of recorded data without consuming it.
+	fn peek(&mut self) -> Result<PeekResult<'_>, PAErr>;
+
+	/// Consumes the fragment returned by the last `peek`, data or hole.
+	fn discard(&mut self) -> Result<(), PAErr>;
+}
+
+/// The playback side of a session.
+pub trait Playback {
+	/// Returns the number of bytes that can be written without overflowing
+	/// the buffer, or `None` if it can't be queried.
+	fn writable_size(&self) -> Option<usize>;
+
+	/// Queues `data` after everything written so far.
+	fn write(&mut self, data: &[u8]) -> Result<(), PAErr>;
+}
+
+impl Capture for Stream {
+	fn peek(&mut self) -> Result<PeekResult<'_>, PAErr> {
+		Stream::peek(self)
+	}
+
+	fn discard(&mut self) -> Result<(), PAErr> {
+		Stream::discard(self)
+	}
+}
+
+impl Playback for Stream {
+	fn writable_size(&self) -> Option<usize> {
+		Stream::writable_size(self)
+	}
+
+	fn write(&mut self, data: &[u8]) -> Result<(), PAErr> {
+		Stream::write(self, data, None, 0, SeekMode::Relative)
+	}
+}
+
+#[cfg(test)]
+pub mod mock {
+	use std::collections::VecDeque;
+
+	use pulse::{error::PAErr, stream::PeekResult};
+
+	use super::{Capture, Playback};
+
+	/// A fragment handed out by `MockCapture`.
+	pub enum Fragment {
+		Data(Vec<u8>),
+		Hole(usize),
+	}
+
+	/// Hands out canned fragments in order, then reports no data.
+	pub struct MockCapture {
+		fragments: VecDeque<Fragment>,
+	}
+
+	impl MockCapture {
+		pub fn new(fragments: impl IntoIterator<Item = Fragment>) -> Self {
+			Self {
+				fragments: fragments.into_iter().collect(),
+			}
+		}
+
+		/// Queues more fragments after any not yet consumed.
+		pub fn push(&mut self, fragment: Fragment) {
+			self.fragments.push_back(fragment);
+		}
+	}
+
+	impl Capture for MockCapture {
+		fn peek(&mut self) -> Result<PeekResult<'_>, PAErr> {
+			Ok(match self.fragments.front() {
+				None => PeekResult::Empty,
+				Some(Fragment::Data(data)) => PeekResult::Data(data),
+				Some(&Fragment::Hole(size)) => PeekResult::Hole(size),
+			})
+		}
+
+		fn discard(&mut self) -> Result<(), PAErr> {
+			self.fragments
+				.pop_front()
+				.expect("discard without a fragment");
+			Ok(())
+		}
+	}
+
+	/// Collects everything written, with a buffer of limited room that only
+	/// frees up when `play` is called.
+	pub struct MockPlayback {
+		pub written: Vec<u8>,
+		/// Bytes that can be written before the buffer is full
+		pub room: usize,
+	}
+
+	impl MockPlayback {
+		pub fn new(room: usize) -> Self {
+			Self {
+				written: Vec::new(),
+				room,
+			}
+		}
+
+		/// Frees up `size` bytes of room, as if they were played.
+		pub fn play(&mut self, size: usize) {
+			self.room += size;
+		}
+	}
+
+	impl Playback for MockPlayback {
+		fn writable_size(&self) -> Option<usize> {
+			Some(self.room)
+		}
+
+		fn write(&mut self, data: &[u8]) -> Result<(), PAErr> {
+			assert!(data.len() <= self.room, "overflowed the playback buffer");
+			self.room -= data.len();
+			self.written.extend_from_slice(data);
+			Ok(())
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::Backend;
+
+	#[test]
+	fn parse_backend() {
+		assert_eq!(Ok(Backend::Pulse), "pulse".parse());
+		#[cfg(feature = "pipewire")]
+		assert_eq!(Ok(Backend::Pipewire), "pipewire".parse());
+		#[cfg(not(feature = "pipewire"))]
+		assert!("pipewire".parse::<Backend>().is_err());
+		assert_eq!(
+			Err("invalid backend 'alsa', expected one of: pulse, pipewire".to_string()),
+			"alsa".parse::<Backend>()
+		);
+	}
+}
@@ -0,0 +1,128 @@
+//! Output held back while the playback buffer is full.
+
+/// Queues processed bytes that don't fit in the playback buffer yet, writing
+/// them out in order ahead of newer data once there is room.
+pub struct Backlog {
+	frame_size: usize,
+	/// Most bytes held back before the oldest are dropped
+	limit: usize,
+	pending: Vec<u8>,
+}
+
+impl Backlog {
+	pub fn new(frame_size: usize, limit: usize) -> Self {
+		Self {
+			frame_size,
+			limit,
+			pending: Vec::new(),
+		}
+	}
+
+	/// Passes as much of the held back bytes followed by `data` to `write` as
+	/// fits in `writable` bytes, in whole frames, and holds back the rest.
+	///
+	/// Returns the number of bytes dropped, oldest first, to keep the backlog
+	/// within its limit.
+	pub fn write<E>(
+		&mut self,
+		data: &[u8],
+		writable: usize,
+		mut write: impl FnMut(&[u8]) -> Result<(), E>,
+	) -> Result<usize, E> {
+		let writable = writable - writable % self.frame_size;
+
+		// the usual case, no copy needed
+		if self.pending.is_empty() && data.len() <= writable {
+			if !data.is_empty() {
+				write(data)?;
+			}
+			return Ok(0)
+		}
+
+		self.pending.extend_from_slice(data);
+		let written = writable.min(self.pending.len());
+		if written > 0 {
+			write(&self.pending[..written])?;
+			self.pending.drain(..written);
+		}
+
+		let excess = self.pending.len().saturating_sub(self.limit);
+		let dropped = excess
+			.next_multiple_of(self.frame_size)
+			.min(self.pending.len());
+		self.pending.drain(..dropped);
+
+		Ok(dropped)
+	}
+
+	/// Returns the number of bytes held back.
+	pub fn len(&self) -> usize {
+		self.pending.len()
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::Backlog;
+
+	/// Writes `data` with room for `writable` bytes, returning what was
+	/// written and the number of bytes dropped.
+	fn write(backlog: &mut Backlog, data: &[u8], writable: usize) -> (Vec<u8>, usize) {
+		let mut written = Vec::new();
+		let dropped = backlog
+			.write::<()>(data, writable, |data| {
+				written.extend_from_slice(data);
+				Ok(())
+			})
+			.unwrap();
+		(written, dropped)
+	}
+
+	#[test]
+	fn holds_back_what_doesnt_fit() {
+		let mut backlog = Backlog::new(2, 64);
+
+		assert_eq!((vec![0, 1, 2, 3], 0), write(&mut backlog, &[0, 1, 2, 3], 8));
+		assert_eq!(0, backlog.len());
+
+		// only whole frames are written
+		assert_eq!((vec![4, 5], 0), write(&mut backlog, &[4, 5, 6, 7], 3));
+		assert_eq!(2, backlog.len());
+
+		// nothing fits, then the held back bytes go out ahead of newer ones
+		assert_eq!((vec![], 0), write(&mut backlog, &[8, 9], 0));
+		assert_eq!((vec![6, 7, 8, 9, 10, 11], 0), write(&mut backlog, &[10, 11], 100));
+		assert_eq!(0, backlog.len());
+	}
+
+	#[test]
+	fn flushes_without_new_data() {
+		let mut backlog = Backlog::new(2, 64);
+		write(&mut backlog, &[0, 1, 2, 3], 0);
+
+		assert_eq!((vec![0, 1], 0), write(&mut backlog, &[], 2));
+		assert_eq!((vec![2, 3], 0), write(&mut backlog, &[], 2));
+		assert_eq!((vec![], 0), write(&mut backlog, &[], 2));
+	}
+
+	#[test]
+	fn drops_oldest_over_limit() {
+		let mut backlog = Backlog::new(2, 4);
+
+		let data = (0..10).collect::<Vec<u8>>();
+		assert_eq!((vec![], 6), write(&mut backlog, &data, 0));
+		assert_eq!(4, backlog.len());
+
+		// the newest bytes are kept
+		assert_eq!((vec![6, 7, 8, 9], 0), write(&mut backlog, &[], 100));
+	}
+
+	#[test]
+	fn write_errors_keep_the_backlog() {
+		let mut backlog = Backlog::new(2, 64);
+		write(&mut backlog, &[0, 1], 0);
+
+		assert_eq!(Err("full"), backlog.write(&[2, 3], 4, |_| Err("full")));
+		assert_eq!(4, backlog.len());
+	}
+}
@@ -0,0 +1,63 @@
+use crate::processor::Processor;
+
+/// Multiplies each channel of interleaved samples by its own fixed gain.
+#[derive(Debug, Clone)]
+pub struct Balance {
+	/// Linear gain of each channel, in interleaved order
+	gains: Vec<f32>,
+}
+
+impl Balance {
+	/// Panics if `gains` is empty.
+	pub fn new(gains: Vec<f32>) -> Self {
+		assert!(!gains.is_empty(), "Balance needs a gain for at least one channel");
+		Self { gains }
+	}
+
+	/// Creates a balance from the gain of each channel in decibels.
+	pub fn from_db(gains_db: &[f32]) -> Self {
+		Self::new(gains_db.iter().map(|db| 10.0f32.powf(db / 20.0)).collect())
+	}
+}
+
+impl Processor for Balance {
+	/// A trailing partial frame takes the gains of the channels it does have.
+	fn process(&mut self, input: &[f32], output: &mut [f32]) {
+		assert_eq!(input.len(), output.len());
+
+		let frames = input
+			.chunks(self.gains.len())
+			.zip(output.chunks_mut(self.gains.len()));
+		for (frame, output) in frames {
+			for ((&sample, output), gain) in frame.iter().zip(output).zip(&self.gains) {
+				*output = sample * gain;
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::Balance;
+	use crate::processor::Processor;
+
+	#[test]
+	fn gains_follow_channels() {
+		let input = [1.0, 1.0, 0.5, -0.5, -1.0, 1.0];
+		let mut output = [0.0; 6];
+
+		Balance::new(vec![1.0, 0.5]).process(&input, &mut output);
+		assert_eq!([1.0, 0.5, 0.5, -0.25, -1.0, 0.5], output);
+
+		Balance::new(vec![0.0, 1.0, 2.0]).process(&input, &mut output);
+		assert_eq!([0.0, 1.0, 1.0, -0.0, -1.0, 2.0], output);
+	}
+
+	#[test]
+	fn from_db() {
+		let mut output = [0.0; 2];
+		Balance::from_db(&[0.0, -6.0]).process(&[1.0, 1.0], &mut output);
+		assert_eq!(1.0, output[0]);
+		assert!((output[1] - 0.501).abs() < 1e-3);
+	}
+}
@@ -0,0 +1,61 @@
+//! Second order IIR filters shared by the filtering stages.
+
+use std::f64::consts::PI;
+
+/// Q of a second order Butterworth filter, two of which in series make a
+/// Linkwitz-Riley filter.
+pub(crate) const BUTTERWORTH_Q: f64 = std::f64::consts::FRAC_1_SQRT_2;
+
+/// Second order IIR filter, run in transposed direct form II.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Biquad {
+	pub(crate) b: [f64; 3],
+	/// Feedback coefficients, with `a0` normalized to 1
+	pub(crate) a: [f64; 2],
+}
+
+impl Biquad {
+	/// Low-pass at `hz` from the Audio EQ Cookbook.
+	pub(crate) fn low_pass(hz: f64, q: f64, rate: u32) -> Self {
+		let (cos, a) = Self::prewarp(hz, q, rate);
+		Self::normalized([(1.0 - cos) / 2.0, 1.0 - cos, (1.0 - cos) / 2.0], a)
+	}
+
+	/// High-pass at `hz` from the Audio EQ Cookbook.
+	pub(crate) fn high_pass(hz: f64, q: f64, rate: u32) -> Self {
+		let (cos, a) = Self::prewarp(hz, q, rate);
+		Self::normalized([(1.0 + cos) / 2.0, -(1.0 + cos), (1.0 + cos) / 2.0], a)
+	}
+
+	/// All-pass turning through 180 degrees at `hz`, from the Audio EQ
+	/// Cookbook.
+	pub(crate) fn all_pass(hz: f64, q: f64, rate: u32) -> Self {
+		let (_, a) = Self::prewarp(hz, q, rate);
+		// the feedback coefficients reversed
+		Self::normalized([a[2], a[1], a[0]], a)
+	}
+
+	/// Returns `cos(w0)` and the feedback coefficients every cookbook filter
+	/// here shares.
+	fn prewarp(hz: f64, q: f64, rate: u32) -> (f64, [f64; 3]) {
+		let w0 = 2.0 * PI * hz / rate as f64;
+		let (cos, alpha) = (w0.cos(), w0.sin() / (2.0 * q));
+		(cos, [1.0 + alpha, -2.0 * cos, 1.0 - alpha])
+	}
+
+	fn normalized(b: [f64; 3], a: [f64; 3]) -> Self {
+		Self {
+			b: b.map(|b| b / a[0]),
+			a: [a[1] / a[0], a[2] / a[0]],
+		}
+	}
+
+	/// Filters one sample, with `state` holding what carries over to the
+	/// next.
+	pub(crate) fn process(&self, state: &mut [f64; 2], x: f64) -> f64 {
+		let y = self.b[0] * x + state[0];
+		state[0] = self.b[1] * x - self.a[0] * y + state[1];
+		state[1] = self.b[2] * x - self.a[1] * y;
+		y
+	}
+}
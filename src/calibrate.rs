@@ -0,0 +1,100 @@
+//! Measurement of the input level for `--calibrate`.
+
+use std::time::Duration;
+
+use shoosh::detector::Detector;
+
+/// How long live input is observed before recommending a volume cap.
+pub const CALIBRATION_TIME: Duration = Duration::from_secs(5);
+
+/// Chunks with an RMS level below this are considered silence and ignored,
+/// so pauses don't pull the measurement down.
+const SILENCE: f32 = 0.0003; // about -70 dB
+
+/// Accumulates the detector and RMS levels of the input, chunk by chunk.
+pub struct Calibration {
+	detector: Detector,
+	chunk_size: usize,
+	level_sum: f64,
+	rms_sum: f64,
+	chunks: u32,
+}
+
+impl Calibration {
+	pub fn new(detector: Detector, chunk_size: usize) -> Self {
+		Self {
+			detector,
+			chunk_size,
+			level_sum: 0.0,
+			rms_sum: 0.0,
+			chunks: 0,
+		}
+	}
+
+	/// Adds a block of interleaved samples to the measurement.
+	pub fn add(&mut self, samples: &[f32]) {
+		for chunk in samples.chunks(self.chunk_size) {
+			let rms = Detector::Rms.level(chunk);
+			if rms < SILENCE {
+				continue
+			}
+
+			self.level_sum += self.detector.level(chunk) as f64;
+			self.rms_sum += rms as f64;
+			self.chunks += 1;
+		}
+	}
+
+	/// Returns the average RMS level of the non-silent input in decibels, or
+	/// `None` if it was all silence.
+	pub fn rms_db(&self) -> Option<f32> {
+		(self.chunks > 0).then(|| to_db(self.rms_sum / self.chunks as f64))
+	}
+
+	/// Returns the volume cap in decibels that brings the average RMS level of
+	/// the input to `target_db`, or `None` if it was all silence.
+	///
+	/// The cap applies to the detector level, so it sits above the target by
+	/// the input's average ratio of detector level to RMS level.
+	pub fn volume_cap_db(&self, target_db: f32) -> Option<f32> {
+		(self.chunks > 0).then(|| target_db + to_db(self.level_sum / self.rms_sum))
+	}
+}
+
+fn to_db(level: f64) -> f32 {
+	(20.0 * level.log10()) as f32
+}
+
+#[cfg(test)]
+mod test {
+	use std::f32::consts::{SQRT_2, TAU};
+
+	use shoosh::detector::Detector;
+
+	use super::Calibration;
+
+	#[test]
+	fn sine_crest_factor() {
+		let sine = (0..64 * 100)
+			.map(|i| 0.5 * (i as f32 / 64.0 * TAU).sin())
+			.collect::<Vec<_>>();
+
+		let mut calibration = Calibration::new(Detector::Peak, 64);
+		assert_eq!(None, calibration.volume_cap_db(-20.0));
+
+		calibration.add(&sine);
+		// silence is ignored
+		calibration.add(&[0.0; 64 * 100]);
+
+		let rms_db = 20.0 * (0.5 / SQRT_2).log10();
+		assert!((calibration.rms_db().unwrap() - rms_db).abs() < 0.01);
+
+		// a peak detector sees a sine 3 dB above its RMS level
+		let cap = calibration.volume_cap_db(-20.0).unwrap();
+		assert!((cap - (-20.0 + 20.0 * SQRT_2.log10())).abs() < 0.01, "cap {cap}");
+
+		let mut calibration = Calibration::new(Detector::Rms, 64);
+		calibration.add(&sine);
+		assert!((calibration.volume_cap_db(-20.0).unwrap() + 20.0).abs() < 0.01);
+	}
+}
@@ -0,0 +1,127 @@
+use std::fmt;
+
+use crate::processor::Processor;
+
+/// Routes the channels of interleaved samples to new positions.
+///
+/// Each output channel takes the samples of one input channel, or is silent
+/// if nothing is routed to it. An input channel may feed several outputs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChannelMap {
+	/// Input channel feeding each output channel
+	sources: Vec<Option<usize>>,
+}
+
+impl ChannelMap {
+	/// Panics if `sources` is empty or refers to a channel it doesn't have.
+	pub fn new(sources: Vec<Option<usize>>) -> Self {
+		assert!(!sources.is_empty(), "ChannelMap needs at least one channel");
+		assert!(
+			sources
+				.iter()
+				.flatten()
+				.all(|&source| source < sources.len()),
+			"ChannelMap source out of range"
+		);
+
+		Self { sources }
+	}
+
+	/// Parses comma separated `IN:OUT` pairs of channel indices for
+	/// `channels` channels, such as `0:1,1:0` to swap stereo channels.
+	pub fn parse(map: &str, channels: usize) -> Result<Self, String> {
+		let mut sources = vec![None; channels];
+
+		for route in map.split(',') {
+			let Some((input, output)) = route.split_once(':') else {
+				return Err(format!("expected IN:OUT, got '{route}'"))
+			};
+
+			let channel = |index: &str| match index.trim().parse::<usize>() {
+				Ok(index) if index < channels => Ok(index),
+				_ => Err(format!("'{index}' is not a channel, expected 0 to {}", channels - 1)),
+			};
+			let (input, output) = (channel(input)?, channel(output)?);
+
+			if sources[output].replace(input).is_some() {
+				return Err(format!("output channel {output} is mapped more than once"))
+			}
+		}
+
+		Ok(Self::new(sources))
+	}
+}
+
+/// Formats the map the way `parse` reads it.
+impl fmt::Display for ChannelMap {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let routes = self
+			.sources
+			.iter()
+			.enumerate()
+			.filter_map(|(output, source)| source.map(|input| format!("{input}:{output}")))
+			.collect::<Vec<_>>();
+		write!(f, "{}", routes.join(","))
+	}
+}
+
+impl Processor for ChannelMap {
+	/// A trailing partial frame keeps only the outputs whose sources it has.
+	fn process(&mut self, input: &[f32], output: &mut [f32]) {
+		assert_eq!(input.len(), output.len());
+
+		let frames = input
+			.chunks(self.sources.len())
+			.zip(output.chunks_mut(self.sources.len()));
+		for (frame, output) in frames {
+			for (output, source) in output.iter_mut().zip(&self.sources) {
+				*output = source
+					.and_then(|source| frame.get(source))
+					.copied()
+					.unwrap_or(0.0);
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::ChannelMap;
+	use crate::processor::Processor;
+
+	#[test]
+	fn swap() {
+		let input = [1.0, 2.0, 3.0, 4.0, 5.0, 6.0];
+		let mut output = [0.0; 6];
+
+		ChannelMap::parse("0:1,1:0", 2)
+			.unwrap()
+			.process(&input, &mut output);
+		assert_eq!([2.0, 1.0, 4.0, 3.0, 6.0, 5.0], output);
+	}
+
+	#[test]
+	fn duplicate_and_silence() {
+		let input = [1.0, 2.0, 3.0, 4.0, 5.0, 6.0];
+		let mut output = [0.0; 6];
+
+		// the first input feeds both of the first outputs, the last gets nothing
+		ChannelMap::parse("0:0,0:1", 3)
+			.unwrap()
+			.process(&input, &mut output);
+		assert_eq!([1.0, 1.0, 0.0, 4.0, 4.0, 0.0], output);
+	}
+
+	#[test]
+	fn parse() {
+		assert_eq!(Ok(ChannelMap::new(vec![Some(1), Some(0)])), ChannelMap::parse("0:1, 1:0", 2));
+		assert!(ChannelMap::parse("0:2", 2).is_err());
+		assert!(ChannelMap::parse("0:1,1:1", 2).is_err());
+		assert!(ChannelMap::parse("0-1", 2).is_err());
+		assert!(ChannelMap::parse("", 2).is_err());
+
+		for map in ["1:0,0:1", "0:0,0:2"] {
+			assert_eq!(map, ChannelMap::parse(map, 3).unwrap().to_string());
+		}
+	}
+}
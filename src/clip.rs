@@ -0,0 +1,41 @@
+use crate::processor::Processor;
+
+/// Hard-limits every sample to `[-limit, limit]`.
+#[derive(Debug, Clone, Copy)]
+pub struct Clip {
+	limit: f32,
+}
+
+impl Clip {
+	pub fn new(limit: f32) -> Self {
+		Self { limit }
+	}
+}
+
+impl Processor for Clip {
+	fn process(&mut self, input: &[f32], output: &mut [f32]) {
+		assert_eq!(input.len(), output.len());
+
+		for (&sample, output) in input.iter().zip(output) {
+			*output = sample.clamp(-self.limit, self.limit);
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::Clip;
+	use crate::processor::Processor;
+
+	#[test]
+	fn bounds_output() {
+		let input = [0.0, 0.5, -0.5, 1.0, -1.0, 1.5, -3.0, f32::INFINITY];
+		let mut output = [0.0; 8];
+
+		Clip::new(1.0).process(&input, &mut output);
+		assert_eq!([0.0, 0.5, -0.5, 1.0, -1.0, 1.0, -1.0, 1.0], output);
+
+		Clip::new(0.25).process(&input, &mut output);
+		assert!(output.iter().all(|v| v.abs() <= 0.25));
+	}
+}
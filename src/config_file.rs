@@ -0,0 +1,111 @@
+//! Options loaded from a TOML config file.
+//!
+//! Each key is the long name of a command line option, for example:
+//!
+//! ```toml
+//! volume-db = -12.5
+//! sink = "alsa_output.pci-0000_00_1f.3.analog-stereo"
+//! window = 64
+//! meter = true
+//! ```
+//!
+//! Options given on the command line take precedence over the config file,
+//! which takes precedence over the built-in defaults. The file given with
+//! `--config` is used if present, otherwise
+//! `$XDG_CONFIG_HOME/shoosh/config.toml` (or `~/.config/shoosh/config.toml`) if
+//! it exists.
+//!
+//! Only top level keys with string, number and boolean values are supported.
+
+use std::{
+	env,
+	fs,
+	path::{Path, PathBuf},
+};
+
+use toml::{Table, Value};
+
+/// Returns the default config file location.
+pub fn default_path() -> Option<PathBuf> {
+	let config_home = match env::var_os("XDG_CONFIG_HOME") {
+		Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+		_ => PathBuf::from(env::var_os("HOME")?).join(".config"),
+	};
+
+	Some(config_home.join("shoosh").join("config.toml"))
+}
+
+/// Loads the config file at `path`, returning its entries as option name and
+/// value pairs. Flags have no value, and flags set to `false` are left out.
+pub fn load(path: &Path) -> Result<Vec<(String, Option<String>)>, String> {
+	let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+	parse(&text)
+}
+
+/// Parses the text of a config file like `load`.
+pub fn parse(text: &str) -> Result<Vec<(String, Option<String>)>, String> {
+	let table = text.parse::<Table>().map_err(|e| e.to_string())?;
+	let mut entries = Vec::new();
+
+	for (key, value) in table {
+		if key == "config" {
+			return Err(format!("invalid key '{key}'"))
+		}
+
+		let value = match value {
+			Value::Boolean(false) => continue,
+			Value::Boolean(true) => None,
+			Value::String(value) => Some(value),
+			Value::Integer(value) => Some(value.to_string()),
+			Value::Float(value) => Some(value.to_string()),
+			Value::Table(_) => return Err(format!("'{key}': tables are not supported")),
+			_ => return Err(format!("'{key}': unsupported value")),
+		};
+
+		entries.push((key, value));
+	}
+
+	Ok(entries)
+}
+
+#[cfg(test)]
+mod test {
+	use super::parse;
+
+	#[test]
+	fn parse_values() {
+		let text = r#"
+			# comment
+			volume = -12.5
+			sink = "a \"quoted\" name" # trailing comment
+			source = 'literal\path'
+			window = 1_024
+			meter = true
+			no-reconnect = false
+		"#;
+
+		let entries = parse(text).unwrap();
+		assert_eq!(
+			vec![
+				("meter".to_string(), None),
+				("sink".to_string(), Some("a \"quoted\" name".to_string())),
+				("source".to_string(), Some("literal\\path".to_string())),
+				("volume".to_string(), Some("-12.5".to_string())),
+				("window".to_string(), Some("1024".to_string())),
+			],
+			entries
+		);
+	}
+
+	#[test]
+	fn parse_errors() {
+		assert!(parse("[table]")
+			.unwrap_err()
+			.contains("tables are not supported"));
+		assert!(parse("\nvolume").unwrap_err().contains("line 2"));
+		assert!(parse("multiband = [200, 2000]").is_err());
+		assert!(parse("sink = \"unterminated").is_err());
+		assert!(parse("sink = unquoted").is_err());
+		assert!(parse("config = \"other.toml\"").is_err());
+	}
+}
@@ -0,0 +1,230 @@
+//! Line based control socket for changing settings while running.
+//!
+//! Clients connect to the Unix socket given with `--control` and send one
+//! command per line. Each command gets a single line reply, `ok` or the
+//! requested values on success and `error: <reason>` otherwise.
+//!
+//! - `volume <linear>` or `volume <decibels>dB` sets the volume cap
+//! - `stats` replies with the current volume cap, gain, chunk level, weighted
+//!   average, input peak and RMS, and the blocks processed and underruns and
+//!   overruns since starting
+//!
+//! For example `echo 'volume -12dB' | socat - UNIX-CONNECT:/tmp/shoosh.sock`.
+
+use std::{
+	fs,
+	io::{self, BufRead, BufReader, Write},
+	os::unix::net::{UnixListener, UnixStream},
+	path::{Path, PathBuf},
+	sync::{
+		atomic::{AtomicU32, AtomicU64, Ordering},
+		Arc,
+		Mutex,
+	},
+	thread,
+};
+
+use pulse::volume::{VolumeDB, VolumeLinear};
+use shoosh::processor::Measurement;
+
+/// Settings and measurements shared between the control socket and the
+/// pipeline.
+pub struct Control {
+	/// Linear volume cap, as `f32` bits
+	volume_cap: AtomicU32,
+	measurement: Mutex<Measurement>,
+	/// Peak level of the last input block, as `f32` bits
+	input_peak: AtomicU32,
+	/// RMS level of the last input block, as `f32` bits
+	input_rms: AtomicU32,
+	/// Totals since starting, across reconnects
+	blocks: AtomicU64,
+	underruns: AtomicU64,
+	overruns: AtomicU64,
+}
+
+impl Control {
+	pub fn new(volume_cap: f32) -> Self {
+		Self {
+			volume_cap: AtomicU32::new(volume_cap.to_bits()),
+			measurement: Mutex::new(Measurement::default()),
+			input_peak: AtomicU32::new(0.0f32.to_bits()),
+			input_rms: AtomicU32::new(0.0f32.to_bits()),
+			blocks: AtomicU64::new(0),
+			underruns: AtomicU64::new(0),
+			overruns: AtomicU64::new(0),
+		}
+	}
+
+	pub fn volume_cap(&self) -> f32 {
+		f32::from_bits(self.volume_cap.load(Ordering::Relaxed))
+	}
+
+	pub fn set_volume_cap(&self, volume_cap: f32) {
+		self.volume_cap
+			.store(volume_cap.to_bits(), Ordering::Relaxed);
+	}
+
+	pub fn measurement(&self) -> Measurement {
+		*self.measurement.lock().unwrap()
+	}
+
+	pub fn set_measurement(&self, measurement: Measurement) {
+		*self.measurement.lock().unwrap() = measurement;
+	}
+
+	pub fn input_peak(&self) -> f32 {
+		f32::from_bits(self.input_peak.load(Ordering::Relaxed))
+	}
+
+	pub fn set_input_peak(&self, input_peak: f32) {
+		self.input_peak
+			.store(input_peak.to_bits(), Ordering::Relaxed);
+	}
+
+	pub fn input_rms(&self) -> f32 {
+		f32::from_bits(self.input_rms.load(Ordering::Relaxed))
+	}
+
+	pub fn set_input_rms(&self, input_rms: f32) {
+		self.input_rms.store(input_rms.to_bits(), Ordering::Relaxed);
+	}
+
+	pub fn blocks(&self) -> u64 {
+		self.blocks.load(Ordering::Relaxed)
+	}
+
+	pub fn count_block(&self) {
+		self.blocks.fetch_add(1, Ordering::Relaxed);
+	}
+
+	pub fn underruns(&self) -> u64 {
+		self.underruns.load(Ordering::Relaxed)
+	}
+
+	pub fn count_underrun(&self) {
+		self.underruns.fetch_add(1, Ordering::Relaxed);
+	}
+
+	pub fn overruns(&self) -> u64 {
+		self.overruns.load(Ordering::Relaxed)
+	}
+
+	pub fn count_overrun(&self) {
+		self.overruns.fetch_add(1, Ordering::Relaxed);
+	}
+
+	/// Runs a single command, returning the reply.
+	fn command(&self, line: &str) -> String {
+		let mut words = line.split_whitespace();
+
+		match (words.next(), words.next(), words.next()) {
+			(Some("volume"), Some(value), None) => match parse_volume(value) {
+				Some(volume_cap) => {
+					self.set_volume_cap(volume_cap);
+					"ok".to_string()
+				}
+				None => format!("error: invalid volume '{value}'"),
+			},
+			(Some("stats"), None, _) => {
+				let measurement = self.measurement();
+				format!(
+					"volume {:.4} gain {:.4} level {:.4} average {:.4} peak {:.4} rms {:.4} \
+					 blocks {} underruns {} overruns {}",
+					self.volume_cap(),
+					measurement.gain,
+					measurement.level,
+					measurement.average,
+					self.input_peak(),
+					self.input_rms(),
+					self.blocks(),
+					self.underruns(),
+					self.overruns(),
+				)
+			}
+			(None, ..) => "error: empty command".to_string(),
+			_ => format!("error: unknown command '{line}'"),
+		}
+	}
+}
+
+/// Parses a positive linear volume, or decibels with a `dB` suffix.
+fn parse_volume(value: &str) -> Option<f32> {
+	let volume = match value.strip_suffix("dB") {
+		Some(db) => VolumeLinear::from(VolumeDB(db.parse::<f64>().ok()?)).0 as f32,
+		None => value.parse::<f32>().ok()?,
+	};
+
+	(volume.is_finite() && volume > 0.0).then_some(volume)
+}
+
+/// The bound control socket, removed when dropped.
+pub struct ControlSocket {
+	path: PathBuf,
+}
+
+impl ControlSocket {
+	/// Binds the socket at `path` and starts accepting clients for `control`
+	/// on a background thread.
+	pub fn bind(path: &Path, control: Arc<Control>) -> io::Result<Self> {
+		let listener = UnixListener::bind(path)?;
+
+		thread::spawn(move || {
+			for stream in listener.incoming() {
+				match stream {
+					Ok(stream) => {
+						let control = Arc::clone(&control);
+						thread::spawn(move || {
+							if let Err(e) = serve(&control, stream) {
+								debug!("control client failed: {e}");
+							}
+						});
+					}
+					Err(e) => warn!("failed to accept control client: {e}"),
+				}
+			}
+		});
+
+		Ok(Self {
+			path: path.to_path_buf(),
+		})
+	}
+}
+
+impl Drop for ControlSocket {
+	fn drop(&mut self) {
+		let _ = fs::remove_file(&self.path);
+	}
+}
+
+fn serve(control: &Control, stream: UnixStream) -> io::Result<()> {
+	let mut writer = stream.try_clone()?;
+
+	for line in BufReader::new(stream).lines() {
+		writeln!(writer, "{}", control.command(&line?))?;
+	}
+
+	Ok(())
+}
+
+#[cfg(test)]
+mod test {
+	use super::Control;
+
+	#[test]
+	fn commands() {
+		let control = Control::new(0.5);
+		assert!(control.command("stats").starts_with("volume 0.5000 gain"));
+
+		assert_eq!("ok", control.command("volume 0.25"));
+		assert_eq!(0.25, control.volume_cap());
+
+		assert_eq!("ok", control.command("volume -6dB"));
+		assert!((control.volume_cap() - 0.501).abs() < 1e-3);
+
+		assert!(control.command("volume -1").starts_with("error"));
+		assert!(control.command("volume").starts_with("error"));
+		assert!(control.command("louder").starts_with("error"));
+		assert!((control.volume_cap() - 0.501).abs() < 1e-3);
+	}
+}
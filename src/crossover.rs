@@ -0,0 +1,171 @@
+use crate::{
+	biquad::{Biquad, BUTTERWORTH_Q},
+	per_channel::PerChannel,
+};
+
+/// Filters of one crossover frequency.
+#[derive(Debug, Clone, Copy)]
+struct Split {
+	/// Run twice for a 4th order Linkwitz-Riley low-pass
+	low_pass: Biquad,
+	/// Run twice for a 4th order Linkwitz-Riley high-pass
+	high_pass: Biquad,
+	/// Phase response of the low-pass and high-pass summed, for the bands
+	/// split off below this frequency
+	all_pass: Biquad,
+}
+
+/// Filter state of one channel for one crossover frequency.
+#[derive(Debug, Clone)]
+struct SplitState {
+	low_pass: [[f64; 2]; 2],
+	high_pass: [[f64; 2]; 2],
+	/// All-pass state of each band below this frequency
+	all_pass: Vec<[f64; 2]>,
+}
+
+/// Splits interleaved samples into frequency bands with 4th order
+/// Linkwitz-Riley crossovers.
+///
+/// Bands split off at lower frequencies are passed through the phase
+/// response of each higher crossover, so the bands always sum back to the
+/// input's magnitude, only shifted in phase.
+#[derive(Debug, Clone)]
+pub struct Crossover {
+	splits: Vec<Split>,
+	/// State of every split for each channel
+	state: PerChannel<Vec<SplitState>>,
+	/// Scratch space for a sample of each band
+	samples: Vec<f64>,
+}
+
+impl Crossover {
+	/// Creates a crossover with a band below, between and above each of
+	/// `frequencies`.
+	///
+	/// Panics if `frequencies` is empty or not ascending.
+	pub fn new(frequencies: &[f32], channels: usize, rate: u32) -> Self {
+		assert!(!frequencies.is_empty(), "Crossover needs at least one frequency");
+		assert!(
+			frequencies.windows(2).all(|pair| pair[0] < pair[1]),
+			"Crossover frequencies must be ascending"
+		);
+
+		let splits = frequencies
+			.iter()
+			.map(|&hz| Split {
+				low_pass: Biquad::low_pass(hz as f64, BUTTERWORTH_Q, rate),
+				high_pass: Biquad::high_pass(hz as f64, BUTTERWORTH_Q, rate),
+				all_pass: Biquad::all_pass(hz as f64, BUTTERWORTH_Q, rate),
+			})
+			.collect::<Vec<_>>();
+
+		let state = (0..frequencies.len())
+			.map(|i| SplitState {
+				low_pass: [[0.0; 2]; 2],
+				high_pass: [[0.0; 2]; 2],
+				all_pass: vec![[0.0; 2]; i],
+			})
+			.collect::<Vec<_>>();
+
+		Self {
+			splits,
+			state: PerChannel::new(channels, state),
+			samples: vec![0.0; frequencies.len() + 1],
+		}
+	}
+
+	/// Returns the number of bands.
+	pub fn bands(&self) -> usize {
+		self.splits.len() + 1
+	}
+
+	/// Splits `input` into `bands`, lowest first, each resized to the length
+	/// of `input`.
+	///
+	/// Panics if there isn't one buffer per band.
+	pub fn split(&mut self, input: &[f32], bands: &mut [Vec<f32>]) {
+		assert_eq!(self.bands(), bands.len(), "Crossover needs a buffer per band");
+		for band in bands.iter_mut() {
+			band.resize(input.len(), 0.0);
+		}
+
+		let samples = &mut self.samples;
+		for (i, &x) in input.iter().enumerate() {
+			let mut rest = x as f64;
+
+			for (j, (split, state)) in self.splits.iter().zip(self.state.next_mut()).enumerate() {
+				for (sample, all_pass) in samples[..j].iter_mut().zip(&mut state.all_pass) {
+					*sample = split.all_pass.process(all_pass, *sample);
+				}
+
+				let [first, second] = &mut state.low_pass;
+				let low = split.low_pass.process(first, rest);
+				samples[j] = split.low_pass.process(second, low);
+
+				let [first, second] = &mut state.high_pass;
+				let high = split.high_pass.process(first, rest);
+				rest = split.high_pass.process(second, high);
+			}
+			samples[self.splits.len()] = rest;
+
+			for (band, &sample) in bands.iter_mut().zip(samples.iter()) {
+				band[i] = sample as f32;
+			}
+		}
+	}
+
+	/// Clears the filter state.
+	pub fn reset(&mut self) {
+		self.state.reset();
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::Crossover;
+	use crate::test_support::{settled_rms as rms, sine};
+
+	fn split(crossover: &mut Crossover, input: &[f32]) -> Vec<Vec<f32>> {
+		let mut bands = vec![Vec::new(); crossover.bands()];
+		crossover.split(input, &mut bands);
+		bands
+	}
+
+	#[test]
+	fn bands_sum_flat() {
+		for frequencies in [&[1000.0][..], &[200.0, 2000.0]] {
+			for hz in [30.0, 150.0, 200.0, 500.0, 1000.0, 2000.0, 5000.0, 15000.0] {
+				let input = sine(hz, 0.5, 2.0, 44100);
+				let bands = split(&mut Crossover::new(frequencies, 2, 44100), &input);
+
+				let sum = (0..input.len())
+					.map(|i| bands.iter().map(|band| band[i]).sum::<f32>())
+					.collect::<Vec<_>>();
+				let db = 20.0 * (rms(&sum) / rms(&input)).log10();
+				assert!(db.abs() < 0.01, "{hz}Hz over {frequencies:?}: {db}dB");
+			}
+		}
+	}
+
+	#[test]
+	fn bands_separate() {
+		let mut crossover = Crossover::new(&[200.0, 2000.0], 2, 44100);
+
+		// each tone well inside a band comes out almost entirely in that band
+		for (hz, expected) in [(50.0, 0), (630.0, 1), (8000.0, 2)] {
+			crossover.reset();
+			let input = sine(hz, 0.5, 2.0, 44100);
+			let bands = split(&mut crossover, &input);
+
+			for (band, samples) in bands.iter().enumerate() {
+				let db = 20.0 * (rms(samples) / rms(&input)).log10();
+				if band == expected {
+					assert!(db > -1.0, "{hz}Hz in band {band}: {db}dB");
+				} else {
+					assert!(db < -15.0, "{hz}Hz in band {band}: {db}dB");
+				}
+			}
+		}
+	}
+}
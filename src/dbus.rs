@@ -0,0 +1,105 @@
+//! Session bus service for desktop integration, built with the `dbus`
+//! feature and enabled with `--dbus`.
+//!
+//! Shoosh takes the name `dev.outfoxxed.Shoosh` and serves the object
+//! `/dev/outfoxxed/Shoosh` with interface `dev.outfoxxed.Shoosh`, which has
+//! these linear `d` properties, accessed through
+//! `org.freedesktop.DBus.Properties`:
+//!
+//! - `VolumeCap`, read-write, the same setting as the control socket's `volume`
+//!   command
+//! - `Gain`, the gain applied to the most recent chunk
+//! - `InputPeak`, the peak level of the most recent input block
+//!
+//! Values are read from the pipeline when requested, no change signals are
+//! sent. For example
+//! `busctl --user set-property dev.outfoxxed.Shoosh /dev/outfoxxed/Shoosh
+//! dev.outfoxxed.Shoosh VolumeCap d 0.25`.
+
+use std::{io, sync::Arc};
+
+use zbus::{
+	blocking::{connection, Connection},
+	fdo::{self, RequestNameFlags},
+	interface,
+};
+
+use crate::control::Control;
+
+const NAME: &str = "dev.outfoxxed.Shoosh";
+const PATH: &str = "/dev/outfoxxed/Shoosh";
+
+/// The `dev.outfoxxed.Shoosh` interface, served from `control`.
+struct Shoosh {
+	control: Arc<Control>,
+}
+
+#[interface(name = "dev.outfoxxed.Shoosh")]
+impl Shoosh {
+	#[zbus(property(emits_changed_signal = "false"))]
+	fn volume_cap(&self) -> f64 {
+		self.control.volume_cap() as f64
+	}
+
+	#[zbus(property)]
+	fn set_volume_cap(&mut self, volume_cap: f64) -> fdo::Result<()> {
+		let volume_cap = volume_cap as f32;
+		if !(volume_cap.is_finite() && volume_cap > 0.0) {
+			return Err(fdo::Error::InvalidArgs("VolumeCap must be positive".to_string()))
+		}
+
+		self.control.set_volume_cap(volume_cap);
+		Ok(())
+	}
+
+	#[zbus(property(emits_changed_signal = "false"))]
+	fn gain(&self) -> f64 {
+		self.control.measurement().gain as f64
+	}
+
+	#[zbus(property(emits_changed_signal = "false"))]
+	fn input_peak(&self) -> f64 {
+		self.control.input_peak() as f64
+	}
+}
+
+/// Connects to the session bus, takes the name and serves `control` until the
+/// returned connection is dropped.
+pub fn start(control: Arc<Control>) -> io::Result<Connection> {
+	let connection = connection::Builder::session()
+		.and_then(|builder| builder.serve_at(PATH, Shoosh { control }))
+		.and_then(|builder| builder.build())
+		.map_err(io::Error::other)?;
+
+	// fail rather than wait in line when another instance has the name
+	connection
+		.request_name_with_flags(NAME, RequestNameFlags::DoNotQueue.into())
+		.map_err(io::Error::other)?;
+
+	debug!("serving {NAME} on the session bus");
+	Ok(connection)
+}
+
+#[cfg(test)]
+mod test {
+	use std::sync::Arc;
+
+	use super::Shoosh;
+	use crate::control::Control;
+
+	#[test]
+	fn properties() {
+		let control = Arc::new(Control::new(0.5));
+		let mut shoosh = Shoosh {
+			control: Arc::clone(&control),
+		};
+
+		shoosh.set_volume_cap(0.25).unwrap();
+		assert_eq!(0.25, control.volume_cap());
+		assert_eq!(0.25, shoosh.volume_cap());
+
+		assert!(shoosh.set_volume_cap(0.0).is_err());
+		assert!(shoosh.set_volume_cap(f64::NAN).is_err());
+		assert_eq!(0.25, shoosh.volume_cap());
+	}
+}
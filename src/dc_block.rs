@@ -0,0 +1,74 @@
+use std::f32::consts::TAU;
+
+use crate::{per_channel::PerChannel, processor::Processor};
+
+/// Cutoff of the high-pass, giving the usual coefficient of 0.995 at 44.1kHz.
+const CUTOFF_HZ: f32 = 35.0;
+
+/// Removes DC offset with a one-pole high-pass filter on each channel:
+/// `y[n] = x[n] - x[n-1] + R * y[n-1]`.
+#[derive(Debug, Clone)]
+pub struct DcBlock {
+	coefficient: f32,
+	/// Previous input and output of each channel
+	state: PerChannel<(f32, f32)>,
+}
+
+impl DcBlock {
+	pub fn new(channels: usize, rate: u32) -> Self {
+		Self {
+			coefficient: (-TAU * CUTOFF_HZ / rate as f32).exp(),
+			state: PerChannel::new(channels, (0.0, 0.0)),
+		}
+	}
+}
+
+impl Processor for DcBlock {
+	fn process(&mut self, input: &[f32], output: &mut [f32]) {
+		assert_eq!(input.len(), output.len());
+
+		for (&x, y) in input.iter().zip(output) {
+			let (previous_x, previous_y) = self.state.next_mut();
+			*y = x - *previous_x + self.coefficient * *previous_y;
+			(*previous_x, *previous_y) = (x, *y);
+		}
+	}
+
+	fn reset(&mut self) {
+		self.state.reset();
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use std::f32::consts::TAU;
+
+	use super::DcBlock;
+	use crate::processor::Processor;
+
+	#[test]
+	fn coefficient() {
+		assert!((DcBlock::new(1, 44100).coefficient - 0.995).abs() < 1e-4);
+	}
+
+	#[test]
+	fn removes_offset() {
+		// a 1kHz stereo tone riding on different offsets per channel
+		let input = (0..44100)
+			.flat_map(|i| {
+				let tone = 0.25 * (i as f32 / 44.1 * TAU).sin();
+				[tone + 0.3, tone - 0.2]
+			})
+			.collect::<Vec<_>>();
+
+		let mut output = vec![0.0; input.len()];
+		DcBlock::new(2, 44100).process(&input, &mut output);
+
+		// the last 100ms, long after the filter has settled
+		let tail = &output[output.len() - 8820..];
+		for channel in 0..2 {
+			let mean = tail.iter().skip(channel).step_by(2).sum::<f32>() / 4410.0;
+			assert!(mean.abs() < 1e-3, "channel {channel} mean {mean}");
+		}
+	}
+}
@@ -0,0 +1,140 @@
+use std::str::FromStr;
+
+/// Measures the level of a chunk of samples.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Detector {
+	/// Largest absolute sample value
+	Peak,
+	/// Root mean square of the samples
+	Rms,
+}
+
+impl Detector {
+	/// Names accepted by `from_str`.
+	pub const NAMES: [&'static str; 2] = ["peak", "rms"];
+
+	/// Returns the name `from_str` accepts for this detector.
+	pub fn name(self) -> &'static str {
+		match self {
+			Self::Peak => "peak",
+			Self::Rms => "rms",
+		}
+	}
+
+	/// Returns the level of `chunk`.
+	pub fn level(self, chunk: &[f32]) -> f32 {
+		match self {
+			Self::Peak => peak(chunk),
+			Self::Rms => {
+				if chunk.is_empty() {
+					return 0.0
+				}
+
+				(chunk.iter().map(|v| v * v).sum::<f32>() / chunk.len() as f32).sqrt()
+			}
+		}
+	}
+}
+
+/// Lanes of the unrolled peak fold, enough for the compiler to fill a 256 bit
+/// vector.
+const LANES: usize = 8;
+
+/// Returns the largest absolute value in `samples`, or 0 if empty.
+///
+/// Folds `LANES` independent maxima so the loop vectorizes on stable, then
+/// combines them with the scalar fold of the remainder.
+fn peak(samples: &[f32]) -> f32 {
+	let chunks = samples.chunks_exact(LANES);
+	let remainder = peak_scalar(chunks.remainder());
+
+	let lanes = chunks.fold([0.0f32; LANES], |mut lanes, chunk| {
+		let chunk = <&[f32; LANES]>::try_from(chunk).unwrap();
+
+		// a comparison instead of `f32::max`, whose NaN handling keeps the loop
+		// from vectorizing; NaN still never wins as the lanes start at 0
+		for i in 0..LANES {
+			let sample = chunk[i].abs();
+			lanes[i] = if sample > lanes[i] { sample } else { lanes[i] };
+		}
+		lanes
+	});
+
+	lanes.into_iter().fold(remainder, f32::max)
+}
+
+/// Scalar fallback of `peak`.
+fn peak_scalar(samples: &[f32]) -> f32 {
+	samples.iter().fold(0.0, |a: f32, &b| f32::max(a, b.abs()))
+}
+
+impl FromStr for Detector {
+	type Err = String;
+
+	fn from_str(name: &str) -> Result<Self, String> {
+		match name {
+			"peak" => Ok(Self::Peak),
+			"rms" => Ok(Self::Rms),
+			_ => Err(format!(
+				"invalid detector '{name}', expected one of: {}",
+				Self::NAMES.join(", ")
+			)),
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use std::f32::consts::TAU;
+
+	use super::{peak, peak_scalar, Detector};
+
+	#[test]
+	fn sine_levels() {
+		// exactly 4 periods of a 0.8 amplitude sine
+		let sine = (0..256)
+			.map(|i| 0.8 * (i as f32 / 64.0 * TAU).sin())
+			.collect::<Vec<_>>();
+
+		let peak = Detector::Peak.level(&sine);
+		let rms = Detector::Rms.level(&sine);
+
+		assert!((peak - 0.8).abs() < 1e-3);
+		assert!((rms / peak - std::f32::consts::FRAC_1_SQRT_2).abs() < 1e-3);
+	}
+
+	#[test]
+	fn parse() {
+		assert_eq!(Ok(Detector::Peak), "peak".parse());
+		assert_eq!(Ok(Detector::Rms), "rms".parse());
+		assert_eq!(
+			Err("invalid detector 'loud', expected one of: peak, rms".to_string()),
+			"loud".parse::<Detector>()
+		);
+	}
+
+	#[test]
+	fn unrolled_peak_matches_scalar() {
+		for len in [0, 1, 7, 8, 9, 63, 64, 65, 1000] {
+			let samples = (0..len)
+				.map(|i| ((i * 37 % 101) as f32 / 50.0 - 1.0) * if i % 3 == 0 { -1.0 } else { 1.0 })
+				.collect::<Vec<_>>();
+			assert_eq!(peak_scalar(&samples), peak(&samples), "len {len}");
+		}
+
+		// the peak may be in any lane or the remainder
+		for at in 0..20 {
+			let mut samples = [0.25; 20];
+			samples[at] = -0.75;
+			assert_eq!(0.75, peak(&samples), "at {at}");
+		}
+
+		assert_eq!(0.5, peak(&[f32::NAN, 0.5, -0.25, f32::NAN, 0.0, 0.1, 0.2, 0.3, 0.4]));
+	}
+
+	#[test]
+	fn empty_chunk() {
+		assert_eq!(0.0, Detector::Peak.level(&[]));
+		assert_eq!(0.0, Detector::Rms.level(&[]));
+	}
+}
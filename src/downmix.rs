@@ -0,0 +1,41 @@
+/// Averages each frame of interleaved `input` into a single sample,
+/// replacing the contents of `output`.
+pub fn downmix(input: &[f32], channels: usize, output: &mut Vec<f32>) {
+	assert!(channels > 0, "channel count must be non-zero");
+
+	output.clear();
+	output.extend(
+		input
+			.chunks_exact(channels)
+			.map(|frame| frame.iter().sum::<f32>() / channels as f32),
+	);
+}
+
+#[cfg(test)]
+mod test {
+	use super::downmix;
+
+	#[test]
+	fn hard_panned() {
+		// left only, right only, then both
+		let input = [0.8, 0.0, 0.0, -0.4, 0.5, 0.25];
+		let mut output = Vec::new();
+
+		downmix(&input, 2, &mut output);
+		assert_eq!(vec![0.4, -0.2, 0.375], output);
+	}
+
+	#[test]
+	fn frames_follow_channel_count() {
+		let input = [0.3, 0.3, 0.3, 0.0, 0.0, 0.6];
+		let mut output = Vec::new();
+
+		downmix(&input, 3, &mut output);
+		assert_eq!(2, output.len());
+		assert!((output[0] - 0.3).abs() < 1e-6);
+		assert!((output[1] - 0.2).abs() < 1e-6);
+
+		downmix(&input, 1, &mut output);
+		assert_eq!(input.as_slice(), output.as_slice());
+	}
+}
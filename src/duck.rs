@@ -0,0 +1,217 @@
+//! Gain reduction driven by a second signal, the sidechain.
+//!
+//! The ducking gain multiplies the volume cap's output. The cap measures the
+//! main signal before ducking, so ducking only ever lowers the output further
+//! below the cap and never changes how the cap reacts to the main signal.
+
+use crate::{
+	detector::Detector,
+	processor::{coeff_from_ms, Processor},
+	ringbuffer::RingBuffer,
+};
+
+/// Settings for a `Ducker`.
+#[derive(Debug, Clone, Copy)]
+pub struct DuckSettings {
+	/// Linear sidechain level at or above which the main signal is ducked
+	pub threshold: f32,
+	/// Linear gain applied to the main signal while ducked
+	pub depth: f32,
+	pub detector: Detector,
+	/// Time for the ducking gain to fall once the sidechain is active
+	pub attack_ms: f32,
+	/// Time for the ducking gain to recover once the sidechain is quiet
+	pub release_ms: f32,
+	/// Number of sidechain chunk levels the sidechain must stay quiet for
+	/// before the ducking releases, must be at least 1
+	pub window: usize,
+	/// Number of interleaved sidechain samples each level is measured over,
+	/// must be at least 1
+	pub chunk_size: usize,
+	/// Sample rate in Hz
+	pub rate: u32,
+	/// Number of interleaved channels of the main signal
+	pub channels: u8,
+}
+
+impl Default for DuckSettings {
+	fn default() -> Self {
+		Self {
+			// -40 dB
+			threshold: 0.01,
+			// -12 dB
+			depth: 0.25,
+			detector: Detector::Peak,
+			attack_ms: 10.0,
+			release_ms: 500.0,
+			window: 128,
+			chunk_size: 64,
+			rate: 44100,
+			channels: 2,
+		}
+	}
+}
+
+/// Lowers its input by a fixed depth while the sidechain is active.
+///
+/// The sidechain arrives separately from the main signal, so `listen` only
+/// decides whether to duck and `process` eases the main signal toward the
+/// resulting gain.
+#[derive(Debug)]
+pub struct Ducker {
+	threshold: f32,
+	depth: f32,
+	detector: Detector,
+	chunk_size: usize,
+	/// Levels of the most recent sidechain chunks
+	levels: RingBuffer<f32>,
+	/// Gain applied to the last processed sample
+	gain: f32,
+	/// Per-sample smoothing coefficient used while the gain is falling
+	attack_coefficient: f32,
+	/// Per-sample smoothing coefficient used while the gain is rising
+	release_coefficient: f32,
+}
+
+impl Ducker {
+	/// Panics if `settings.window` or `settings.chunk_size` is zero.
+	pub fn new(settings: &DuckSettings) -> Self {
+		assert!(settings.chunk_size != 0, "Ducker chunk size must be non-zero");
+
+		let sample_rate = settings.rate * settings.channels as u32;
+
+		Self {
+			threshold: settings.threshold,
+			depth: settings.depth,
+			detector: settings.detector,
+			chunk_size: settings.chunk_size,
+			levels: RingBuffer::new(settings.window),
+			gain: 1.0,
+			attack_coefficient: coeff_from_ms(settings.attack_ms, sample_rate),
+			release_coefficient: coeff_from_ms(settings.release_ms, sample_rate),
+		}
+	}
+
+	/// Measures a block of interleaved sidechain samples.
+	pub fn listen(&mut self, sidechain: &[f32]) {
+		for chunk in sidechain.chunks(self.chunk_size) {
+			self.levels.push(self.detector.level(chunk));
+		}
+	}
+
+	/// Returns true while any sidechain chunk in the window reached the
+	/// threshold.
+	pub fn is_ducked(&self) -> bool {
+		self.levels
+			.max()
+			.is_some_and(|level| level >= self.threshold)
+	}
+
+	/// Returns the gain applied to the last processed sample.
+	pub fn gain(&self) -> f32 {
+		self.gain
+	}
+}
+
+impl Processor for Ducker {
+	fn process(&mut self, input: &[f32], output: &mut [f32]) {
+		assert_eq!(input.len(), output.len());
+
+		let (target, coefficient) = match self.is_ducked() {
+			true => (self.depth, self.attack_coefficient),
+			false => (1.0, self.release_coefficient),
+		};
+
+		for (&sample, output) in input.iter().zip(output) {
+			self.gain = target + (self.gain - target) * coefficient;
+			*output = sample * self.gain;
+		}
+	}
+
+	fn reset(&mut self) {
+		self.levels.clear();
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::{DuckSettings, Ducker};
+	use crate::{
+		processor::{Processor, VolumeCap, VolumeCapSettings},
+		test_support::sine,
+	};
+
+	const RATE: usize = 44100;
+
+	/// A signal that stays at `level`, so the cap's average matches its peak.
+	fn square(level: f32, seconds: f32) -> Vec<f32> {
+		[level, level, -level, -level].repeat((seconds * RATE as f32) as usize / 2)
+	}
+
+	fn peak(samples: &[f32]) -> f32 {
+		samples.iter().fold(0.0, |max, v| v.abs().max(max))
+	}
+
+	/// Runs the main signal through a volume cap and the ducker, a block at a
+	/// time as the pipeline does, with the sidechain alongside.
+	fn run(ducker: &mut Ducker, main: &[f32], sidechain: &[f32]) -> Vec<f32> {
+		let mut volume_cap = VolumeCap::new(&VolumeCapSettings {
+			volume_cap: 0.5,
+			..Default::default()
+		});
+		let mut limited = vec![0.0; 1024];
+		let mut output = Vec::new();
+
+		for (main, sidechain) in main.chunks(1024).zip(sidechain.chunks(1024)) {
+			ducker.listen(sidechain);
+
+			let limited = &mut limited[..main.len()];
+			volume_cap.process(main, limited);
+			let start = output.len();
+			output.resize(start + main.len(), 0.0);
+			ducker.process(limited, &mut output[start..]);
+		}
+
+		output
+	}
+
+	#[test]
+	fn ducks_while_sidechain_is_active() {
+		let mut ducker = Ducker::new(&DuckSettings {
+			release_ms: 100.0,
+			..Default::default()
+		});
+
+		// a loud main signal throughout, with a -20 dB burst on the sidechain
+		let main = square(1.0, 3.0);
+		let sidechain = [
+			sine(350.0, 0.0, 1.0, 44100),
+			sine(350.0, 0.1, 1.0, 44100),
+			sine(350.0, 0.0, 1.0, 44100),
+		]
+		.concat();
+		let output = run(&mut ducker, &main, &sidechain);
+
+		let second = RATE * 2;
+		let before = peak(&output[second / 2..second]);
+		let during = peak(&output[second * 3 / 2..second * 2]);
+		let after = peak(&output[second * 11 / 4..]);
+
+		// the cap holds the main signal at 0.5, ducking lowers it a further 12 dB
+		assert!((before - 0.5).abs() < 1e-3, "before {before}");
+		assert!((during - 0.125).abs() < 1e-3, "during {during}");
+		assert!((after - 0.5).abs() < 1e-3, "after {after}");
+	}
+
+	#[test]
+	fn quiet_sidechain_is_ignored() {
+		let mut ducker = Ducker::new(&DuckSettings::default());
+
+		// -60 dB hiss on the sidechain, below the -40 dB threshold
+		let main = square(0.25, 1.0);
+		let sidechain = sine(350.0, 0.001, 1.0, 44100);
+
+		assert_eq!(main, run(&mut ducker, &main, &sidechain));
+		assert!(!ducker.is_ducked());
+	}
+}
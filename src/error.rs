@@ -0,0 +1,106 @@
+use std::{fmt, io};
+
+use pulse::error::PAErr;
+
+/// Errors that stop shoosh from running.
+#[derive(Debug)]
+pub enum ShooshError {
+	/// The mainloop could not be created
+	MainloopCreate,
+	/// The mainloop thread could not be started
+	MainloopStart(PAErr),
+	/// The context or its property list could not be created
+	ContextCreate,
+	/// The context failed to connect to the server
+	ContextConnect(PAErr),
+	/// A stream could not be created
+	StreamCreate(&'static str),
+	/// A stream failed to connect to its device
+	StreamConnect(&'static str, PAErr),
+	/// A stream failed while running, usually because its device disappeared
+	StreamFailed(&'static str, PAErr),
+	/// Reading from or writing to a stream failed
+	Io(PAErr),
+	/// The recording file could not be created or written
+	Record(io::Error),
+	/// The input file could not be opened or read
+	InputFile(io::Error),
+	/// The control socket could not be created
+	Control(io::Error),
+	/// The session bus service could not be started
+	#[cfg_attr(not(feature = "dbus"), allow(dead_code))]
+	Dbus(io::Error),
+	/// The metrics endpoint could not be bound
+	#[cfg_attr(not(feature = "metrics"), allow(dead_code))]
+	Metrics(io::Error),
+	/// The PipeWire main loop, context or a stream could not be set up
+	#[cfg(feature = "pipewire")]
+	Pipewire(pw::Error),
+	/// A PipeWire stream failed while running
+	#[cfg(feature = "pipewire")]
+	PipewireStream(&'static str, String),
+	/// The source is the monitor of the sink
+	Feedback,
+}
+
+impl ShooshError {
+	/// Returns true if reconnecting to the server may resolve the error.
+	pub fn is_recoverable(&self) -> bool {
+		!matches!(
+			self,
+			Self::MainloopCreate
+				| Self::MainloopStart(_)
+				| Self::ContextCreate
+				| Self::Record(_)
+				| Self::InputFile(_)
+				| Self::Control(_)
+				| Self::Dbus(_)
+				| Self::Metrics(_)
+				| Self::Feedback
+		)
+	}
+}
+
+impl fmt::Display for ShooshError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::MainloopCreate => write!(f, "Failed to create mainloop"),
+			Self::MainloopStart(e) => write!(f, "Failed to start mainloop: {e}"),
+			Self::ContextCreate => write!(f, "Failed to create context"),
+			Self::ContextConnect(e) => write!(f, "Failed to connect to pulseaudio: {e}"),
+			Self::StreamCreate(name) => write!(f, "Failed to create {name} stream"),
+			Self::StreamConnect(name, e) => write!(f, "Failed to connect {name} stream: {e}"),
+			Self::StreamFailed(name, e) => write!(f, "The {name} stream failed: {e}"),
+			Self::Io(e) => write!(f, "Stream I/O failed: {e}"),
+			Self::Record(e) => write!(f, "Failed to write recording: {e}"),
+			Self::InputFile(e) => write!(f, "Failed to read input file: {e}"),
+			Self::Control(e) => write!(f, "Failed to create control socket: {e}"),
+			Self::Dbus(e) => write!(f, "Failed to register on the session bus: {e}"),
+			Self::Metrics(e) => write!(f, "Failed to serve metrics: {e}"),
+			#[cfg(feature = "pipewire")]
+			Self::Pipewire(e) => write!(f, "PipeWire failed: {e}"),
+			#[cfg(feature = "pipewire")]
+			Self::PipewireStream(name, e) => write!(f, "The {name} stream failed: {e}"),
+			Self::Feedback => write!(
+				f,
+				"The source is the monitor of the sink, which would feed the output back into the \
+				 input (use --force to run anyway)"
+			),
+		}
+	}
+}
+
+impl std::error::Error for ShooshError {}
+
+impl From<PAErr> for ShooshError {
+	fn from(e: PAErr) -> Self {
+		Self::Io(e)
+	}
+}
+
+#[cfg(feature = "pipewire")]
+impl From<pw::Error> for ShooshError {
+	fn from(e: pw::Error) -> Self {
+		Self::Pipewire(e)
+	}
+}
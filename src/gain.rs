@@ -0,0 +1,72 @@
+use crate::processor::Processor;
+
+/// Multiplies every sample by a fixed gain.
+#[derive(Debug, Clone, Copy)]
+pub struct Gain {
+	gain: f32,
+}
+
+impl Gain {
+	pub fn new(gain: f32) -> Self {
+		Self { gain }
+	}
+
+	/// Creates a gain of `gain_db` decibels.
+	pub fn from_db(gain_db: f32) -> Self {
+		Self::new(10.0f32.powf(gain_db / 20.0))
+	}
+}
+
+impl Processor for Gain {
+	fn process(&mut self, input: &[f32], output: &mut [f32]) {
+		assert_eq!(input.len(), output.len());
+
+		for (&sample, output) in input.iter().zip(output) {
+			*output = sample * self.gain;
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::Gain;
+	use crate::{
+		clip::Clip,
+		processor::{Processor, VolumeCap, VolumeCapSettings},
+	};
+
+	#[test]
+	fn from_db() {
+		let mut output = [0.0; 2];
+		Gain::from_db(6.0).process(&[0.25, -0.5], &mut output);
+		assert!((output[0] - 0.4988).abs() < 1e-3);
+		assert!((output[1] + 0.9976).abs() < 1e-3);
+	}
+
+	#[test]
+	fn makeup_respects_clip() {
+		let settings = VolumeCapSettings {
+			volume_cap: 0.5,
+			..VolumeCapSettings::default()
+		};
+		let mut volume_cap = VolumeCap::new(&settings);
+		let mut makeup = Gain::from_db(12.0);
+		let mut clip = Clip::new(1.0);
+
+		let input = (0..4096)
+			.map(|i| (i as f32 * 0.05).sin() * 0.9)
+			.collect::<Vec<_>>();
+		let mut limited = vec![0.0; input.len()];
+		let mut boosted = vec![0.0; input.len()];
+		let mut output = vec![0.0; input.len()];
+
+		volume_cap.process(&input, &mut limited);
+		makeup.process(&limited, &mut boosted);
+		clip.process(&boosted, &mut output);
+
+		// makeup brings the limited signal back over full scale, which the clip
+		// catches
+		assert!(boosted.iter().any(|v| v.abs() > 1.0));
+		assert!(output.iter().all(|v| v.abs() <= 1.0));
+	}
+}
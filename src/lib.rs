@@ -0,0 +1,54 @@
+//! Shoosh's sample processing, independent of any audio server.
+//!
+//! Without the default `std` feature only [`ringbuffer`] is built, which
+//! needs nothing beyond an allocator.
+
+#![cfg_attr(not(any(test, feature = "std")), no_std)]
+
+extern crate alloc;
+
+pub mod ringbuffer;
+
+#[cfg(feature = "std")]
+mod biquad;
+#[cfg(feature = "std")]
+mod per_channel;
+#[cfg(all(test, feature = "std"))]
+mod test_support;
+
+#[cfg(feature = "std")]
+pub mod balance;
+#[cfg(feature = "std")]
+pub mod channel_map;
+#[cfg(feature = "std")]
+pub mod clip;
+#[cfg(feature = "std")]
+pub mod crossover;
+#[cfg(feature = "std")]
+pub mod dc_block;
+#[cfg(feature = "std")]
+pub mod detector;
+#[cfg(feature = "std")]
+pub mod downmix;
+#[cfg(feature = "std")]
+pub mod duck;
+#[cfg(feature = "std")]
+pub mod gain;
+#[cfg(feature = "std")]
+pub mod loudness;
+#[cfg(feature = "std")]
+pub mod mid_side;
+#[cfg(feature = "std")]
+pub mod multiband;
+#[cfg(feature = "std")]
+pub mod processor;
+#[cfg(feature = "std")]
+pub mod softclip;
+#[cfg(feature = "std")]
+pub mod suspend;
+#[cfg(feature = "std")]
+pub mod true_peak;
+#[cfg(feature = "std")]
+pub mod unlinked;
+#[cfg(feature = "std")]
+pub mod weighting;
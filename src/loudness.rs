@@ -0,0 +1,423 @@
+//! Loudness measurement per ITU-R BS.1770 / EBU R128, and normalization of
+//! the long-term loudness toward a target.
+
+use std::f64::consts::PI;
+
+use crate::{
+	biquad::Biquad,
+	per_channel::PerChannel,
+	processor::{coeff_from_ms, Processor},
+};
+
+/// Offset in the loudness formula that makes a 1kHz tone read as its level.
+const LOUDNESS_OFFSET: f64 = -0.691;
+
+/// Blocks quieter than this never count toward the integrated loudness.
+const ABSOLUTE_GATE: f64 = -70.0;
+
+/// Blocks more than this far below the absolutely gated loudness are left
+/// out of the integrated loudness.
+const RELATIVE_GATE: f64 = -10.0;
+
+/// Length of a gating block.
+const BLOCK_MS: u32 = 400;
+
+/// Gating blocks overlap by 75%, starting every quarter block.
+const STEPS_PER_BLOCK: usize = 4;
+
+/// Resolution of the block loudness histogram, in LU.
+const BIN_WIDTH: f64 = 0.1;
+
+/// Loudest block the histogram tells apart, louder ones share its last bin.
+const HISTOGRAM_MAX: f64 = 10.0;
+
+/// Most the normalizer raises quiet input by, so noise between programmes
+/// isn't pulled up to the target.
+const MAX_BOOST_DB: f32 = 20.0;
+
+/// Time constant of the normalization gain, long enough that it follows the
+/// programme rather than individual sounds.
+const GAIN_SMOOTHING_MS: f32 = 3000.0;
+
+/// The K-weighting filter of BS.1770: a high shelf modelling the head,
+/// followed by a high-pass that discounts low frequencies.
+///
+/// The coefficients are derived for any sample rate, matching the tables in
+/// the standard at 48kHz.
+#[derive(Debug, Clone)]
+pub struct KWeighting {
+	shelf: Biquad,
+	high_pass: Biquad,
+	/// State of both stages for each channel
+	state: PerChannel<[[f64; 2]; 2]>,
+}
+
+impl KWeighting {
+	pub fn new(channels: usize, rate: u32) -> Self {
+		let rate = rate as f64;
+
+		let (f0, gain_db, q) = (1681.974450955533, 3.999843853973347, 0.7071752369554196);
+		let k = (PI * f0 / rate).tan();
+		let vh = 10.0f64.powf(gain_db / 20.0);
+		let vb = vh.powf(0.4996667741545416);
+		let a0 = 1.0 + k / q + k * k;
+		let shelf = Biquad {
+			b: [
+				(vh + vb * k / q + k * k) / a0,
+				2.0 * (k * k - vh) / a0,
+				(vh - vb * k / q + k * k) / a0,
+			],
+			a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+		};
+
+		let (f0, q) = (38.13547087602444, 0.5003270373238773);
+		let k = (PI * f0 / rate).tan();
+		let a0 = 1.0 + k / q + k * k;
+		let high_pass = Biquad {
+			b: [1.0, -2.0, 1.0],
+			a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+		};
+
+		Self {
+			shelf,
+			high_pass,
+			state: PerChannel::new(channels, [[0.0; 2]; 2]),
+		}
+	}
+
+	/// Filters the next interleaved sample.
+	fn filter(&mut self, x: f32) -> f64 {
+		let [shelf, high_pass] = self.state.next_mut();
+		let y = self.shelf.process(shelf, x as f64);
+		self.high_pass.process(high_pass, y)
+	}
+}
+
+impl Processor for KWeighting {
+	fn process(&mut self, input: &[f32], output: &mut [f32]) {
+		assert_eq!(input.len(), output.len());
+
+		for (&x, y) in input.iter().zip(output) {
+			*y = self.filter(x) as f32;
+		}
+	}
+
+	fn reset(&mut self) {
+		self.state.reset();
+	}
+}
+
+/// Measures the gated integrated loudness of everything pushed to it.
+///
+/// Block loudness is kept in a histogram of 0.1 LU bins rather than block by
+/// block, so memory stays fixed however long shoosh runs, at the cost of the
+/// relative gate being placed to within a bin. Every channel has a weight of
+/// 1, as the channel positions aren't known.
+#[derive(Debug, Clone)]
+pub struct LoudnessMeter {
+	weighting: KWeighting,
+	channels: usize,
+	/// Frames in a quarter block
+	step_frames: usize,
+	/// Sum of squares of the weighted samples in the current step so far
+	step_energy: f64,
+	/// Frames in the current step so far
+	step_position: usize,
+	/// Sum of squares of the last `STEPS_PER_BLOCK` complete steps
+	steps: [f64; STEPS_PER_BLOCK],
+	/// Complete steps so far, up to `STEPS_PER_BLOCK`
+	steps_filled: usize,
+	/// Sample count of the next sample in the current frame
+	sample_position: usize,
+	/// Number and summed mean square of the gated blocks in each bin, from the
+	/// absolute gate up
+	histogram: Vec<(u64, f64)>,
+}
+
+impl LoudnessMeter {
+	pub fn new(channels: usize, rate: u32) -> Self {
+		let bins = ((HISTOGRAM_MAX - ABSOLUTE_GATE) / BIN_WIDTH).round() as usize;
+
+		Self {
+			weighting: KWeighting::new(channels, rate),
+			channels,
+			step_frames: (rate * BLOCK_MS / 1000) as usize / STEPS_PER_BLOCK,
+			step_energy: 0.0,
+			step_position: 0,
+			steps: [0.0; STEPS_PER_BLOCK],
+			steps_filled: 0,
+			sample_position: 0,
+			histogram: vec![(0, 0.0); bins],
+		}
+	}
+
+	/// Measures interleaved `samples`, returning true if a gating block
+	/// completed, which may change the integrated loudness.
+	pub fn push(&mut self, samples: &[f32]) -> bool {
+		let mut completed = false;
+
+		for &sample in samples {
+			let y = self.weighting.filter(sample);
+			self.step_energy += y * y;
+
+			self.sample_position += 1;
+			if self.sample_position < self.channels {
+				continue
+			}
+			self.sample_position = 0;
+
+			self.step_position += 1;
+			if self.step_position == self.step_frames {
+				completed |= self.finish_step();
+			}
+		}
+
+		completed
+	}
+
+	/// Returns true if the step completed a block.
+	fn finish_step(&mut self) -> bool {
+		self.steps.copy_within(1.., 0);
+		self.steps[STEPS_PER_BLOCK - 1] = self.step_energy;
+		self.step_energy = 0.0;
+		self.step_position = 0;
+
+		self.steps_filled = (self.steps_filled + 1).min(STEPS_PER_BLOCK);
+		if self.steps_filled < STEPS_PER_BLOCK {
+			return false
+		}
+
+		let mean_square =
+			self.steps.iter().sum::<f64>() / (self.step_frames * STEPS_PER_BLOCK) as f64;
+		let loudness = loudness(mean_square);
+		if loudness > ABSOLUTE_GATE {
+			let bin =
+				(((loudness - ABSOLUTE_GATE) / BIN_WIDTH) as usize).min(self.histogram.len() - 1);
+			let (count, sum) = &mut self.histogram[bin];
+			*count += 1;
+			*sum += mean_square;
+		}
+
+		true
+	}
+
+	/// Returns the integrated loudness in LUFS, or `None` until a block above
+	/// the absolute gate has been measured.
+	pub fn integrated(&self) -> Option<f32> {
+		let (count, sum) = self
+			.histogram
+			.iter()
+			.fold((0, 0.0), |(count, sum), &(c, s)| (count + c, sum + s));
+		if count == 0 {
+			return None
+		}
+
+		let gate = loudness(sum / count as f64) + RELATIVE_GATE;
+		let first = ((gate - ABSOLUTE_GATE) / BIN_WIDTH).max(0.0) as usize;
+		let (count, sum) = self.histogram[first.min(self.histogram.len() - 1)..]
+			.iter()
+			.fold((0, 0.0), |(count, sum), &(c, s)| (count + c, sum + s));
+
+		Some(loudness(sum / count as f64) as f32)
+	}
+
+	/// Clears the filter and the current block, keeping the loudness measured
+	/// so far.
+	pub fn reset(&mut self) {
+		self.weighting.reset();
+		self.step_energy = 0.0;
+		self.step_position = 0;
+		self.steps_filled = 0;
+		self.sample_position = 0;
+	}
+}
+
+/// Returns the loudness in LUFS of a K-weighted mean square summed over
+/// channels.
+fn loudness(mean_square: f64) -> f64 {
+	LOUDNESS_OFFSET + 10.0 * mean_square.log10()
+}
+
+/// Slowly adjusts the gain so the integrated loudness of the input meets a
+/// target, leaving peaks to the volume cap after it.
+///
+/// The gain starts at unity and only moves once the first gating block has
+/// been measured.
+#[derive(Debug, Clone)]
+pub struct LoudnessNormalizer {
+	meter: LoudnessMeter,
+	channels: usize,
+	target_lufs: f32,
+	coefficient: f32,
+	/// Linear gain the current gain is moving toward
+	target_gain: f32,
+	gain: f32,
+	/// Sample count of the next sample in the current frame
+	sample_position: usize,
+}
+
+impl LoudnessNormalizer {
+	pub fn new(target_lufs: f32, channels: usize, rate: u32) -> Self {
+		Self {
+			meter: LoudnessMeter::new(channels, rate),
+			channels,
+			target_lufs,
+			coefficient: coeff_from_ms(GAIN_SMOOTHING_MS, rate),
+			target_gain: 1.0,
+			gain: 1.0,
+			sample_position: 0,
+		}
+	}
+
+	/// Returns the integrated loudness of the input so far in LUFS.
+	pub fn integrated(&self) -> Option<f32> {
+		self.meter.integrated()
+	}
+
+	/// Returns the gain applied to the most recent frame.
+	pub fn gain(&self) -> f32 {
+		self.gain
+	}
+}
+
+impl Processor for LoudnessNormalizer {
+	fn process(&mut self, input: &[f32], output: &mut [f32]) {
+		assert_eq!(input.len(), output.len());
+
+		for (&sample, output) in input.iter().zip(output) {
+			if self.meter.push(&[sample]) {
+				if let Some(integrated) = self.meter.integrated() {
+					let gain_db = (self.target_lufs - integrated).min(MAX_BOOST_DB);
+					self.target_gain = 10.0f32.powf(gain_db / 20.0);
+				}
+			}
+
+			// the gain only moves between frames, so channels stay linked
+			if self.sample_position == 0 {
+				self.gain = self.target_gain + self.coefficient * (self.gain - self.target_gain);
+			}
+			self.sample_position = (self.sample_position + 1) % self.channels;
+
+			*output = sample * self.gain;
+		}
+	}
+
+	/// Keeps the loudness measured so far and the gain, as a gap in the input
+	/// doesn't change the programme's loudness.
+	fn reset(&mut self) {
+		self.meter.reset();
+		self.sample_position = 0;
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::{KWeighting, LoudnessMeter, LoudnessNormalizer};
+	use crate::{
+		processor::Processor,
+		test_support::{settled_rms, sine},
+	};
+
+	/// `seconds` of a stereo sine at `hz` with a peak of `level_db` dBFS.
+	fn sine_db(hz: f32, level_db: f32, seconds: f32, rate: u32) -> Vec<f32> {
+		sine(hz, 10.0f32.powf(level_db / 20.0), seconds, rate)
+	}
+
+	/// Returns the gain in decibels of the K-weighting filter at `hz`.
+	fn response(hz: f32, rate: u32) -> f32 {
+		let input = sine(hz, 1.0, 2.0, rate);
+		let mut output = vec![0.0; input.len()];
+		KWeighting::new(2, rate).process(&input, &mut output);
+
+		20.0 * (settled_rms(&output) / settled_rms(&input)).log10()
+	}
+
+	#[test]
+	fn k_weighting_coefficients() {
+		// the tables in BS.1770 at 48kHz
+		let filter = KWeighting::new(1, 48000);
+		let shelf = [1.53512485958697, -2.69169618940638, 1.19839281085285];
+		let shelf_feedback = [-1.69065929318241, 0.73248077421585];
+		let high_pass_feedback = [-1.99004745483398, 0.99007225036621];
+
+		for (a, b) in filter.shelf.b.iter().zip(shelf) {
+			assert!((a - b).abs() < 1e-9, "{a} != {b}");
+		}
+		for (a, b) in filter.shelf.a.iter().zip(shelf_feedback) {
+			assert!((a - b).abs() < 1e-9, "{a} != {b}");
+		}
+		assert_eq!([1.0, -2.0, 1.0], filter.high_pass.b);
+		for (a, b) in filter.high_pass.a.iter().zip(high_pass_feedback) {
+			assert!((a - b).abs() < 1e-9, "{a} != {b}");
+		}
+	}
+
+	#[test]
+	fn k_weighting_response() {
+		for rate in [44100, 48000] {
+			for (hz, expected_db) in [
+				(20.0, -13.27),
+				(100.0, -1.13),
+				(500.0, 0.04),
+				(1000.0, 0.70),
+				(2000.0, 3.07),
+				(10000.0, 4.04),
+			] {
+				let db = response(hz, rate);
+				assert!((db - expected_db).abs() < 0.05, "{hz}Hz at {rate}: {db}dB");
+			}
+		}
+	}
+
+	#[test]
+	fn integrated_loudness() {
+		// EBU Tech 3341 case 1: a 1kHz stereo sine at -23dBFS reads -23 LUFS
+		let mut meter = LoudnessMeter::new(2, 48000);
+		assert!(meter.integrated().is_none());
+		meter.push(&sine_db(1000.0, -23.0, 20.0, 48000));
+		let integrated = meter.integrated().unwrap();
+		assert!((integrated + 23.0).abs() < 0.1, "{integrated}");
+
+		// case 3: 10s at -36, 60s at -23 and 10s at -36 again, where the relative
+		// gate drops the quieter parts
+		let mut meter = LoudnessMeter::new(2, 48000);
+		meter.push(&sine_db(1000.0, -36.0, 10.0, 48000));
+		meter.push(&sine_db(1000.0, -23.0, 60.0, 48000));
+		meter.push(&sine_db(1000.0, -36.0, 10.0, 48000));
+		let integrated = meter.integrated().unwrap();
+		assert!((integrated + 23.0).abs() < 0.1, "{integrated}");
+
+		// silence is gated out entirely
+		let mut meter = LoudnessMeter::new(2, 48000);
+		meter.push(&vec![0.0; 96000]);
+		assert!(meter.integrated().is_none());
+	}
+
+	#[test]
+	fn normalizes_toward_target() {
+		let input = sine_db(1000.0, -30.0, 30.0, 44100);
+		let mut output = vec![0.0; input.len()];
+		let mut normalizer = LoudnessNormalizer::new(-16.0, 2, 44100);
+		normalizer.process(&input, &mut output);
+
+		let mut meter = LoudnessMeter::new(2, 44100);
+		meter.push(&output[output.len() - 44100 * 2 * 5..]);
+		let integrated = meter.integrated().unwrap();
+		assert!((integrated + 16.0).abs() < 0.2, "{integrated}");
+
+		// the channels share the gain
+		for frame in output.chunks(2) {
+			assert_eq!(frame[0], -frame[1]);
+		}
+	}
+
+	#[test]
+	fn quiet_input_boost_is_limited() {
+		let input = sine_db(1000.0, -60.0, 30.0, 44100);
+		let mut output = vec![0.0; input.len()];
+		let mut normalizer = LoudnessNormalizer::new(-16.0, 2, 44100);
+		normalizer.process(&input, &mut output);
+
+		assert!((normalizer.gain() - 10.0).abs() < 0.1, "{}", normalizer.gain());
+	}
+}
@@ -1,214 +1,1597 @@
-use std::{env, mem, num::ParseFloatError};
+use std::{
+	cell::RefCell,
+	env,
+	fmt::Write as _,
+	io::Write as _,
+	num::{ParseFloatError, ParseIntError},
+	path::{Path, PathBuf},
+	process,
+	rc::Rc,
+	sync::Arc,
+	thread,
+	time::{Duration, Instant},
+};
 
-use getopts::Options;
+use env_logger::Env;
+use getopts::{Matches, Options};
+use log::LevelFilter;
 use pulse::{
-	context::{self, Context},
-	def::BufferAttr,
-	mainloop::standard::{IterateResult, Mainloop},
-	proplist::{self, Proplist},
-	sample::{Format, Spec},
-	stream::{self, PeekResult, SeekMode, Stream}, volume::{VolumeDB, VolumeLinear},
+	sample::Spec,
+	volume::{VolumeDB, VolumeLinear},
+};
+use shoosh::{
+	channel_map::ChannelMap,
+	detector::Detector,
+	duck::DuckSettings,
+	processor::VolumeCapSettings,
+	weighting::Weighting,
+};
+
+use crate::{
+	backend::Backend,
+	calibrate::{Calibration, CALIBRATION_TIME},
+	control::{Control, ControlSocket},
+	error::ShooshError,
+	pipeline::Pipeline,
+	recorder::Recorder,
+	sample::SampleFormat,
+	session::{HoleMode, Session, Shared},
+	stats::Stats,
+	wav::WavReader,
 };
 
-use crate::ringbuffer::RingBuffer;
+#[macro_use]
+extern crate log;
 
-mod ringbuffer;
+mod backend;
+mod backlog;
+mod calibrate;
+mod config_file;
+mod control;
+#[cfg(feature = "dbus")]
+mod dbus;
+mod error;
+mod meter;
+#[cfg(feature = "metrics")]
+mod metrics;
+mod pipeline;
+#[cfg(feature = "pipewire")]
+mod pipewire;
+mod recorder;
+mod sample;
+mod session;
+mod shutdown;
+mod stats;
+mod wav;
 
 fn main() {
 	let args = env::args().collect::<Vec<_>>();
 
-	let mut opts = Options::new();
-	opts.optflag("h", "help", "print this help");
-	opts.optopt(
-		"v",
-		"volume",
-		"maximum allowable volume in decibels",
-		"VOLUME",
-	);
+	let opts = options();
 	let matches = match opts.parse(&args[1..]) {
 		Ok(x) => x,
 		Err(e) => {
-			println!("{}", e.to_string());
-			return
+			eprintln!("{}", e);
+			process::exit(1);
 		}
 	};
 
+	let config_path = match matches.opt_str("config") {
+		Some(path) => Some(PathBuf::from(path)),
+		None => config_file::default_path().filter(|path| path.exists()),
+	};
+
+	let matches = match config_path {
+		Some(path) => match merge_config_file(&opts, &args[1..], matches, &path) {
+			Ok(x) => x,
+			Err(e) => {
+				eprintln!("config file '{}': {e}", path.display());
+				process::exit(1);
+			}
+		},
+		None => matches,
+	};
+
 	if matches.opt_present("h") {
 		print!("{}", opts.usage(&format!("usage: {} [options]", args[0].to_owned())));
 		return
 	}
 
-	let volume_cap_db = match matches.opt_get::<f32>("v") {
-		Ok(None) => {
-			println!("volume cap must be specified (-v)");
-			return
+	if matches.opt_present("V") {
+		match option_env!("SHOOSH_GIT_HASH") {
+			Some(hash) => println!("shoosh {} ({hash})", env!("CARGO_PKG_VERSION")),
+			None => println!("shoosh {}", env!("CARGO_PKG_VERSION")),
+		}
+		return
+	}
+
+	let mut logger = env_logger::Builder::from_env(Env::default().default_filter_or("info"));
+	match matches.opt_count("verbose") {
+		_ if matches.opt_present("q") => logger.filter_level(LevelFilter::Error),
+		0 => &mut logger,
+		1 => logger.filter_level(LevelFilter::Debug),
+		_ => logger.filter_level(LevelFilter::Trace),
+	}
+	.format(|buf, record| writeln!(buf, "[{}] {}", record.level(), record.args()))
+	.init();
+
+	let backend = match matches.opt_get_default("backend", Backend::Pulse) {
+		Ok(x) => x,
+		Err(e) => {
+			eprintln!("{e}");
+			process::exit(1);
+		}
+	};
+	if backend == Backend::Pipewire {
+		let unsupported = ["list-devices", "calibrate", "sidechain", "print-latency"]
+			.into_iter()
+			.find(|&name| matches.opt_present(name));
+		if let Some(name) = unsupported {
+			eprintln!("--{name} isn't supported by the pipewire backend");
+			process::exit(1);
+		}
+	}
+
+	if matches.opt_present("list-devices") {
+		if let Err(e) = session::list_devices(matches.opt_str("server").as_deref()) {
+			error!("{e}");
+			process::exit(1);
 		}
+
+		return
+	}
+
+	let calibrate_db = match matches.opt_get::<f32>("calibrate") {
+		Ok(x) => x,
 		Err(ParseFloatError { .. }) => {
-			println!("volume cap must be specified in decibels (ex: -12.5)");
-			return
+			eprintln!("calibration target must be specified in decibels (ex: -20)");
+			process::exit(1);
 		}
-		Ok(Some(x)) => x,
 	};
 
-	let volume_cap_linear = VolumeLinear::from(VolumeDB(volume_cap_db as f64)).0 as f32;
+	if matches.opt_present("v") && matches.opt_present("d") {
+		eprintln!("-v and -d are mutually exclusive");
+		process::exit(1);
+	}
 
-	run(volume_cap_linear);
-}
+	let volume_cap_linear = match (matches.opt_get::<f32>("v"), matches.opt_get::<f32>("d")) {
+		(Ok(Some(linear)), _) if linear.is_finite() && linear > 0.0 => linear,
+		(Ok(Some(_)) | Err(ParseFloatError { .. }), _) => {
+			eprintln!("volume cap must be a positive linear multiplier (ex: 0.25)");
+			process::exit(1);
+		}
+		(_, Ok(Some(db))) => 10f32.powf(db / 20.0),
+		(_, Err(ParseFloatError { .. })) => {
+			eprintln!("volume cap must be specified in dBFS (ex: -12.5)");
+			process::exit(1);
+		}
+		// calibrating measures the input before any volume cap is applied, and
+		// bypassing applies none
+		_ if calibrate_db.is_some() || matches.opt_present("bypass") => 1.0,
+		_ => {
+			eprintln!("volume cap must be specified (-v or -d)");
+			process::exit(1);
+		}
+	};
+
+	if calibrate_db.is_none() {
+		info!("volume cap: linear {volume_cap_linear:.4}");
+	}
+
+	let rate = match matches.opt_get_default::<u32>("r", 44100) {
+		Ok(x) => x,
+		Err(ParseIntError { .. }) => {
+			eprintln!("sample rate must be specified in Hz (ex: 48000)");
+			process::exit(1);
+		}
+	};
+
+	let channels = match matches.opt_get_default::<u8>("c", 2) {
+		Ok(x) => x,
+		Err(ParseIntError { .. }) => {
+			eprintln!("channel count must be a positive integer (ex: 1)");
+			process::exit(1);
+		}
+	};
+
+	let format = match matches.opt_get_default("f", SampleFormat::F32le) {
+		Ok(x) => x,
+		Err(e) => {
+			eprintln!("{e}");
+			process::exit(1);
+		}
+	};
+
+	let input = match matches.opt_str("input-file") {
+		Some(path) => match WavReader::open(Path::new(&path)) {
+			Ok(reader) => Some(reader),
+			Err(e) => {
+				eprintln!("failed to open input file '{path}': {e}");
+				process::exit(1);
+			}
+		},
+		None => None,
+	};
+
+	// the input file decides the format, explicit options must agree with it
+	let (rate, channels, format) = match &input {
+		Some(reader) => {
+			if matches.opt_present("r") && rate != reader.rate() {
+				eprintln!(
+					"sample rate {rate} Hz does not match the input file ({} Hz)",
+					reader.rate()
+				);
+				process::exit(1);
+			}
+
+			if matches.opt_present("c") && channels != reader.channels() {
+				eprintln!(
+					"channel count {channels} does not match the input file ({})",
+					reader.channels()
+				);
+				process::exit(1);
+			}
+
+			if matches.opt_present("f") && format != reader.format() {
+				eprintln!("sample format does not match the input file");
+				process::exit(1);
+			}
+
+			(reader.rate(), reader.channels(), reader.format())
+		}
+		None => (rate, channels, format),
+	};
 
-fn run(volume_cap: f32) {
 	let spec = Spec {
-		format: Format::F32le,
-		channels: 2,
-		rate: 44100,
+		format: format.pulse_format(),
+		channels,
+		rate,
 	};
-	assert!(spec.is_valid());
 
-	let mut proplist = Proplist::new().unwrap();
-	proplist
-		.set_str(proplist::properties::APPLICATION_NAME, "Shoosh")
-		.unwrap();
+	if !spec.rate_is_valid() {
+		eprintln!("sample rate {rate} Hz is not supported by pulseaudio");
+		process::exit(1);
+	}
 
-	let mut mainloop = Mainloop::new().expect("Failed to create mainloop");
+	if !spec.channels_are_valid() {
+		eprintln!("channel count {channels} is not supported by pulseaudio");
+		process::exit(1);
+	}
 
-	let mut context = Context::new_with_proplist(&mainloop, "Shoosh", &proplist)
-		.expect("Failed to create context");
+	let knee_db = match matches.opt_get_default::<f32>("knee", 0.0) {
+		Ok(x) if x >= 0.0 => x,
+		_ => {
+			eprintln!("knee must be a non-negative number of decibels (ex: 6)");
+			process::exit(1);
+		}
+	};
 
-	context
-		.connect(None, context::FlagSet::NOFLAGS, None)
-		.expect("Failed to connect to pulseaudio");
+	let attack_ms = match matches.opt_get_default::<f32>("attack", 0.0) {
+		Ok(x) if x >= 0.0 => x,
+		_ => {
+			eprintln!("attack must be a non-negative number of milliseconds (ex: 5)");
+			process::exit(1);
+		}
+	};
 
-	let poll_mainloop = |mainloop: &mut Mainloop| match mainloop.iterate(true) {
-		IterateResult::Err(_) | IterateResult::Quit(_) => {
-			eprintln!("Iterate unsuccessful, exiting...");
-			return
+	let release_ms = match matches.opt_get_default::<f32>("release", 0.0) {
+		Ok(x) if x >= 0.0 => x,
+		_ => {
+			eprintln!("release must be a non-negative number of milliseconds (ex: 200)");
+			process::exit(1);
 		}
-		IterateResult::Success(_) => {}
 	};
 
-	// wait for context
-	loop {
-		poll_mainloop(&mut mainloop);
+	let gate_threshold = match matches.opt_get::<f32>("gate") {
+		Ok(x) => x.map(|db| VolumeLinear::from(VolumeDB(db as f64)).0 as f32),
+		Err(ParseFloatError { .. }) => {
+			eprintln!("gate threshold must be specified in decibels (ex: -50)");
+			process::exit(1);
+		}
+	};
+
+	let gate_attack_ms = match matches.opt_get_default::<f32>("gate-attack", 1.0) {
+		Ok(x) if x >= 0.0 => x,
+		_ => {
+			eprintln!("gate attack must be a non-negative number of milliseconds (ex: 1)");
+			process::exit(1);
+		}
+	};
+
+	let gate_release_ms = match matches.opt_get_default::<f32>("gate-release", 100.0) {
+		Ok(x) if x >= 0.0 => x,
+		_ => {
+			eprintln!("gate release must be a non-negative number of milliseconds (ex: 100)");
+			process::exit(1);
+		}
+	};
+
+	let lookahead_ms = match matches.opt_get_default::<f32>("lookahead", 0.0) {
+		Ok(x) if x >= 0.0 => x,
+		_ => {
+			eprintln!("lookahead must be a non-negative number of milliseconds (ex: 5)");
+			process::exit(1);
+		}
+	};
+
+	let detector = match matches.opt_get_default("detector", Detector::Peak) {
+		Ok(x) => x,
+		Err(e) => {
+			eprintln!("{e}");
+			process::exit(1);
+		}
+	};
 
-		match context.get_state() {
-			context::State::Ready => break,
-			context::State::Failed | context::State::Terminated => {
-				eprintln!("Context state is failed or terminated, exiting...");
-				return
+	let true_peak = matches.opt_present("true-peak");
+	if true_peak && detector != Detector::Peak {
+		eprintln!("--true-peak replaces the peak detector and can't be combined with --detector");
+		process::exit(1);
+	}
+
+	let decay = match matches.opt_get_default("decay", Weighting::DEFAULT_DECAY) {
+		Ok(x) if x > 0.0 && x <= 1.0 => x,
+		_ => {
+			eprintln!("decay must be above 0 and at most 1 (ex: 0.9)");
+			process::exit(1);
+		}
+	};
+
+	let weighting = match matches.opt_get_default("weighting", Weighting::Linear) {
+		Ok(Weighting::Exponential { .. }) => Weighting::Exponential { decay },
+		Ok(x) => x,
+		Err(e) => {
+			eprintln!("{e}");
+			process::exit(1);
+		}
+	};
+	if matches.opt_present("decay") && !matches!(weighting, Weighting::Exponential { .. }) {
+		eprintln!("--decay only applies to --weighting exponential");
+		process::exit(1);
+	}
+
+	let window = match matches.opt_get_default::<usize>("window", 128) {
+		Ok(x) if x >= 1 => x,
+		_ => {
+			eprintln!("window must be a positive number of chunks (ex: 64)");
+			process::exit(1);
+		}
+	};
+
+	let chunk_size = match matches.opt_get::<usize>("chunk") {
+		Ok(None) => 64,
+		Ok(Some(x)) if x >= 1 && x % channels as usize == 0 => x,
+		_ => {
+			eprintln!("chunk size must be a positive multiple of the channel count ({channels})");
+			process::exit(1);
+		}
+	};
+
+	let on_hole = match matches.opt_get_default("on-hole", HoleMode::Silence) {
+		Ok(x) => x,
+		Err(e) => {
+			eprintln!("{e}");
+			process::exit(1);
+		}
+	};
+
+	let suspend_after = match matches.opt_get::<f32>("suspend-on-silence") {
+		Ok(None) => None,
+		Ok(Some(x)) if x > 0.0 => Some(Duration::from_secs_f32(x)),
+		_ => {
+			eprintln!("suspend time must be a positive number of seconds (ex: 5)");
+			process::exit(1);
+		}
+	};
+
+	let silence_threshold = match matches.opt_get_default::<f32>("silence-threshold", -70.0) {
+		Ok(db) => VolumeLinear::from(VolumeDB(db as f64)).0 as f32,
+		Err(ParseFloatError { .. }) => {
+			eprintln!("silence threshold must be specified in decibels (ex: -60)");
+			process::exit(1);
+		}
+	};
+
+	let sidechain = matches.opt_str("sidechain");
+	if sidechain.is_some()
+		&& input.is_some()
+		&& (matches.opt_present("record") || matches.opt_present("raw-out"))
+	{
+		eprintln!("--sidechain records from the server and can't be used when processing offline");
+		process::exit(1);
+	}
+
+	let offline = matches.opt_present("record") || matches.opt_present("raw-out");
+	if backend == Backend::Pipewire && input.is_some() && !offline {
+		eprintln!("the pipewire backend can't play back --input-file, only process it offline");
+		process::exit(1);
+	}
+
+	let duck_depth = match matches.opt_get_default::<f32>("duck", 12.0) {
+		Ok(db) if db >= 0.0 => VolumeLinear::from(VolumeDB(-db as f64)).0 as f32,
+		_ => {
+			eprintln!("duck must be a non-negative number of decibels (ex: 12)");
+			process::exit(1);
+		}
+	};
+
+	let duck_threshold = match matches.opt_get_default::<f32>("duck-threshold", -40.0) {
+		Ok(db) => VolumeLinear::from(VolumeDB(db as f64)).0 as f32,
+		Err(ParseFloatError { .. }) => {
+			eprintln!("duck threshold must be specified in decibels (ex: -40)");
+			process::exit(1);
+		}
+	};
+
+	let gain_floor = match matches.opt_get::<f32>("gain-floor") {
+		Ok(None) => 0.0,
+		Ok(Some(db)) if db <= 0.0 => VolumeLinear::from(VolumeDB(db as f64)).0 as f32,
+		_ => {
+			eprintln!("gain floor must be a non-positive number of decibels (ex: -30)");
+			process::exit(1);
+		}
+	};
+
+	let lufs = match matches.opt_get::<f32>("lufs") {
+		Ok(x) if x.is_none_or(|x| (-70.0..=0.0).contains(&x)) => x,
+		_ => {
+			eprintln!("loudness target must be between -70 and 0 LUFS (ex: -16)");
+			process::exit(1);
+		}
+	};
+
+	let makeup_db = match matches.opt_get::<f32>("makeup") {
+		Ok(x) if x.is_none_or(|x| x >= 0.0) => x,
+		_ => {
+			eprintln!("makeup gain must be a non-negative number of decibels (ex: 6)");
+			process::exit(1);
+		}
+	};
+
+	let ceiling = match matches.opt_get::<f32>("headroom") {
+		Ok(None) => None,
+		Ok(Some(db)) if db >= 0.0 => Some(VolumeLinear::from(VolumeDB(-db as f64)).0 as f32),
+		_ => {
+			eprintln!("headroom must be a non-negative number of decibels (ex: 1)");
+			process::exit(1);
+		}
+	};
+
+	let clip = match (matches.opt_present("clip"), matches.opt_str("clip").as_deref()) {
+		// makeup gain can push the output past full scale
+		(false, _) if makeup_db.is_some() => Some(1.0),
+		(false, _) => None,
+		(true, None) => Some(1.0),
+		(true, Some("cap")) => Some(volume_cap_linear),
+		(true, Some(limit)) => {
+			eprintln!("unknown clip limit '{limit}', expected --clip or --clip=cap");
+			process::exit(1);
+		}
+	};
+
+	// the ceiling also holds after makeup gain, and through the ramp into each
+	// chunk's gain
+	let clip = match (clip, ceiling) {
+		(Some(clip), Some(ceiling)) => Some(clip.min(ceiling)),
+		(clip, ceiling) => clip.or(ceiling),
+	};
+
+	let stats = match matches.opt_get::<f32>("stats") {
+		Ok(None) => None,
+		Ok(Some(x)) if x > 0.0 => Some(Duration::from_secs_f32(x)),
+		_ => {
+			eprintln!("stats interval must be a positive number of seconds (ex: 5)");
+			process::exit(1);
+		}
+	};
+
+	let duration = match matches.opt_get_default::<f32>("duration", 0.0) {
+		Ok(0.0) => None,
+		Ok(x) if x > 0.0 => Some(Duration::from_secs_f32(x)),
+		_ => {
+			eprintln!("duration must be a non-negative number of seconds (ex: 10)");
+			process::exit(1);
+		}
+	};
+
+	let latency_ms = match matches.opt_get::<f32>("latency") {
+		Ok(None) => None,
+		Ok(Some(x)) if x > 0.0 => Some(x),
+		_ => {
+			eprintln!("latency must be a positive number of milliseconds (ex: 20)");
+			process::exit(1);
+		}
+	};
+
+	let idle_sleep = match matches.opt_get_default::<u64>("idle-sleep", 100_000) {
+		Ok(x) if x > 0 => Duration::from_micros(x),
+		_ => {
+			eprintln!("idle sleep must be a positive number of microseconds (ex: 20000)");
+			process::exit(1);
+		}
+	};
+
+	let dither = matches.opt_present("dither");
+	if dither && format != SampleFormat::S16le {
+		eprintln!("--dither only applies to the s16le format");
+		process::exit(1);
+	}
+
+	#[cfg(feature = "dbus")]
+	let dbus = matches.opt_present("dbus");
+	#[cfg(not(feature = "dbus"))]
+	let dbus = false;
+
+	#[cfg(feature = "metrics")]
+	let metrics_addr = matches.opt_str("metrics-addr");
+	#[cfg(not(feature = "metrics"))]
+	let metrics_addr = None;
+
+	let balance_db = match matches.opt_str("balance") {
+		Some(gains) => match gains
+			.split(':')
+			.map(str::parse)
+			.collect::<Result<Vec<f32>, _>>()
+		{
+			Ok(gains) if gains.len() == channels as usize => Some(gains),
+			_ => {
+				eprintln!(
+					"balance must be one gain in decibels per channel, separated by colons (ex: \
+					 0:-3)"
+				);
+				process::exit(1);
+			}
+		},
+		None => None,
+	};
+
+	let channel_map = match matches.opt_str("channel-map") {
+		Some(map) => match ChannelMap::parse(&map, channels as usize) {
+			Ok(map) => Some(map),
+			Err(e) => {
+				eprintln!("invalid channel map: {e}");
+				process::exit(1);
+			}
+		},
+		None => None,
+	};
+
+	let mono = matches.opt_present("mono");
+
+	let bypass = matches.opt_present("bypass");
+	if bypass && mono {
+		eprintln!("--bypass can't change the channel count, so can't be combined with --mono");
+		process::exit(1);
+	}
+
+	let mid_side = matches.opt_present("ms");
+	if mid_side && (mono || channels != 2) {
+		eprintln!("mid/side processing needs exactly 2 channels, without --mono");
+		process::exit(1);
+	}
+
+	let multiband = match matches.opt_str("multiband") {
+		Some(frequencies) => match frequencies
+			.split(',')
+			.map(str::parse)
+			.collect::<Result<Vec<f32>, _>>()
+		{
+			Ok(frequencies)
+				if (1..=2).contains(&frequencies.len())
+					&& frequencies.windows(2).all(|pair| pair[0] < pair[1])
+					&& frequencies
+						.iter()
+						.all(|&hz| hz > 0.0 && hz < rate as f32 / 2.0) =>
+				Some(frequencies),
+			_ => {
+				eprintln!(
+					"multiband needs 1 or 2 ascending crossover frequencies in Hz below half the \
+					 sample rate, separated by commas (ex: 200,2000)"
+				);
+				process::exit(1);
 			}
-			_ => {}
+		},
+		None => None,
+	};
+	if multiband.is_some() && (matches.opt_present("unlink") || mid_side) {
+		eprintln!("--multiband can't be combined with --unlink or --ms");
+		process::exit(1);
+	}
+
+	let config = Config {
+		format,
+		spec,
+		bypass,
+		channel_map,
+		balance_db,
+		mono,
+		unlink: matches.opt_present("unlink"),
+		mid_side,
+		multiband,
+		backend,
+		server: matches.opt_str("server"),
+		source: matches.opt_str("source"),
+		sink: matches.opt_str("sink"),
+		sidechain,
+		force: matches.opt_present("force"),
+		reconnect: !matches.opt_present("no-reconnect"),
+		latency_ms,
+		idle_sleep,
+		on_hole,
+		suspend_after,
+		silence_threshold,
+		dc_block: matches.opt_present("dc-block"),
+		lufs,
+		makeup_db,
+		softclip: matches.opt_present("softclip"),
+		clip,
+		dither,
+		meter: matches.opt_present("meter"),
+		stats,
+		print_latency: matches.opt_present("print-latency"),
+		record: matches.opt_str("record").map(PathBuf::from),
+		raw_out: matches.opt_str("raw-out").map(PathBuf::from),
+		duration,
+		input_file: matches.opt_str("input-file").map(PathBuf::from),
+		control: matches.opt_str("control").map(PathBuf::from),
+		dbus,
+		metrics_addr,
+		duck: DuckSettings {
+			threshold: duck_threshold,
+			depth: duck_depth,
+			detector,
+			window,
+			chunk_size,
+			rate,
+			channels: if mono { 1 } else { channels },
+			..Default::default()
+		},
+		limiter: VolumeCapSettings {
+			volume_cap: volume_cap_linear,
+			ceiling,
+			knee_db,
+			allow_boost: matches.opt_present("allow-boost"),
+			gain_floor,
+			detector,
+			true_peak,
+			weighting,
+			prefill: matches.opt_present("prefill-cap"),
+			attack_ms,
+			release_ms,
+			gate_threshold,
+			gate_attack_ms,
+			gate_release_ms,
+			lookahead_ms,
+			window,
+			chunk_size,
+			rate,
+			channels: if mono { 1 } else { channels },
+		},
+	};
+
+	if matches.opt_present("dump-config") {
+		print!("{}", config.dump());
+		return
+	}
+
+	let (playback_buffer, recording_buffer) = config.buffer_sizes();
+	info!("buffer sizes: playback {playback_buffer} bytes, recording {recording_buffer} bytes");
+
+	shutdown::install_handler();
+
+	if let Some(target_db) = calibrate_db {
+		if let Err(e) = calibrate(&config, input, target_db) {
+			error!("{e}");
+			process::exit(1);
 		}
+
+		return
+	}
+
+	if let Err(e) = run(&config, input) {
+		error!("{e}");
+		process::exit(1);
+	}
+}
+
+/// Returns the command line options, which are also the config file keys.
+fn options() -> Options {
+	let mut opts = Options::new();
+	opts.optflag("h", "help", "print this help");
+	opts.optflag("V", "version", "print the version");
+	opts.optopt(
+		"",
+		"config",
+		"TOML file of long option names to values, overridden by the command line (default \
+		 $XDG_CONFIG_HOME/shoosh/config.toml)",
+		"PATH",
+	);
+	opts.optflag("q", "quiet", "only print errors");
+	opts.optflagmulti("", "verbose", "print debug messages, or trace messages if repeated");
+	opts.optopt(
+		"v",
+		"volume",
+		"maximum allowable volume as a linear multiplier, between 0 and 1 (ex: 0.25)",
+		"VOLUME",
+	);
+	opts.optopt(
+		"d",
+		"volume-db",
+		"maximum allowable volume in dBFS, instead of -v (ex: -12)",
+		"DB",
+	);
+	opts.optopt(
+		"",
+		"calibrate",
+		"measure the input and print the volume cap that brings its average RMS level to DB, then \
+		 exit",
+		"DB",
+	);
+	opts.optopt("r", "rate", "sample rate in Hz (default 44100)", "RATE");
+	opts.optopt("c", "channels", "number of channels (default 2)", "CHANNELS");
+	opts.optopt(
+		"f",
+		"format",
+		&format!("sample format, {} (default f32le)", one_of(&SampleFormat::NAMES)),
+		"FORMAT",
+	);
+	opts.optopt(
+		"",
+		"balance",
+		"gain of each input channel in decibels, applied before limiting to fix a lopsided source \
+		 (ex: 0:-3 lowers the right channel)",
+		"L:R",
+	);
+	opts.optopt(
+		"",
+		"channel-map",
+		"route input channels to output channels as comma separated IN:OUT pairs of channel \
+		 indices, before any other processing, leaving unrouted outputs silent (ex: 0:1,1:0 swaps \
+		 stereo)",
+		"MAP",
+	);
+	opts.optflag("", "mono", "average the input channels into a single output channel");
+	opts.optflag(
+		"",
+		"unlink",
+		"limit each channel separately instead of reducing all channels together",
+	);
+	opts.optflag("", "ms", "limit the mid and side of stereo input separately");
+	opts.optopt(
+		"",
+		"multiband",
+		"limit 2 or 3 frequency bands separately, split at the comma separated crossover \
+		 frequencies in Hz (ex: 200,2000)",
+		"HZ",
+	);
+	opts.optopt(
+		"",
+		"backend",
+		&format!(
+			"server API to run against, {} (default pulse, pipewire needs the pipewire feature)",
+			one_of(&Backend::NAMES)
+		),
+		"BACKEND",
+	);
+	opts.optopt(
+		"",
+		"server",
+		"address of the server to connect to, such as tcp:host (default the local server)",
+		"ADDR",
+	);
+	opts.optopt("", "source", "name of the source to record from", "NAME");
+	opts.optopt("", "sink", "name of the sink to play back to", "NAME");
+	opts.optopt(
+		"",
+		"sidechain",
+		"also record from SOURCE and lower the output while it is active, on top of the volume cap",
+		"SOURCE",
+	);
+	opts.optopt(
+		"",
+		"duck",
+		"how far --sidechain lowers the output in decibels (default 12)",
+		"DB",
+	);
+	opts.optopt(
+		"",
+		"duck-threshold",
+		"sidechain level at which --sidechain starts lowering the output (default -40)",
+		"DB",
+	);
+	opts.optflag("", "list-devices", "list available sources and sinks");
+	opts.optflag(
+		"",
+		"dump-config",
+		"print the settings in effect after merging the command line, config file and defaults, \
+		 then exit",
+	);
+	opts.optflag("", "force", "run even if the source is the monitor of the sink");
+	opts.optflag("", "meter", "draw a live level meter on stderr");
+	opts.optopt("", "stats", "print processing statistics every INTERVAL seconds", "INTERVAL");
+	opts.optflag("", "print-latency", "print the latency from recording to playback every second");
+	opts.optopt("", "record", "also write the processed audio to a WAV file", "FILE");
+	opts.optopt(
+		"",
+		"raw-out",
+		"also write the processed audio to FILE as raw samples with no header",
+		"FILE",
+	);
+	opts.optopt(
+		"",
+		"duration",
+		"stop after SECONDS, or after that much of the input when processing offline (default 0, \
+		 run until interrupted)",
+		"SECONDS",
+	);
+	opts.optopt(
+		"",
+		"input-file",
+		"read from a WAV file instead of the source; with --record or --raw-out, process it \
+		 offline without playing it",
+		"FILE",
+	);
+	opts.optopt(
+		"",
+		"control",
+		"listen for commands on a Unix socket, see the control module for the protocol",
+		"SOCKET",
+	);
+	#[cfg(feature = "dbus")]
+	opts.optflag(
+		"",
+		"dbus",
+		"serve the volume cap and levels on the session bus as dev.outfoxxed.Shoosh",
+	);
+	#[cfg(feature = "metrics")]
+	opts.optopt(
+		"",
+		"metrics-addr",
+		"serve Prometheus metrics over HTTP at /metrics, see the metrics module for the names",
+		"HOST:PORT",
+	);
+	opts.optopt(
+		"",
+		"latency",
+		"target buffering latency of the streams, lower for monitoring or higher on loaded systems",
+		"MS",
+	);
+	opts.optopt(
+		"",
+		"idle-sleep",
+		"time the main thread sleeps between checks for shutdown and stream failures; audio is \
+		 processed as it arrives either way, and --stats counts the wakeups (default 100000)",
+		"US",
+	);
+	opts.optflag(
+		"",
+		"no-reconnect",
+		"exit instead of reconnecting when the server goes away, and let the server move the \
+		 streams to the default device instead of waiting for a named device to come back",
+	);
+	opts.optopt(
+		"",
+		"knee",
+		"width of a soft knee around the cap in decibels (default 0, a hard knee)",
+		"DB",
+	);
+	opts.optopt("", "attack", "time for gain reduction to take effect (default 0)", "MS");
+	opts.optopt("", "release", "time for gain reduction to recover (default 0)", "MS");
+	opts.optopt("", "gate", "silence the output while the input is below DB", "DB");
+	opts.optopt("", "gate-attack", "time for the gate to open (default 1)", "MS");
+	opts.optopt("", "gate-release", "time for the gate to close (default 100)", "MS");
+	opts.optopt(
+		"",
+		"lookahead",
+		"delay the audio so gain reduction starts before a transient, adding MS of latency \
+		 (default 0)",
+		"MS",
+	);
+	opts.optopt(
+		"",
+		"detector",
+		&format!("level detector, {} (default peak)", one_of(&Detector::NAMES)),
+		"DETECTOR",
+	);
+	opts.optflagopt(
+		"",
+		"clip",
+		"hard-limit the output to full scale, or to the volume cap with --clip=cap",
+		"cap",
+	);
+	opts.optflag(
+		"",
+		"softclip",
+		"round off output peaks above half of full scale instead of letting them clip, \
+		 oversampled to keep aliasing down, adding 7 frames of latency",
+	);
+	opts.optflag(
+		"",
+		"allow-boost",
+		"don't clamp the volume cap's gain to unity, it only ever reduces the volume on its own",
+	);
+	opts.optopt(
+		"",
+		"headroom",
+		"keep the output DB below full scale: peaks are limited to that ceiling whatever the \
+		 volume cap, which still limits the average, and the output is hard-limited to it after \
+		 any makeup gain",
+		"DB",
+	);
+	opts.optopt(
+		"",
+		"lufs",
+		"slowly adjust the gain so the integrated loudness of the input meets TARGET LUFS, ahead \
+		 of the volume cap (ex: -16)",
+		"TARGET",
+	);
+	opts.optopt(
+		"",
+		"gain-floor",
+		"never reduce the volume by more than DB, letting extreme input through above the cap \
+		 (ex: -30)",
+		"DB",
+	);
+	opts.optopt(
+		"",
+		"makeup",
+		"raise the output by DB after limiting, hard-limited to full scale unless --clip=cap",
+		"DB",
+	);
+	opts.optopt(
+		"",
+		"suspend-on-silence",
+		"skip processing and play silence once the input has been below the silence threshold for \
+		 SECONDS",
+		"SECONDS",
+	);
+	opts.optopt(
+		"",
+		"silence-threshold",
+		"level below which --suspend-on-silence counts the input as silent (default -70)",
+		"DB",
+	);
+	opts.optflag("", "dc-block", "remove DC offset from the input before measuring it");
+	opts.optflag("", "dither", "dither the output before quantizing it, s16le only");
+	opts.optflag(
+		"",
+		"true-peak",
+		"detect peaks between samples by oversampling, replaces the peak detector",
+	);
+	opts.optopt(
+		"",
+		"weighting",
+		&format!(
+			"averaging weight over the window, {}; ema is a moving average with a time constant \
+			 of the window (default linear)",
+			one_of(&Weighting::NAMES)
+		),
+		"WEIGHTING",
+	);
+	opts.optflag(
+		"",
+		"prefill-cap",
+		"start the average at the volume cap, so no reduction is applied until loud input arrives",
+	);
+	opts.optopt(
+		"",
+		"decay",
+		"per-chunk decay of the exponential weighting, above 0 and at most 1 (default 0.95)",
+		"FACTOR",
+	);
+	opts.optopt("", "window", "number of chunks in the averaging window (default 128)", "CHUNKS");
+	opts.optopt(
+		"",
+		"chunk",
+		"interleaved samples per gain update, a multiple of the channel count (default 64)",
+		"SAMPLES",
+	);
+	opts.optopt(
+		"",
+		"on-hole",
+		&format!(
+			"what to play for gaps in the recording, {} (default silence)",
+			one_of(&HoleMode::NAMES)
+		),
+		"MODE",
+	);
+	opts.optflag(
+		"",
+		"bypass",
+		"pass the input through to the output untouched, ignoring every processing option, to \
+		 compare against or to check the routing",
+	);
+	opts
+}
+
+/// Lists option values for help text, as `a, b or c`.
+fn one_of(names: &[&str]) -> String {
+	match names {
+		[] => String::new(),
+		[name] => name.to_string(),
+		[rest @ .., last] => format!("{} or {last}", rest.join(", ")),
 	}
+}
 
-	let mut playback_stream = Stream::new(&mut context, "Shoosh sink", &spec, None)
-		.expect("Failed to create playback stream");
+/// Adds the options set in the config file at `path` that were not given on
+/// the command line, returning the combined matches.
+fn merge_config_file(
+	opts: &Options,
+	args: &[String],
+	matches: Matches,
+	path: &Path,
+) -> Result<Matches, String> {
+	let file_args = config_args(config_file::load(path)?);
 
-	let mut recording_stream = Stream::new(&mut context, "Shoosh source", &spec, None)
-		.expect("Failed to create recording stream");
+	// check the file on its own first, as looking up an unknown option panics
+	opts.parse(file_args.iter().map(|(_, arg)| arg))
+		.map_err(|e| e.to_string())?;
 
-	playback_stream
-		.connect_playback(
-			None,
-			Some(&BufferAttr {
-				maxlength: u32::MAX,
-				tlength: 1024,
-				prebuf: u32::MAX,
-				minreq: u32::MAX,
-				fragsize: 0,
-			}),
-			stream::FlagSet::empty(),
-			None,
-			None,
-		)
-		.expect("Failed to connect playback stream");
-
-	recording_stream
-		.connect_record(
-			None,
-			Some(&BufferAttr {
-				maxlength: u32::MAX,
-				tlength: 0,
-				prebuf: 0,
-				minreq: 0,
-				fragsize: 1024 * mem::size_of::<f32>() as u32,
-			}),
-			stream::FlagSet::empty(),
+	let args = args
+		.iter()
+		.cloned()
+		.chain(
+			file_args
+				.into_iter()
+				.filter(|(key, _)| match key.as_str() {
+					// either sets the volume cap, so overrides both
+					"volume" | "volume-db" =>
+						!matches.opt_present("volume") && !matches.opt_present("volume-db"),
+					key => !matches.opt_present(key),
+				})
+				.map(|(_, arg)| arg),
 		)
-		.expect("Failed to connect recording stream");
-
-	// wait for streams
-	'wait_streams: loop {
-		poll_mainloop(&mut mainloop);
-
-		for stream in [&playback_stream, &recording_stream] {
-			match stream.get_state() {
-				stream::State::Ready => {}
-				stream::State::Failed | stream::State::Terminated => {
-					eprintln!("Stream state is failed or terminated, exiting...");
-					return
+		.collect::<Vec<_>>();
+
+	opts.parse(args).map_err(|e| e.to_string())
+}
+
+/// Turns config file entries into their option names and command line
+/// arguments.
+fn config_args(entries: Vec<(String, Option<String>)>) -> Vec<(String, String)> {
+	entries
+		.into_iter()
+		.map(|(key, value)| match value {
+			Some(value) => (key.clone(), format!("--{key}={value}")),
+			None => (key.clone(), format!("--{key}")),
+		})
+		.collect()
+}
+
+/// Settings resolved from the command line and config file.
+struct Config {
+	format: SampleFormat,
+	/// Spec of the recording stream or input file
+	spec: Spec,
+	/// Pass the input through without processing it
+	bypass: bool,
+	/// Routing of input channels to output channels
+	channel_map: Option<ChannelMap>,
+	/// Gain of each input channel in decibels
+	balance_db: Option<Vec<f32>>,
+	/// Downmix to a single channel before processing
+	mono: bool,
+	/// Limit each channel separately
+	unlink: bool,
+	/// Limit the mid and side of stereo input separately
+	mid_side: bool,
+	/// Crossover frequencies of the bands to limit separately
+	multiband: Option<Vec<f32>>,
+	backend: Backend,
+	/// Server to connect to, or the default server if `None`
+	server: Option<String>,
+	/// Source to record from, or the default source if `None`
+	source: Option<String>,
+	/// Sink to play back to, or the default sink if `None`
+	sink: Option<String>,
+	/// Source whose level ducks the output, if any
+	sidechain: Option<String>,
+	/// Run even if the source monitors the sink
+	force: bool,
+	/// Reconnect when the connection to the server is lost
+	reconnect: bool,
+	/// Target stream buffering latency, or `None` for the built in buffer
+	/// sizes
+	latency_ms: Option<f32>,
+	/// Time the main thread sleeps between checks for shutdown and stream
+	/// failures, which the audio doesn't wait on
+	idle_sleep: Duration,
+	on_hole: HoleMode,
+	/// Suspend processing once the input has been silent this long
+	suspend_after: Option<Duration>,
+	/// Linear level below which the input counts as silent
+	silence_threshold: f32,
+	/// Remove DC offset before the volume cap
+	dc_block: bool,
+	/// Integrated loudness to normalize the input toward, in LUFS
+	lufs: Option<f32>,
+	/// Gain after limiting, in decibels
+	makeup_db: Option<f32>,
+	/// Round off peaks before the clip
+	softclip: bool,
+	/// Hard-limit the output to this level
+	clip: Option<f32>,
+	/// Dither the output before quantizing it to 16 bits
+	dither: bool,
+	/// Draw a live level meter
+	meter: bool,
+	/// How often to print processing statistics, if at all
+	stats: Option<Duration>,
+	/// Print the recording to playback latency every second
+	print_latency: bool,
+	/// WAV file to write the processed audio to
+	record: Option<PathBuf>,
+	/// File to write the processed audio to without a header
+	raw_out: Option<PathBuf>,
+	/// Stop after this long
+	duration: Option<Duration>,
+	/// WAV file to read instead of recording from the source
+	input_file: Option<PathBuf>,
+	/// Unix socket to accept control commands on
+	control: Option<PathBuf>,
+	/// Serve the control values on the session bus
+	dbus: bool,
+	/// Address to serve Prometheus metrics on
+	metrics_addr: Option<String>,
+	/// Ducking applied while the sidechain is active
+	duck: DuckSettings,
+	limiter: VolumeCapSettings,
+}
+
+/// Two channels of f32 at 44.1kHz with every optional stage off, for tests
+/// that build a pipeline.
+#[cfg(test)]
+impl Default for Config {
+	fn default() -> Self {
+		Self {
+			format: SampleFormat::F32le,
+			spec: Spec {
+				format: SampleFormat::F32le.pulse_format(),
+				channels: 2,
+				rate: 44100,
+			},
+			bypass: false,
+			channel_map: None,
+			balance_db: None,
+			mono: false,
+			unlink: false,
+			mid_side: false,
+			multiband: None,
+			backend: Backend::Pulse,
+			server: None,
+			source: None,
+			sink: None,
+			sidechain: None,
+			force: false,
+			reconnect: true,
+			latency_ms: None,
+			idle_sleep: Duration::from_millis(100),
+			on_hole: HoleMode::Silence,
+			suspend_after: None,
+			silence_threshold: 0.0,
+			dc_block: false,
+			lufs: None,
+			makeup_db: None,
+			softclip: false,
+			clip: None,
+			dither: false,
+			meter: false,
+			stats: None,
+			print_latency: false,
+			record: None,
+			raw_out: None,
+			duration: None,
+			input_file: None,
+			control: None,
+			dbus: false,
+			metrics_addr: None,
+			duck: DuckSettings::default(),
+			limiter: VolumeCapSettings::default(),
+		}
+	}
+}
+
+impl Config {
+	/// Returns the number of channels after downmixing, if any.
+	fn output_channels(&self) -> u8 {
+		if self.mono {
+			1
+		} else {
+			self.spec.channels
+		}
+	}
+
+	/// Formats every setting as a config file, under the long option names
+	/// and in the units they take, with settings that aren't set left as
+	/// comments.
+	fn dump(&self) -> String {
+		let mut output = String::new();
+		// rounded off, as the conversion from decibels and back isn't exact
+		let db = |linear: f32| {
+			let db = VolumeDB::from(VolumeLinear(linear as f64)).0 as f32;
+			(db * 100.0).round() / 100.0
+		};
+		let mut line = |key: &str, value: Option<String>| {
+			let _ = match value {
+				Some(value) => writeln!(output, "{key} = {value}"),
+				None => writeln!(output, "# {key} is not set"),
+			};
+		};
+		let string = |value: &str| format!("{value:?}");
+		let list = |values: &[f32], separator: &str| {
+			let values = values.iter().map(f32::to_string).collect::<Vec<_>>();
+			string(&values.join(separator))
+		};
+		let path = |value: &PathBuf| string(&value.display().to_string());
+		let seconds = |value: Duration| value.as_secs_f32().to_string();
+
+		let limiter = &self.limiter;
+		line("volume-db", Some(db(limiter.volume_cap).to_string()));
+		line("format", Some(string(self.format.name())));
+		line("rate", Some(self.spec.rate.to_string()));
+		line("channels", Some(self.spec.channels.to_string()));
+		line("bypass", Some(self.bypass.to_string()));
+		line(
+			"channel-map",
+			self.channel_map
+				.as_ref()
+				.map(|map| string(&map.to_string())),
+		);
+		line("balance", self.balance_db.as_deref().map(|gains| list(gains, ":")));
+		line("mono", Some(self.mono.to_string()));
+		line("unlink", Some(self.unlink.to_string()));
+		line("ms", Some(self.mid_side.to_string()));
+		line(
+			"multiband",
+			self.multiband
+				.as_deref()
+				.map(|frequencies| list(frequencies, ",")),
+		);
+		line("backend", Some(string(self.backend.name())));
+		line("server", self.server.as_deref().map(string));
+		line("source", self.source.as_deref().map(string));
+		line("sink", self.sink.as_deref().map(string));
+		line("sidechain", self.sidechain.as_deref().map(string));
+		line("duck", Some((-db(self.duck.depth)).to_string()));
+		line("duck-threshold", Some(db(self.duck.threshold).to_string()));
+		line("force", Some(self.force.to_string()));
+		line("no-reconnect", Some((!self.reconnect).to_string()));
+		line("latency", self.latency_ms.map(|ms| ms.to_string()));
+		line("idle-sleep", Some(self.idle_sleep.as_micros().to_string()));
+		line("on-hole", Some(string(self.on_hole.name())));
+		line("suspend-on-silence", self.suspend_after.map(seconds));
+		line("silence-threshold", Some(db(self.silence_threshold).to_string()));
+		line("dc-block", Some(self.dc_block.to_string()));
+		line("lufs", self.lufs.map(|lufs| lufs.to_string()));
+		line("headroom", limiter.ceiling.map(|ceiling| (-db(ceiling)).to_string()));
+		line("detector", Some(string(limiter.detector.name())));
+		line("true-peak", Some(limiter.true_peak.to_string()));
+		line("weighting", Some(string(limiter.weighting.name())));
+		if let Weighting::Exponential { decay } = limiter.weighting {
+			line("decay", Some(decay.to_string()));
+		}
+		line("prefill-cap", Some(limiter.prefill.to_string()));
+		line("window", Some(limiter.window.to_string()));
+		line("chunk", Some(limiter.chunk_size.to_string()));
+		line("knee", Some(limiter.knee_db.to_string()));
+		line("allow-boost", Some(limiter.allow_boost.to_string()));
+		line(
+			"gain-floor",
+			(limiter.gain_floor > 0.0).then(|| db(limiter.gain_floor).to_string()),
+		);
+		line("attack", Some(limiter.attack_ms.to_string()));
+		line("release", Some(limiter.release_ms.to_string()));
+		line("gate", limiter.gate_threshold.map(|gate| db(gate).to_string()));
+		line("gate-attack", Some(limiter.gate_attack_ms.to_string()));
+		line("gate-release", Some(limiter.gate_release_ms.to_string()));
+		line("lookahead", Some(limiter.lookahead_ms.to_string()));
+		line("makeup", self.makeup_db.map(|makeup| makeup.to_string()));
+		line("softclip", Some(self.softclip.to_string()));
+		line("clip", self.clip_option().map(str::to_string));
+		line("dither", Some(self.dither.to_string()));
+		line("meter", Some(self.meter.to_string()));
+		line("stats", self.stats.map(seconds));
+		line("print-latency", Some(self.print_latency.to_string()));
+		line("record", self.record.as_ref().map(path));
+		line("raw-out", self.raw_out.as_ref().map(path));
+		line("duration", self.duration.map(seconds));
+		line("input-file", self.input_file.as_ref().map(path));
+		line("control", self.control.as_ref().map(path));
+		#[cfg(feature = "dbus")]
+		line("dbus", Some(self.dbus.to_string()));
+		#[cfg(feature = "metrics")]
+		line("metrics-addr", self.metrics_addr.as_deref().map(string));
+
+		output
+	}
+
+	/// Returns the `--clip` value that gives the output limit, as TOML, or
+	/// `None` if there is none.
+	///
+	/// The limit is also lowered to the headroom ceiling, and defaults to full
+	/// scale with makeup gain, so `--clip` alone gives it unless it is the
+	/// volume cap below both.
+	fn clip_option(&self) -> Option<&'static str> {
+		let clip = self.clip?;
+		let cap = self.limiter.volume_cap;
+
+		match clip == cap && cap < self.limiter.ceiling.unwrap_or(1.0) {
+			true => Some("\"cap\""),
+			false => Some("true"),
+		}
+	}
+
+	/// Returns the playback target length and the recording fragment size in
+	/// bytes.
+	fn buffer_sizes(&self) -> (u32, u32) {
+		let sample_size = self.format.size() as u32;
+
+		match self.latency_ms {
+			None => (1024, 1024 * sample_size),
+			Some(ms) => {
+				let frames = (ms / 1000.0 * self.spec.rate as f32).round().max(1.0) as u32;
+				(
+					frames * sample_size * self.output_channels() as u32,
+					frames * sample_size * self.spec.channels as u32,
+				)
+			}
+		}
+	}
+}
+
+fn run(config: &Config, input: Option<WavReader>) -> Result<(), ShooshError> {
+	let recorder = Recorder::create(config)
+		.map_err(ShooshError::Record)?
+		.map(|recorder| Rc::new(RefCell::new(recorder)));
+
+	let control = (config.control.is_some() || config.dbus || config.metrics_addr.is_some())
+		.then(|| Arc::new(Control::new(config.limiter.volume_cap)));
+
+	// removes the socket when dropped
+	let _control_socket = match (&config.control, &control) {
+		(Some(path), Some(control)) =>
+			Some(ControlSocket::bind(path, Arc::clone(control)).map_err(ShooshError::Control)?),
+		_ => None,
+	};
+
+	#[cfg(feature = "dbus")]
+	let _dbus = match (config.dbus, &control) {
+		(true, Some(control)) => Some(dbus::start(Arc::clone(control)).map_err(ShooshError::Dbus)?),
+		_ => None,
+	};
+
+	#[cfg(feature = "metrics")]
+	if let (Some(addr), Some(control)) = (&config.metrics_addr, &control) {
+		metrics::start(addr, Arc::clone(control)).map_err(ShooshError::Metrics)?;
+	}
+
+	let result = match input {
+		Some(input) if recorder.is_some() =>
+			process_offline(config, input, recorder.clone(), control),
+		input => match config.backend {
+			Backend::Pulse => run_sessions(
+				config,
+				recorder.as_ref(),
+				input.map(|input| Rc::new(RefCell::new(input))),
+				control,
+			),
+			#[cfg(feature = "pipewire")]
+			Backend::Pipewire => pipewire::run(config, recorder.clone(), control),
+			#[cfg(not(feature = "pipewire"))]
+			Backend::Pipewire => unreachable!("the pipewire backend needs the pipewire feature"),
+		},
+	};
+
+	// the header is only valid once finalized, so do it even after a failure
+	if let Some(recorder) = recorder {
+		let finalized = recorder
+			.borrow_mut()
+			.finalize()
+			.map_err(ShooshError::Record);
+		result.and(finalized)
+	} else {
+		result
+	}
+}
+
+/// Measures `input`, or the source for `CALIBRATION_TIME`, and prints the
+/// volume cap that brings its average level to `target_db`.
+fn calibrate(config: &Config, input: Option<WavReader>, target_db: f32) -> Result<(), ShooshError> {
+	let calibration = Rc::new(RefCell::new(Calibration::new(
+		config.limiter.detector,
+		config.limiter.chunk_size,
+	)));
+
+	match input {
+		Some(mut input) => {
+			let mut buffer = vec![0; 1024 * config.format.size()];
+			let mut samples = Vec::new();
+
+			loop {
+				let read = input.read(&mut buffer).map_err(ShooshError::InputFile)?;
+				if read == 0 {
+					break
 				}
-				_ => continue 'wait_streams,
+
+				config.format.decode_into(&buffer[..read], &mut samples);
+				calibration.borrow_mut().add(&samples);
 			}
 		}
+		None => {
+			info!(
+				"calibrating for {}s, play something at a typical volume",
+				CALIBRATION_TIME.as_secs()
+			);
+			Session::connect(config)?.calibrate(config, &calibration, CALIBRATION_TIME)?;
+		}
+	}
 
-		break
+	let calibration = calibration.borrow();
+	match (calibration.rms_db(), calibration.volume_cap_db(target_db)) {
+		(Some(rms_db), Some(volume_cap_db)) => {
+			info!("average input level: {rms_db:.1} dB RMS");
+			println!("recommended volume cap: -d {volume_cap_db:.1}");
+		}
+		_ => warn!("the input was silent, nothing to calibrate against"),
 	}
 
-	const BUFFER_SIZE: usize = 128;
-	let mut volume_buffer = RingBuffer::<f32>::new(BUFFER_SIZE);
+	Ok(())
+}
+
+/// Processes `input` straight into the recording without connecting to the
+/// server, stopping after `config.duration` of it if set.
+fn process_offline(
+	config: &Config,
+	mut input: WavReader,
+	recorder: Option<Shared<Recorder>>,
+	control: Option<Arc<Control>>,
+) -> Result<(), ShooshError> {
+	let stats = config
+		.stats
+		.map(|interval| Rc::new(RefCell::new(Stats::new(interval))));
+	let mut pipeline = Pipeline::new(config, recorder, stats.clone(), control);
+	let mut buffer = vec![0; 1024 * config.format.size()];
+
+	// offline processing runs faster than real time, so the duration is of input
+	let frame_size = config.format.size() * config.spec.channels as usize;
+	let mut remaining = config
+		.duration
+		.map(|duration| (duration.as_secs_f64() * config.spec.rate as f64) as usize * frame_size);
+
+	while !shutdown::requested() && remaining != Some(0) {
+		let size = remaining.map_or(buffer.len(), |remaining| remaining.min(buffer.len()));
+		let read = input
+			.read(&mut buffer[..size])
+			.map_err(ShooshError::InputFile)?;
+		if read == 0 {
+			break
+		}
+
+		if let Some(remaining) = &mut remaining {
+			*remaining -= read;
+		}
+
+		pipeline.process(&buffer[..read])?;
+		if let Some(stats) = &stats {
+			stats.borrow_mut().report_if_due();
+		}
+	}
+
+	Ok(())
+}
+
+/// Runs sessions until shutdown or the end of the input file, reconnecting as
+/// configured.
+///
+/// Only a session that was established is reconnected. When the first connect
+/// fails, the server or a device is more likely missing or misspelled than
+/// briefly gone, so the error is returned instead.
+fn run_sessions(
+	config: &Config,
+	recorder: Option<&Shared<Recorder>>,
+	input: Option<Shared<WavReader>>,
+	control: Option<Arc<Control>>,
+) -> Result<(), ShooshError> {
+	if let Some(duration) = config.duration {
+		shutdown::set_deadline(duration);
+	}
+
+	let mut backoff = INITIAL_BACKOFF;
+	let mut connected = false;
+
 	loop {
-		poll_mainloop(&mut mainloop);
-
-		match recording_stream.peek().unwrap() {
-			PeekResult::Empty => {}
-			PeekResult::Hole(_) => recording_stream.discard().unwrap(),
-			PeekResult::Data(data) => {
-				let start = std::time::Instant::now();
-				let float_data = data
-					.chunks(mem::size_of::<f32>())
-					.map(|chunk| f32::from_le_bytes(<[u8; 4]>::try_from(chunk).unwrap()))
-					.collect::<Vec<f32>>();
-				let audio_data = float_data
-					.chunks(64)
-					.map(|chunk| {
-						let chunk_max = chunk
-							.iter()
-							.fold(0.0, |a: f32, &b| f32::max(a.abs(), b.abs()));
-						volume_buffer.append(&[chunk_max]);
-
-						let weighted_average = volume_buffer
-							.iter()
-							.enumerate()
-							.map(|(i, v)| v * (i as f32 / BUFFER_SIZE as f32))
-							.sum::<f32>() / (BUFFER_SIZE as f32 * 0.5);
-
-						let volume_multiplier =
-							volume_cap / weighted_average.max(volume_cap).max(chunk_max);
-						/*println!(
-							"VolMul: {volume_multiplier:.03} | WAVG: {weighted_average:.3} | \
-							 CWAVG: {:.3}",
-							weighted_average.max(volume_cap).max(chunk_max)
-						);*/
-						chunk.into_iter().map(move |v| v * volume_multiplier)
-					})
-					.flatten()
-					.collect::<Vec<_>>();
-
-				playback_stream
-					.write(
-						&audio_data
-							.iter()
-							.map(|f| f.to_le_bytes())
-							.flatten()
-							.collect::<Vec<_>>()[..],
-						None,
-						0,
-						SeekMode::Relative,
-					)
-					.unwrap();
-
-				recording_stream.discard().unwrap();
-				println!("Processing took {:?}", std::time::Instant::now().duration_since(start));
+		let result = match Session::connect(config) {
+			Ok(mut session) => {
+				connected = true;
+				backoff = INITIAL_BACKOFF;
+				session.process(config, recorder.cloned(), input.clone(), control.clone())
+			}
+			Err(e) if !connected => return Err(e),
+			Err(e) => Err(e),
+		};
+
+		match result {
+			Ok(()) => return Ok(()),
+			Err(e) if config.reconnect && e.is_recoverable() => {
+				warn!("{e}, reconnecting in {}s...", backoff.as_secs());
+				if !sleep_unless_shutdown(backoff) {
+					return Ok(())
+				}
+
+				backoff = (backoff * 2).min(MAX_BACKOFF);
 			}
+			Err(e) => return Err(e),
+		}
+	}
+}
+
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Sleeps for `duration`, returning false early if shutdown was requested.
+fn sleep_unless_shutdown(duration: Duration) -> bool {
+	let deadline = Instant::now() + duration;
+
+	while Instant::now() < deadline {
+		if shutdown::requested() {
+			return false
 		}
+
+		thread::sleep(Duration::from_millis(100));
+	}
+
+	!shutdown::requested()
+}
+
+#[cfg(test)]
+mod test {
+	use std::time::Duration;
+
+	use pulse::volume::{VolumeDB, VolumeLinear};
+	use shoosh::processor::VolumeCapSettings;
+
+	use super::{config_args, config_file, options, Config};
+
+	#[test]
+	fn dump_config() {
+		let volume_cap = VolumeLinear::from(VolumeDB(-12.0)).0 as f32;
+		let config = Config {
+			balance_db: Some(vec![0.0, -3.0]),
+			mid_side: true,
+			multiband: Some(vec![200.0, 2000.0]),
+			suspend_after: Some(Duration::from_secs(5)),
+			clip: Some(volume_cap),
+			limiter: VolumeCapSettings {
+				volume_cap,
+				chunk_size: 128,
+				..Default::default()
+			},
+			..Default::default()
+		};
+		let dump = config.dump();
+
+		assert!(dump.contains("volume-db = -12\n"));
+		assert!(dump.contains("format = \"f32le\"\nrate = 44100\nchannels = 2\n"));
+		assert!(dump.contains("chunk = 128\n"));
+		assert!(dump.contains("suspend-on-silence = 5\n"));
+		assert!(dump.contains("weighting = \"linear\"\n"));
+		assert!(dump.contains("# gain-floor is not set\n"));
+		assert!(dump.contains("# sink is not set\n"));
+		assert!(!dump.contains("decay"));
+
+		// the dump reads back as a config file, setting the same options
+		let entries = config_file::parse(&dump).unwrap();
+		let matches = options()
+			.parse(config_args(entries).into_iter().map(|(_, arg)| arg))
+			.unwrap();
+		assert_eq!(Some("-12"), matches.opt_str("volume-db").as_deref());
+		assert_eq!(Some("128"), matches.opt_str("chunk").as_deref());
+		assert_eq!(Some("0:-3"), matches.opt_str("balance").as_deref());
+		assert_eq!(Some("200,2000"), matches.opt_str("multiband").as_deref());
+		assert_eq!(Some("5"), matches.opt_str("suspend-on-silence").as_deref());
+		assert_eq!(Some("cap"), matches.opt_str("clip").as_deref());
+		assert!(matches.opt_present("ms"));
+		assert!(!matches.opt_present("mono"));
+		assert!(!matches.opt_present("no-reconnect"));
+		assert!(!matches.opt_present("sink"));
 	}
 }
@@ -0,0 +1,107 @@
+//! Live level meter drawn on stderr.
+
+use std::{
+	io::{self, Write},
+	time::{Duration, Instant},
+};
+
+use shoosh::processor::Measurement;
+
+/// Minimum time between redraws.
+const REDRAW_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Width of each bar in characters.
+const BAR_WIDTH: usize = 30;
+
+/// Lowest level shown on the bars.
+const FLOOR_DB: f32 = -60.0;
+
+pub struct Meter {
+	/// Redraw a single line in place instead of printing one line per update
+	in_place: bool,
+	last_draw: Option<Instant>,
+}
+
+impl Meter {
+	pub fn new() -> Self {
+		// SAFETY: isatty has no preconditions.
+		let in_place = unsafe { libc::isatty(libc::STDERR_FILENO) } == 1;
+
+		Self {
+			in_place,
+			last_draw: None,
+		}
+	}
+
+	/// Draws `measurement`, unless the meter was drawn very recently.
+	pub fn update(&mut self, measurement: Measurement) {
+		let now = Instant::now();
+		if self
+			.last_draw
+			.is_some_and(|last_draw| now - last_draw < REDRAW_INTERVAL)
+		{
+			return
+		}
+
+		self.last_draw = Some(now);
+
+		let line = render(measurement);
+		let mut stderr = io::stderr().lock();
+		// the meter is best effort, so write errors are ignored
+		let _ = if self.in_place {
+			write!(stderr, "\r{line}\x1b[K")
+		} else {
+			writeln!(stderr, "{line}")
+		};
+	}
+}
+
+impl Drop for Meter {
+	fn drop(&mut self) {
+		if self.in_place && self.last_draw.is_some() {
+			eprintln!();
+		}
+	}
+}
+
+/// Formats a measurement as a single meter line.
+fn render(measurement: Measurement) -> String {
+	format!(
+		"in {} {:>6.1} dB | avg {} {:>6.1} dB | gain {:.3}",
+		bar(measurement.level),
+		to_db(measurement.level),
+		bar(measurement.average),
+		to_db(measurement.average),
+		measurement.gain,
+	)
+}
+
+fn to_db(level: f32) -> f32 {
+	(20.0 * level.log10()).max(FLOOR_DB)
+}
+
+/// Draws `level` as a bar on a dB scale from `FLOOR_DB` to 0.
+fn bar(level: f32) -> String {
+	let fraction = 1.0 - to_db(level) / FLOOR_DB;
+	let filled = ((fraction * BAR_WIDTH as f32).round() as usize).min(BAR_WIDTH);
+
+	format!("[{}{}]", "#".repeat(filled), " ".repeat(BAR_WIDTH - filled))
+}
+
+#[cfg(test)]
+mod test {
+	use super::{bar, BAR_WIDTH};
+
+	#[test]
+	fn bar_scale() {
+		let empty = format!("[{}]", " ".repeat(BAR_WIDTH));
+		let full = format!("[{}]", "#".repeat(BAR_WIDTH));
+
+		assert_eq!(empty, bar(0.0));
+		assert_eq!(empty, bar(0.0001));
+		assert_eq!(full, bar(1.0));
+		assert_eq!(full, bar(2.0));
+		// -30 dB is halfway
+		assert_eq!(BAR_WIDTH / 2, bar(0.001f32.sqrt()).matches('#').count());
+	}
+}
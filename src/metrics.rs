@@ -0,0 +1,174 @@
+//! Prometheus metrics endpoint, built with the `metrics` feature and enabled
+//! with `--metrics-addr`.
+//!
+//! `GET /metrics` is answered in the Prometheus text format with:
+//!
+//! - `shoosh_volume_cap` (gauge), the linear volume cap
+//! - `shoosh_gain` (gauge), the gain applied to the most recent chunk
+//! - `shoosh_input_peak` (gauge), the linear peak of the most recent input
+//!   block
+//! - `shoosh_input_rms` (gauge), the linear RMS level of the most recent input
+//!   block
+//! - `shoosh_blocks_total` (counter), blocks processed, so
+//!   `rate(shoosh_blocks_total[1m])` is the processing iterations per second
+//! - `shoosh_underruns_total` (counter), playback underruns
+//! - `shoosh_overruns_total` (counter), recording overruns
+//!
+//! Counters start from 0 when shoosh starts and carry across reconnects. Only
+//! enough HTTP/1.0 for a scraper is implemented, so there are no
+//! dependencies, and only a few clients are served at a time. For example `curl http://127.0.0.1:9500/metrics`.
+
+use std::{
+	fmt::Write as _,
+	io::{self, BufRead, BufReader, Read, Write},
+	net::{TcpListener, TcpStream},
+	sync::{
+		atomic::{AtomicUsize, Ordering},
+		Arc,
+	},
+	thread,
+	time::Duration,
+};
+
+use crate::control::Control;
+
+/// How long a read from or write to a client may block.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Number of clients served at once, further connections are closed
+/// straight away.
+const MAX_CLIENTS: usize = 8;
+
+/// Bytes of request line and headers read before giving up on a client.
+const MAX_REQUEST_SIZE: u64 = 8192;
+
+/// Binds `addr` and serves metrics for `control` on a background thread.
+pub fn start(addr: &str, control: Arc<Control>) -> io::Result<()> {
+	let listener = TcpListener::bind(addr)?;
+	debug!("serving metrics on {}", listener.local_addr()?);
+
+	let clients = Arc::new(AtomicUsize::new(0));
+	thread::spawn(move || {
+		for stream in listener.incoming() {
+			match stream {
+				Ok(_) if clients.load(Ordering::Relaxed) >= MAX_CLIENTS => {
+					debug!("too many metrics clients, closing connection");
+				}
+				Ok(stream) => {
+					let control = Arc::clone(&control);
+					let clients = Arc::clone(&clients);
+					clients.fetch_add(1, Ordering::Relaxed);
+					thread::spawn(move || {
+						if let Err(e) = serve(&control, stream) {
+							debug!("metrics client failed: {e}");
+						}
+						clients.fetch_sub(1, Ordering::Relaxed);
+					});
+				}
+				Err(e) => warn!("failed to accept metrics client: {e}"),
+			}
+		}
+	});
+
+	Ok(())
+}
+
+fn serve(control: &Control, stream: TcpStream) -> io::Result<()> {
+	stream.set_read_timeout(Some(TIMEOUT))?;
+	stream.set_write_timeout(Some(TIMEOUT))?;
+	let mut writer = stream.try_clone()?;
+	// a client trickling bytes in under the timeout still runs out eventually
+	let mut reader = BufReader::new(stream.take(MAX_REQUEST_SIZE));
+
+	let mut request = String::new();
+	reader.read_line(&mut request)?;
+
+	// the headers don't matter, but must be read before replying
+	let mut header = String::new();
+	while reader.read_line(&mut header)? > 0 && !header.trim().is_empty() {
+		header.clear();
+	}
+
+	let (status, body) = match is_metrics_request(&request) {
+		true => ("200 OK", render(control)),
+		false => ("404 Not Found", "not found\n".to_string()),
+	};
+
+	write!(
+		writer,
+		"HTTP/1.0 {status}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: \
+		 {}\r\nConnection: close\r\n\r\n{body}",
+		body.len()
+	)
+}
+
+/// Returns true if the request line asks for the metrics.
+fn is_metrics_request(request: &str) -> bool {
+	let mut words = request.split_whitespace();
+	(words.next(), words.next()) == (Some("GET"), Some("/metrics"))
+}
+
+/// Formats the current values of `control` as Prometheus metrics.
+fn render(control: &Control) -> String {
+	let gauges = [
+		("volume_cap", "Linear volume cap", control.volume_cap()),
+		("gain", "Gain applied to the most recent chunk", control.measurement().gain),
+		("input_peak", "Linear peak of the most recent input block", control.input_peak()),
+		(
+			"input_rms",
+			"Linear RMS level of the most recent input block",
+			control.input_rms(),
+		),
+	];
+	let counters = [
+		("blocks_total", "Blocks processed", control.blocks()),
+		("underruns_total", "Playback underruns", control.underruns()),
+		("overruns_total", "Recording overruns", control.overruns()),
+	];
+
+	let mut output = String::new();
+	for (name, help, value) in gauges {
+		let _ = write!(output, "# HELP shoosh_{name} {help}\n# TYPE shoosh_{name} gauge\n");
+		let _ = writeln!(output, "shoosh_{name} {value}");
+	}
+	for (name, help, value) in counters {
+		let _ = write!(output, "# HELP shoosh_{name} {help}\n# TYPE shoosh_{name} counter\n");
+		let _ = writeln!(output, "shoosh_{name} {value}");
+	}
+
+	output
+}
+
+#[cfg(test)]
+mod test {
+	use super::{is_metrics_request, render};
+	use crate::control::Control;
+
+	#[test]
+	fn renders_metrics() {
+		let control = Control::new(0.5);
+		control.set_input_rms(0.25);
+		control.count_block();
+		control.count_block();
+		control.count_overrun();
+
+		let output = render(&control);
+		assert!(output.contains("# TYPE shoosh_volume_cap gauge\nshoosh_volume_cap 0.5\n"));
+		assert!(output.contains("shoosh_input_rms 0.25\n"));
+		assert!(output.contains("# TYPE shoosh_blocks_total counter\nshoosh_blocks_total 2\n"));
+		assert!(output.contains("shoosh_underruns_total 0\n"));
+		assert!(output.contains("shoosh_overruns_total 1\n"));
+
+		// every sample has its help and type
+		let samples = output.lines().filter(|line| !line.starts_with('#')).count();
+		assert_eq!(samples * 3, output.lines().count());
+	}
+
+	#[test]
+	fn routes() {
+		assert!(is_metrics_request("GET /metrics HTTP/1.1\r\n"));
+		assert!(!is_metrics_request("GET / HTTP/1.1\r\n"));
+		assert!(!is_metrics_request("POST /metrics HTTP/1.1\r\n"));
+		assert!(!is_metrics_request(""));
+	}
+}
@@ -0,0 +1,65 @@
+/// Converts interleaved left/right frames to mid/side, with mid the average of
+/// the channels and side half their difference.
+pub fn to_mid_side(input: &[f32], output: &mut [f32]) {
+	assert_eq!(input.len(), output.len());
+
+	for (frame, output) in input.chunks_exact(2).zip(output.chunks_exact_mut(2)) {
+		output[0] = (frame[0] + frame[1]) * 0.5;
+		output[1] = (frame[0] - frame[1]) * 0.5;
+	}
+}
+
+/// Converts interleaved mid/side frames back to left/right, undoing
+/// `to_mid_side`.
+///
+/// The sum of mid and side can reach twice the level of either, so a volume
+/// cap applied to mid and side separately holds for each channel only up to
+/// that factor.
+pub fn to_left_right(input: &[f32], output: &mut [f32]) {
+	assert_eq!(input.len(), output.len());
+
+	for (frame, output) in input.chunks_exact(2).zip(output.chunks_exact_mut(2)) {
+		output[0] = frame[0] + frame[1];
+		output[1] = frame[0] - frame[1];
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::{to_left_right, to_mid_side};
+	use crate::{
+		processor::{Processor, VolumeCapSettings},
+		unlinked::UnlinkedVolumeCap,
+	};
+
+	#[test]
+	fn round_trip() {
+		let input = [0.5, 0.5, 0.5, -0.5, 1.0, 0.0, -0.25, 0.75, 0.375, -0.875];
+		let mut mid_side = [0.0; 10];
+		let mut output = [0.0; 10];
+
+		to_mid_side(&input, &mut mid_side);
+		// identical channels are all mid, opposite ones all side
+		assert_eq!([0.5, 0.0, 0.0, 0.5], mid_side[..4]);
+
+		to_left_right(&mid_side, &mut output);
+		assert_eq!(input, output);
+	}
+
+	#[test]
+	fn unity_gain_is_lossless() {
+		let input = (0..64 * 16)
+			.map(|i| ((i * 37) % 64) as f32 / 128.0 - 0.25)
+			.collect::<Vec<_>>();
+		let mut mid_side = vec![0.0; input.len()];
+		let mut limited = vec![0.0; input.len()];
+		let mut output = vec![0.0; input.len()];
+
+		let mut volume_cap = UnlinkedVolumeCap::new(&VolumeCapSettings::default());
+		to_mid_side(&input, &mut mid_side);
+		volume_cap.process(&mid_side, &mut limited);
+		to_left_right(&limited, &mut output);
+
+		assert_eq!(input, output);
+	}
+}
@@ -0,0 +1,156 @@
+use crate::{
+	crossover::Crossover,
+	processor::{Measurement, Processor, VolumeCap, VolumeCapSettings},
+};
+
+/// Limits frequency bands of its input separately and sums them back, so a
+/// loud bass doesn't reduce the gain of everything above it.
+///
+/// Each band gets its own `VolumeCap` with the same settings, cap included,
+/// so the recombined output can exceed the cap by up to a factor of the
+/// number of bands when every band is at it.
+#[derive(Debug)]
+pub struct MultibandVolumeCap {
+	crossover: Crossover,
+	/// One volume cap per band, lowest first
+	bands: Vec<VolumeCap>,
+	/// Scratch buffers for the input and output of each band
+	input: Vec<Vec<f32>>,
+	output: Vec<f32>,
+}
+
+impl MultibandVolumeCap {
+	/// Creates a multiband volume cap with a band below, between and above
+	/// each of `frequencies`.
+	///
+	/// Panics if `frequencies` is empty or not ascending, or on settings
+	/// `VolumeCap::new` rejects.
+	pub fn new(frequencies: &[f32], settings: &VolumeCapSettings) -> Self {
+		let crossover = Crossover::new(frequencies, settings.channels as usize, settings.rate);
+
+		Self {
+			bands: (0..crossover.bands())
+				.map(|_| VolumeCap::new(settings))
+				.collect(),
+			input: vec![Vec::new(); crossover.bands()],
+			output: Vec::new(),
+			crossover,
+		}
+	}
+
+	/// Returns the highest levels and the lowest gain of any band for the most
+	/// recently processed chunk.
+	pub fn measurement(&self) -> Measurement {
+		self.bands
+			.iter()
+			.map(VolumeCap::measurement)
+			.reduce(|a, b| Measurement {
+				level: a.level.max(b.level),
+				average: a.average.max(b.average),
+				gain: a.gain.min(b.gain),
+				boost_clamped: a.boost_clamped || b.boost_clamped,
+			})
+			.unwrap_or_default()
+	}
+
+	/// Changes the linear volume cap of every band, taking effect from the
+	/// next chunk.
+	pub fn set_volume_cap(&mut self, volume_cap: f32) {
+		for band in &mut self.bands {
+			band.set_volume_cap(volume_cap);
+		}
+	}
+}
+
+impl Processor for MultibandVolumeCap {
+	fn process(&mut self, input: &[f32], output: &mut [f32]) {
+		assert_eq!(input.len(), output.len());
+
+		self.crossover.split(input, &mut self.input);
+		output.fill(0.0);
+		self.output.resize(input.len(), 0.0);
+
+		for (band, input) in self.bands.iter_mut().zip(&self.input) {
+			band.process(input, &mut self.output);
+
+			for (output, &sample) in output.iter_mut().zip(&self.output) {
+				*output += sample;
+			}
+		}
+	}
+
+	fn reset(&mut self) {
+		self.crossover.reset();
+		for band in &mut self.bands {
+			band.reset();
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::MultibandVolumeCap;
+	use crate::{
+		crossover::Crossover,
+		processor::{VolumeCap, VolumeCapSettings},
+		test_support::{process, settled_rms, sine},
+	};
+
+	/// Two seconds of a loud 60Hz tone under a quiet 6kHz one, in stereo.
+	fn bass_and_treble() -> Vec<f32> {
+		let treble = sine(6000.0, 0.1, 2.0, 44100);
+		sine(60.0, 0.8, 2.0, 44100)
+			.iter()
+			.zip(treble)
+			.map(|(bass, treble)| bass + treble)
+			.collect()
+	}
+
+	/// Returns the RMS level of each band of the second half of `samples`.
+	fn band_levels(samples: &[f32]) -> Vec<f32> {
+		let mut crossover = Crossover::new(&[1000.0], 2, 44100);
+		let mut bands = vec![Vec::new(); 2];
+		crossover.split(samples, &mut bands);
+
+		bands.iter().map(|band| settled_rms(band)).collect()
+	}
+
+	#[test]
+	fn bands_are_independent() {
+		let settings = VolumeCapSettings {
+			volume_cap: 0.25,
+			..Default::default()
+		};
+		let input = bass_and_treble();
+		let treble = 0.1 / 2.0f32.sqrt();
+
+		let mut multiband = MultibandVolumeCap::new(&[1000.0], &settings);
+		let levels = band_levels(&process(&mut multiband, &input));
+		// the bass is brought down toward the cap, the treble left alone
+		assert!(levels[0] < 0.8 / 2.0f32.sqrt() * 0.5, "bass {}", levels[0]);
+		assert!((levels[1] / treble - 1.0).abs() < 0.05, "treble {}", levels[1]);
+		assert!(multiband.measurement().gain < 0.5);
+
+		// broadband, the treble is reduced along with the bass
+		let levels = band_levels(&process(&mut VolumeCap::new(&settings), &input));
+		assert!(levels[1] < treble * 0.5, "broadband treble {}", levels[1]);
+	}
+
+	#[test]
+	fn quiet_input_sums_back() {
+		let input = bass_and_treble()
+			.iter()
+			.map(|v| v * 0.1)
+			.collect::<Vec<_>>();
+		let output = process(
+			&mut MultibandVolumeCap::new(&[200.0, 2000.0], &VolumeCapSettings::default()),
+			&input,
+		);
+
+		// nothing is limited, so only the crossover's phase shift remains
+		let input_levels = band_levels(&input);
+		for (output, input) in band_levels(&output).iter().zip(input_levels) {
+			assert!((output / input - 1.0).abs() < 0.01, "{output} != {input}");
+		}
+	}
+}
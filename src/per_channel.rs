@@ -0,0 +1,73 @@
+//! State kept for each channel by stages that filter interleaved samples.
+
+/// The state of each channel of an interleaved signal, along with the
+/// channel of the next sample.
+///
+/// Filter state is kept per channel, so interleaved input may be split into
+/// chunks anywhere, even mid-frame.
+#[derive(Debug, Clone)]
+pub(crate) struct PerChannel<S> {
+	/// State every channel starts from, and returns to on `reset`
+	initial: S,
+	states: Vec<S>,
+	/// Channel of the next sample
+	channel: usize,
+}
+
+impl<S: Clone> PerChannel<S> {
+	/// Creates state for `channels` channels, each starting as `initial`.
+	///
+	/// Panics if `channels` is zero.
+	pub(crate) fn new(channels: usize, initial: S) -> Self {
+		assert!(channels != 0, "at least one channel is needed");
+
+		Self {
+			states: vec![initial.clone(); channels],
+			initial,
+			channel: 0,
+		}
+	}
+
+	/// Returns the state of the next sample's channel, moving on to the
+	/// channel after it.
+	pub(crate) fn next_mut(&mut self) -> &mut S {
+		let channel = self.channel;
+		self.channel = (channel + 1) % self.states.len();
+		&mut self.states[channel]
+	}
+
+	/// Returns every channel to the initial state, starting again from the
+	/// first channel.
+	pub(crate) fn reset(&mut self) {
+		self.states.fill(self.initial.clone());
+		self.channel = 0;
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::PerChannel;
+
+	#[test]
+	fn follows_interleaved_channels() {
+		let mut sums = PerChannel::new(3, 0);
+
+		// split mid-frame, which must not mix up the channels
+		for chunk in [&[1, 10, 100, 2][..], &[20, 200, 3, 30, 300]] {
+			for &sample in chunk {
+				*sums.next_mut() += sample;
+			}
+		}
+		assert_eq!(vec![6, 60, 600], sums.states);
+
+		sums.reset();
+		*sums.next_mut() += 5;
+		assert_eq!(vec![5, 0, 0], sums.states);
+	}
+
+	#[test]
+	#[should_panic(expected = "at least one channel is needed")]
+	fn zero_channels() {
+		PerChannel::new(0, 0.0);
+	}
+}
@@ -0,0 +1,440 @@
+//! Decoding, processing and encoding of blocks of audio.
+
+use std::{
+	cell::RefCell,
+	mem,
+	rc::Rc,
+	sync::Arc,
+	time::{Duration, Instant},
+};
+
+use shoosh::{
+	balance::Balance,
+	channel_map::ChannelMap,
+	clip::Clip,
+	dc_block::DcBlock,
+	detector::Detector,
+	downmix::downmix,
+	duck::Ducker,
+	gain::Gain,
+	loudness::LoudnessNormalizer,
+	mid_side::{to_left_right, to_mid_side},
+	multiband::MultibandVolumeCap,
+	processor::{Measurement, Processor, VolumeCap},
+	softclip::SoftClip,
+	suspend::SilenceSuspend,
+	unlinked::UnlinkedVolumeCap,
+};
+
+use crate::{
+	control::Control,
+	error::ShooshError,
+	meter::Meter,
+	recorder::Recorder,
+	sample::{Dither, FrameAssembler, SampleFormat},
+	session::Shared,
+	stats::Stats,
+	Config,
+};
+
+/// Time the output fades in over when resuming after silence.
+const RESUME_RAMP: Duration = Duration::from_millis(10);
+
+/// Decodes blocks of input, runs them through the processor and encodes them
+/// for playback. Everything returned for playback is also recorded.
+pub struct Pipeline {
+	format: SampleFormat,
+	/// Input channel count
+	channels: usize,
+	/// Holds back partial frames split across blocks
+	assembler: FrameAssembler,
+	/// Pass the input through without decoding or processing it
+	bypass: bool,
+	channel_map: Option<ChannelMap>,
+	/// Per-channel gain correcting an imbalanced input
+	balance: Option<Balance>,
+	/// Input channel count to average into one, if downmixing
+	downmix: Option<usize>,
+	/// Skips processing while the input is silent
+	suspend: Option<SilenceSuspend>,
+	dc_block: Option<DcBlock>,
+	/// Moves the long-term loudness toward `--lufs` ahead of the volume cap
+	loudness: Option<LoudnessNormalizer>,
+	/// Limit the mid and side of stereo input instead of left and right
+	mid_side: bool,
+	processor: Limiter,
+	/// Lowers the limited output while the sidechain is active, shared with
+	/// the sidechain stream's read callback
+	ducker: Option<Shared<Ducker>>,
+	/// Fixed gain after the limiter, ahead of the clip
+	makeup: Option<Gain>,
+	/// Rounds off peaks ahead of the clip
+	softclip: Option<SoftClip>,
+	clip: Option<Clip>,
+	dither: Option<Dither>,
+	meter: Option<Meter>,
+	recorder: Option<Shared<Recorder>>,
+	stats: Option<Shared<Stats>>,
+	control: Option<Arc<Control>>,
+	/// Scratch buffers reused across blocks to keep allocations off the hot
+	/// path
+	input: Vec<f32>,
+	output: Vec<f32>,
+	bytes: Vec<u8>,
+}
+
+impl Pipeline {
+	pub fn new(
+		config: &Config,
+		recorder: Option<Shared<Recorder>>,
+		stats: Option<Shared<Stats>>,
+		control: Option<Arc<Control>>,
+	) -> Self {
+		Self {
+			format: config.format,
+			channels: config.spec.channels as usize,
+			assembler: FrameAssembler::new(config.format.size() * config.spec.channels as usize),
+			bypass: config.bypass,
+			channel_map: config.channel_map.clone(),
+			balance: config.balance_db.as_deref().map(Balance::from_db),
+			downmix: config.mono.then_some(config.spec.channels as usize),
+			suspend: config.suspend_after.map(|hold| {
+				let frames = |duration: Duration| {
+					(duration.as_secs_f32() * config.spec.rate as f32).round() as usize
+				};
+				SilenceSuspend::new(config.silence_threshold, frames(hold), frames(RESUME_RAMP))
+			}),
+			dc_block: config
+				.dc_block
+				.then(|| DcBlock::new(config.output_channels() as usize, config.spec.rate)),
+			loudness: config.lufs.map(|target| {
+				LoudnessNormalizer::new(target, config.output_channels() as usize, config.spec.rate)
+			}),
+			mid_side: config.mid_side,
+			processor: if let Some(frequencies) = &config.multiband {
+				Limiter::Multiband(MultibandVolumeCap::new(frequencies, &config.limiter))
+			} else if config.unlink || config.mid_side {
+				Limiter::Unlinked(UnlinkedVolumeCap::new(&config.limiter))
+			} else {
+				Limiter::Linked(Box::new(VolumeCap::new(&config.limiter)))
+			},
+			ducker: config
+				.sidechain
+				.as_ref()
+				.map(|_| Rc::new(RefCell::new(Ducker::new(&config.duck)))),
+			makeup: config.makeup_db.map(Gain::from_db),
+			softclip: config
+				.softclip
+				.then(|| SoftClip::new(config.output_channels() as usize)),
+			clip: config.clip.map(Clip::new),
+			dither: config.dither.then(Dither::new),
+			meter: config.meter.then(Meter::new),
+			recorder,
+			stats,
+			control,
+			input: Vec::new(),
+			output: Vec::new(),
+			bytes: Vec::new(),
+		}
+	}
+
+	/// Processes a block of encoded samples, returning the encoded result.
+	pub fn process(&mut self, data: &[u8]) -> Result<&[u8], ShooshError> {
+		let start = Instant::now();
+		let data = self.assembler.push(data);
+
+		if self.bypass {
+			self.bytes.clear();
+			self.bytes.extend_from_slice(data);
+
+			let processing_time = start.elapsed();
+			trace!("processing took {processing_time:?}");
+			// nothing is measured, so the stats only show the overhead
+			if let Some(stats) = &self.stats {
+				stats.borrow_mut().block(processing_time, 0.0, 1.0);
+			}
+			if let Some(control) = &self.control {
+				control.count_block();
+			}
+
+			self.record()?;
+			return Ok(&self.bytes)
+		}
+
+		self.format.decode_into(data, &mut self.input);
+
+		if let Some(suspend) = &mut self.suspend {
+			let was_suspended = suspend.is_suspended();
+			let skip = suspend.update(&self.input, self.channels);
+
+			if skip != was_suspended {
+				if skip {
+					debug!("input is silent, suspending processing");
+				} else {
+					debug!("input returned, resuming processing");
+				}
+			}
+
+			if skip {
+				let size = data.len();
+				return self.silence(size)
+			}
+		}
+
+		self.output.resize(self.input.len(), 0.0);
+		let input_peak = Detector::Peak.level(&self.input);
+		if let Some(control) = &self.control {
+			control.set_input_peak(input_peak);
+			control.set_input_rms(Detector::Rms.level(&self.input));
+		}
+
+		if let Some(channel_map) = &mut self.channel_map {
+			channel_map.process(&self.input, &mut self.output);
+			mem::swap(&mut self.input, &mut self.output);
+		}
+
+		if let Some(balance) = &mut self.balance {
+			balance.process(&self.input, &mut self.output);
+			mem::swap(&mut self.input, &mut self.output);
+		}
+
+		if let Some(channels) = self.downmix {
+			downmix(&self.input, channels, &mut self.output);
+			mem::swap(&mut self.input, &mut self.output);
+		}
+		self.output.resize(self.input.len(), 0.0);
+
+		if let Some(dc_block) = &mut self.dc_block {
+			dc_block.process(&self.input, &mut self.output);
+			mem::swap(&mut self.input, &mut self.output);
+		}
+
+		if let Some(loudness) = &mut self.loudness {
+			loudness.process(&self.input, &mut self.output);
+			mem::swap(&mut self.input, &mut self.output);
+		}
+
+		if self.mid_side {
+			to_mid_side(&self.input, &mut self.output);
+			mem::swap(&mut self.input, &mut self.output);
+		}
+
+		if let Some(control) = &self.control {
+			self.processor.set_volume_cap(control.volume_cap());
+		}
+		self.processor.process(&self.input, &mut self.output);
+		if self.processor.measurement().boost_clamped {
+			trace!("gain target exceeded unity and was clamped");
+		}
+		if let Some(control) = &self.control {
+			control.set_measurement(self.processor.measurement());
+			control.count_block();
+		}
+
+		if self.mid_side {
+			to_left_right(&self.output, &mut self.input);
+			mem::swap(&mut self.input, &mut self.output);
+		}
+
+		if let Some(ducker) = &self.ducker {
+			ducker.borrow_mut().process(&self.output, &mut self.input);
+			mem::swap(&mut self.input, &mut self.output);
+		}
+
+		let output_channels = self.output_channels();
+		if let Some(suspend) = &mut self.suspend {
+			suspend.ramp(&mut self.output, output_channels);
+		}
+		if let Some(makeup) = &mut self.makeup {
+			makeup.process(&self.output, &mut self.input);
+			mem::swap(&mut self.input, &mut self.output);
+		}
+		if let Some(softclip) = &mut self.softclip {
+			softclip.process(&self.output, &mut self.input);
+			mem::swap(&mut self.input, &mut self.output);
+		}
+		if let Some(clip) = &mut self.clip {
+			clip.process(&self.output, &mut self.input);
+			mem::swap(&mut self.input, &mut self.output);
+		}
+		if let Some(meter) = &mut self.meter {
+			meter.update(self.processor.measurement());
+		}
+		if let Some(dither) = &mut self.dither {
+			dither.apply(&mut self.output);
+		}
+		self.format.encode_into(&self.output, &mut self.bytes);
+
+		let processing_time = start.elapsed();
+		trace!("processing took {processing_time:?}");
+		if let Some(stats) = &self.stats {
+			stats.borrow_mut().block(
+				processing_time,
+				input_peak,
+				self.processor.measurement().gain,
+			);
+		}
+
+		self.record()?;
+		Ok(&self.bytes)
+	}
+
+	/// Returns the ducker to feed the sidechain into, if there is one.
+	pub fn ducker(&self) -> Option<Shared<Ducker>> {
+		self.ducker.clone()
+	}
+
+	fn output_channels(&self) -> usize {
+		if self.downmix.is_some() {
+			1
+		} else {
+			self.channels
+		}
+	}
+
+	/// Returns the input size in bytes that produces `size` bytes of output.
+	pub fn input_size(&self, size: usize) -> usize {
+		size * self.downmix.unwrap_or(1)
+	}
+
+	/// Returns silence in place of `size` bytes of input.
+	pub fn silence(&mut self, size: usize) -> Result<&[u8], ShooshError> {
+		// zero bytes are silence in every supported format
+		self.bytes.clear();
+		self.bytes.resize(size / self.downmix.unwrap_or(1), 0);
+
+		self.record()?;
+		Ok(&self.bytes)
+	}
+
+	/// Discards the processor's history after a gap in the input.
+	pub fn reset(&mut self) {
+		self.assembler.clear();
+		if let Some(dc_block) = &mut self.dc_block {
+			dc_block.reset();
+		}
+		if let Some(loudness) = &mut self.loudness {
+			loudness.reset();
+		}
+		self.processor.reset();
+	}
+
+	fn record(&mut self) -> Result<(), ShooshError> {
+		if let Some(recorder) = &self.recorder {
+			recorder
+				.borrow_mut()
+				.write(&self.bytes)
+				.map_err(ShooshError::Record)?;
+		}
+
+		Ok(())
+	}
+}
+
+/// The volume cap, either over all channels together, each separately or
+/// each frequency band separately.
+enum Limiter {
+	Linked(Box<VolumeCap>),
+	Unlinked(UnlinkedVolumeCap),
+	Multiband(MultibandVolumeCap),
+}
+
+impl Limiter {
+	fn processor(&mut self) -> &mut dyn Processor {
+		match self {
+			Self::Linked(volume_cap) => volume_cap.as_mut(),
+			Self::Unlinked(volume_cap) => volume_cap,
+			Self::Multiband(volume_cap) => volume_cap,
+		}
+	}
+
+	fn process(&mut self, input: &[f32], output: &mut [f32]) {
+		self.processor().process(input, output);
+	}
+
+	fn reset(&mut self) {
+		self.processor().reset();
+	}
+
+	fn measurement(&self) -> Measurement {
+		match self {
+			Self::Linked(volume_cap) => volume_cap.measurement(),
+			Self::Unlinked(volume_cap) => volume_cap.measurement(),
+			Self::Multiband(volume_cap) => volume_cap.measurement(),
+		}
+	}
+
+	fn set_volume_cap(&mut self, cap: f32) {
+		match self {
+			Self::Linked(volume_cap) => volume_cap.set_volume_cap(cap),
+			Self::Unlinked(volume_cap) => volume_cap.set_volume_cap(cap),
+			Self::Multiband(volume_cap) => volume_cap.set_volume_cap(cap),
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use shoosh::processor::VolumeCapSettings;
+
+	use super::Pipeline;
+	use crate::{sample::SampleFormat, Config};
+
+	#[test]
+	fn headroom_ceiling_holds_after_makeup() {
+		// -6 dBFS of headroom over a cap at full scale, then 12 dB of makeup,
+		// configured as the command line does
+		let ceiling = 0.5;
+		let config = Config {
+			makeup_db: Some(12.0),
+			clip: Some(ceiling),
+			limiter: VolumeCapSettings {
+				volume_cap: 1.0,
+				ceiling: Some(ceiling),
+				..Default::default()
+			},
+			..Default::default()
+		};
+		let mut pipeline = Pipeline::new(&config, None, None, None);
+
+		let samples = (0..4096)
+			.map(|i| (i as f32 * 0.05).sin() * if i % 1024 < 256 { 1.0 } else { 0.1 })
+			.collect::<Vec<_>>();
+		let mut input = Vec::new();
+		SampleFormat::F32le.encode_into(&samples, &mut input);
+
+		let mut output = Vec::new();
+		SampleFormat::F32le.decode_into(pipeline.process(&input).unwrap(), &mut output);
+		assert_eq!(samples.len(), output.len());
+		assert!(output.iter().all(|v| v.abs() <= ceiling));
+		// the quiet parts are raised by the makeup gain
+		assert!(output.iter().any(|v| v.abs() > 0.3));
+	}
+
+	#[test]
+	fn bypass_passes_bytes_through() {
+		let config = Config {
+			bypass: true,
+			clip: Some(0.1),
+			limiter: VolumeCapSettings {
+				volume_cap: 0.1,
+				..Default::default()
+			},
+			..Default::default()
+		};
+		let mut pipeline = Pipeline::new(&config, None, None, None);
+
+		let samples = (0..4096)
+			.map(|i| (i as f32 * 0.05).sin())
+			.collect::<Vec<_>>();
+		let mut input = Vec::new();
+		SampleFormat::F32le.encode_into(&samples, &mut input);
+
+		// a partial frame is held back like when processing
+		let (first, rest) = input.split_at(4099);
+		let mut output = pipeline.process(first).unwrap().to_vec();
+		assert_eq!(4096, output.len());
+		output.extend_from_slice(pipeline.process(rest).unwrap());
+		assert_eq!(input, output);
+	}
+}
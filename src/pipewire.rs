@@ -0,0 +1,356 @@
+//! Connection to a PipeWire server through its native API, used with
+//! `--backend pipewire` instead of its pulseaudio compatibility server.
+//!
+//! Both streams run their process callbacks on the main loop, which runs on
+//! the main thread, so nothing needs locking. Each recorded buffer goes
+//! through the same `process_recorded` as a pulseaudio session, into a queue
+//! the playback stream fills its buffers from. A timer on the main loop
+//! watches for shutdown and stream failures.
+//!
+//! The session manager links both streams and moves them when a device goes
+//! away, so there is no reconnecting, and no sidechain, calibration, latency
+//! reporting or input file playback.
+
+use std::{cell::RefCell, collections::VecDeque, io::Cursor, rc::Rc, sync::Arc};
+
+use pulse::{error::PAErr, stream::PeekResult};
+use pw::{
+	context::ContextRc,
+	keys,
+	main_loop::{MainLoopRc, MainLoopWeak},
+	properties::{properties, PropertiesBox},
+	spa::{
+		self,
+		param::{audio::AudioInfoRaw, ParamType},
+		pod::{serialize::PodSerializer, Object, Pod, Value},
+		utils::{Direction, SpaTypes},
+	},
+	stream::{Stream, StreamFlags, StreamRc, StreamState},
+};
+
+use crate::{
+	backend::{Capture, Playback},
+	backlog::Backlog,
+	control::Control,
+	error::ShooshError,
+	pipeline::Pipeline,
+	recorder::Recorder,
+	session::{self, Shared, BACKLOG_LIMIT},
+	shutdown,
+	stats::Stats,
+	Config,
+};
+
+/// Processes audio from the source to the sink until shutdown is requested or
+/// a stream fails.
+///
+/// Everything played back is also written to `recorder`, if given, and
+/// `control` can change the volume cap while running.
+pub fn run(
+	config: &Config,
+	recorder: Option<Shared<Recorder>>,
+	control: Option<Arc<Control>>,
+) -> Result<(), ShooshError> {
+	if let Some(duration) = config.duration {
+		shutdown::set_deadline(duration);
+	}
+
+	pw::init();
+	let mainloop = MainLoopRc::new(None)?;
+	let context = ContextRc::new(&mainloop, None)?;
+	let core = context.connect_rc(
+		config
+			.server
+			.as_deref()
+			.map(|server| properties! { *keys::REMOTE_NAME => server }),
+	)?;
+
+	let recording_stream = StreamRc::new(
+		core.clone(),
+		"Shoosh source",
+		stream_properties(config, "Capture", config.source.as_deref()),
+	)?;
+	let playback_stream = StreamRc::new(
+		core,
+		"Shoosh sink",
+		stream_properties(config, "Playback", config.sink.as_deref()),
+	)?;
+
+	let error = Rc::new(RefCell::new(None));
+	let stats = config
+		.stats
+		.map(|interval| Rc::new(RefCell::new(Stats::new(interval))));
+
+	let output_frame_size = config.format.size() * config.output_channels() as usize;
+	let (playback_buffer, _) = config.buffer_sizes();
+	let queue = Rc::new(RefCell::new(PlaybackQueue::new(playback_buffer as usize)));
+
+	let _recording_listener = {
+		let mut pipeline = Pipeline::new(config, recorder, stats.clone(), control.clone());
+		let limit =
+			(BACKLOG_LIMIT.as_secs_f32() * config.spec.rate as f32) as usize * output_frame_size;
+		let mut backlog = Backlog::new(output_frame_size, limit);
+		let on_hole = config.on_hole;
+		let queue = Rc::clone(&queue);
+		let error = Rc::clone(&error);
+		let read_stats = stats.clone();
+
+		recording_stream
+			.add_local_listener_with_user_data(())
+			.state_changed(log_state("recording", &error))
+			.process(move |stream, _| {
+				let Some(mut buffer) = stream.dequeue_buffer() else {
+					return
+				};
+				let Some(data) = buffer.datas_mut().first_mut() else {
+					return
+				};
+
+				let chunk = data.chunk();
+				let (offset, size) = (chunk.offset() as usize, chunk.size() as usize);
+				let Some(bytes) = data.data() else { return };
+
+				let end = (offset + size).min(bytes.len());
+				let mut recorded = RecordedBuffer(Some(&bytes[offset.min(end)..end]));
+				let result = session::process_recorded(
+					&mut pipeline,
+					&mut backlog,
+					on_hole,
+					&mut recorded,
+					&mut *queue.borrow_mut(),
+				);
+
+				match result {
+					Ok(true) => {}
+					Ok(false) =>
+						if let Some(stats) = &read_stats {
+							stats.borrow_mut().empty_read();
+						},
+					Err(e) => {
+						error.borrow_mut().get_or_insert(e);
+					}
+				}
+			})
+			.register()?
+	};
+
+	let _playback_listener = {
+		let queue = Rc::clone(&queue);
+		let underrun_stats = stats.clone();
+
+		playback_stream
+			.add_local_listener_with_user_data(())
+			.state_changed(log_state("playback", &error))
+			.process(move |stream, _| {
+				let Some(mut buffer) = stream.dequeue_buffer() else {
+					return
+				};
+				let requested = buffer.requested() as usize * output_frame_size;
+				let Some(data) = buffer.datas_mut().first_mut() else {
+					return
+				};
+				let Some(slots) = data.data() else { return };
+
+				// the server only asks for a size when it knows what it needs
+				let size = match requested {
+					0 => slots.len(),
+					requested => requested.min(slots.len()),
+				};
+				let size = size - size % output_frame_size;
+
+				if !queue.borrow_mut().read_into(&mut slots[..size]) {
+					warn!("playback underrun");
+					if let Some(stats) = &underrun_stats {
+						stats.borrow_mut().underrun();
+					}
+					if let Some(control) = &control {
+						control.count_underrun();
+					}
+				}
+
+				let chunk = data.chunk_mut();
+				*chunk.offset_mut() = 0;
+				*chunk.stride_mut() = output_frame_size as i32;
+				*chunk.size_mut() = size as u32;
+			})
+			.register()?
+	};
+
+	let flags = StreamFlags::AUTOCONNECT | StreamFlags::MAP_BUFFERS;
+	let format = format_param(config, "recording", config.spec.channels)?;
+	recording_stream.connect(Direction::Input, None, flags, &mut [
+		Pod::from_bytes(&format).ok_or(ShooshError::StreamCreate("recording"))?
+	])?;
+	let format = format_param(config, "playback", config.output_channels())?;
+	playback_stream.connect(Direction::Output, None, flags, &mut [
+		Pod::from_bytes(&format).ok_or(ShooshError::StreamCreate("playback"))?
+	])?;
+
+	let timer = mainloop.loop_().add_timer({
+		let mainloop = mainloop.downgrade();
+		let error = Rc::clone(&error);
+
+		move |_| {
+			if let Some(stats) = &stats {
+				let mut stats = stats.borrow_mut();
+				stats.wakeup();
+				stats.report_if_due();
+			}
+
+			if shutdown::requested() || error.borrow().is_some() {
+				quit(&mainloop);
+			}
+		}
+	});
+	timer
+		.update_timer(Some(config.idle_sleep), Some(config.idle_sleep))
+		.into_sync_result()
+		.map_err(pw::Error::SpaError)?;
+
+	mainloop.run();
+
+	recording_stream.disconnect()?;
+	playback_stream.disconnect()?;
+
+	match error.borrow_mut().take() {
+		Some(e) => Err(e),
+		None => Ok(()),
+	}
+}
+
+fn quit(mainloop: &MainLoopWeak) {
+	if let Some(mainloop) = mainloop.upgrade() {
+		mainloop.quit();
+	}
+}
+
+/// Returns the properties of a stream in the media `category`, linked to the
+/// node named `target` or the default device if `None`.
+fn stream_properties(config: &Config, category: &str, target: Option<&str>) -> PropertiesBox {
+	let mut properties = properties! {
+		*keys::MEDIA_TYPE => "Audio",
+		*keys::MEDIA_CATEGORY => category,
+		*keys::APP_NAME => "Shoosh",
+	};
+
+	if let Some(ms) = config.latency_ms {
+		let frames = (ms / 1000.0 * config.spec.rate as f32).round().max(1.0) as u32;
+		properties.insert(*keys::NODE_LATENCY, format!("{frames}/{}", config.spec.rate));
+	}
+	if let Some(target) = target {
+		properties.insert(*keys::TARGET_OBJECT, target);
+	}
+
+	properties
+}
+
+/// Returns the format the stream `name` of `channels` channels is connected
+/// with, as a serialized pod.
+fn format_param(config: &Config, name: &'static str, channels: u8) -> Result<Vec<u8>, ShooshError> {
+	let mut info = AudioInfoRaw::new();
+	info.set_format(config.format.pipewire_format());
+	info.set_rate(config.spec.rate);
+	info.set_channels(channels as u32);
+
+	let mut position = [0; spa::param::audio::MAX_CHANNELS];
+	match channels {
+		1 => position[0] = spa::sys::SPA_AUDIO_CHANNEL_MONO,
+		2 => position[..2].copy_from_slice(&[
+			spa::sys::SPA_AUDIO_CHANNEL_FL,
+			spa::sys::SPA_AUDIO_CHANNEL_FR,
+		]),
+		// left for the server to pick
+		_ => {}
+	}
+	info.set_position(position);
+
+	let object = Value::Object(Object {
+		type_: SpaTypes::ObjectParamFormat.as_raw(),
+		id: ParamType::EnumFormat.as_raw(),
+		properties: info.into(),
+	});
+
+	PodSerializer::serialize(Cursor::new(Vec::new()), &object)
+		.map(|(cursor, _)| cursor.into_inner())
+		.map_err(|_| ShooshError::StreamCreate(name))
+}
+
+/// Returns a state callback that logs the state of the stream `name` and
+/// records the error it fails with.
+fn log_state(
+	name: &'static str,
+	error: &Shared<Option<ShooshError>>,
+) -> impl FnMut(&Stream, &mut (), StreamState, StreamState) + 'static {
+	let error = Rc::clone(error);
+
+	move |_, _, _, state| match state {
+		StreamState::Error(message) => {
+			error
+				.borrow_mut()
+				.get_or_insert(ShooshError::PipewireStream(name, message));
+		}
+		state => debug!("{name} stream {state:?}"),
+	}
+}
+
+/// A single recorded buffer, handed to `process_recorded` as a capture that
+/// runs out after it.
+struct RecordedBuffer<'a>(Option<&'a [u8]>);
+
+impl Capture for RecordedBuffer<'_> {
+	fn peek(&mut self) -> Result<PeekResult<'_>, PAErr> {
+		Ok(match self.0 {
+			Some(data) => PeekResult::Data(data),
+			None => PeekResult::Empty,
+		})
+	}
+
+	fn discard(&mut self) -> Result<(), PAErr> {
+		self.0 = None;
+		Ok(())
+	}
+}
+
+/// Processed output waiting for the playback stream to ask for it, up to
+/// `limit` bytes.
+struct PlaybackQueue {
+	data: VecDeque<u8>,
+	limit: usize,
+	started: bool,
+}
+
+impl PlaybackQueue {
+	fn new(limit: usize) -> Self {
+		Self {
+			data: VecDeque::with_capacity(limit),
+			limit,
+			started: false,
+		}
+	}
+
+	/// Moves the oldest queued bytes into `output`, filling the rest with
+	/// silence, and returns false if that ran the queue dry after playback
+	/// started.
+	fn read_into(&mut self, output: &mut [u8]) -> bool {
+		let size = output.len().min(self.data.len());
+		for (slot, byte) in output.iter_mut().zip(self.data.drain(..size)) {
+			*slot = byte;
+		}
+		// zero bytes are silence in every supported format
+		output[size..].fill(0);
+
+		self.started |= size > 0;
+		size == output.len() || !self.started
+	}
+}
+
+impl Playback for PlaybackQueue {
+	fn writable_size(&self) -> Option<usize> {
+		Some(self.limit.saturating_sub(self.data.len()))
+	}
+
+	fn write(&mut self, data: &[u8]) -> Result<(), PAErr> {
+		self.data.extend(data);
+		Ok(())
+	}
+}
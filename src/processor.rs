@@ -0,0 +1,973 @@
+use crate::{
+	detector::Detector,
+	ringbuffer::{RingBuffer, SummedRingBuffer},
+	true_peak::TruePeak,
+	weighting::Weighting,
+};
+
+/// A stage that transforms blocks of interleaved samples.
+pub trait Processor {
+	/// Processes `input` into `output`, which must be the same length.
+	fn process(&mut self, input: &[f32], output: &mut [f32]);
+
+	/// Discards any history, for example after a gap in the input.
+	fn reset(&mut self) {}
+}
+
+/// Settings for a `VolumeCap`.
+#[derive(Debug, Clone, Copy)]
+pub struct VolumeCapSettings {
+	/// Linear volume cap
+	pub volume_cap: f32,
+	/// Linear level no peak is let through above, whatever the volume cap,
+	/// or `None` for no ceiling
+	///
+	/// The volume cap limits the averaged level, so with a cap below the
+	/// ceiling the ceiling never comes into play. Above it, the average may
+	/// reach the cap while peaks are still held to the ceiling. As with the
+	/// cap, gain ramps in over a chunk, so only lookahead keeps every sample
+	/// under it.
+	pub ceiling: Option<f32>,
+	pub detector: Detector,
+	/// Measure the peak of the oversampled signal instead of the detector
+	/// level, catching peaks between samples
+	pub true_peak: bool,
+	pub weighting: Weighting,
+	/// Start the average at the cap, and return it there on `reset`, instead
+	/// of starting empty
+	///
+	/// A level at the cap needs no reduction, so the gain starts at unity and
+	/// only loud input lowers it.
+	pub prefill: bool,
+	/// Width of the soft knee around the cap in dB, 0 for a hard knee
+	pub knee_db: f32,
+	/// Let the gain target exceed unity instead of clamping it
+	pub allow_boost: bool,
+	/// Linear gain the applied gain never falls below, 0 for no floor
+	///
+	/// Input loud enough to need more reduction than this exceeds the cap.
+	pub gain_floor: f32,
+	/// Time for the applied gain to fall toward a lower target
+	pub attack_ms: f32,
+	/// Time for the applied gain to rise toward a higher target
+	pub release_ms: f32,
+	/// Linear level below which the noise gate closes, or `None` for no gate
+	pub gate_threshold: Option<f32>,
+	/// Time for the gate to open once the level rises above the threshold
+	pub gate_attack_ms: f32,
+	/// Time for the gate to close once the level falls below the threshold
+	pub gate_release_ms: f32,
+	/// Delay applied to the audio so gain reduction for a transient is in
+	/// place before the transient is output
+	///
+	/// Any non-zero lookahead is at least one chunk. With no attack time,
+	/// no output sample then exceeds the cap.
+	pub lookahead_ms: f32,
+	/// Number of chunk levels averaged, must be at least 1
+	///
+	/// The window covers `window * chunk_size / (rate * channels)` seconds of
+	/// audio; the defaults are about 93ms of stereo audio at 44.1kHz.
+	pub window: usize,
+	/// Number of interleaved samples the gain target is computed over, must be
+	/// at least 1
+	///
+	/// Should be a multiple of `channels` so every frame gets one gain target.
+	pub chunk_size: usize,
+	/// Sample rate in Hz
+	pub rate: u32,
+	/// Number of interleaved channels
+	pub channels: u8,
+}
+
+impl Default for VolumeCapSettings {
+	fn default() -> Self {
+		Self {
+			volume_cap: 1.0,
+			ceiling: None,
+			detector: Detector::Peak,
+			true_peak: false,
+			weighting: Weighting::Linear,
+			prefill: false,
+			knee_db: 0.0,
+			allow_boost: false,
+			gain_floor: 0.0,
+			attack_ms: 0.0,
+			release_ms: 0.0,
+			gate_threshold: None,
+			gate_attack_ms: 1.0,
+			gate_release_ms: 100.0,
+			lookahead_ms: 0.0,
+			window: 128,
+			chunk_size: 64,
+			rate: 44100,
+			channels: 2,
+		}
+	}
+}
+
+/// Values computed for the most recently processed chunk.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Measurement {
+	/// Detector level of the chunk
+	pub level: f32,
+	/// Weighted average of the volume history
+	pub average: f32,
+	/// Gain applied to the chunk's last sample
+	pub gain: f32,
+	/// True if the chunk's gain target exceeded unity and was clamped
+	pub boost_clamped: bool,
+}
+
+/// Limits the volume of its input to a cap, based on a weighted average of
+/// recent chunk levels.
+#[derive(Debug)]
+pub struct VolumeCap {
+	/// Linear volume cap
+	volume_cap: f32,
+	ceiling: Option<f32>,
+	knee_db: f32,
+	allow_boost: bool,
+	gain_floor: f32,
+	detector: Detector,
+	true_peak: Option<TruePeak>,
+	average: Average,
+	prefill: bool,
+	chunk_size: usize,
+	/// Input not yet output, when looking ahead
+	delay_line: Option<RingBuffer<f32>>,
+	/// Levels of the chunks that are in the delay line or being processed
+	lookahead_levels: Option<RingBuffer<f32>>,
+	/// Gain applied to the last processed sample
+	gain: f32,
+	/// Per-sample smoothing coefficient used while the gain is falling
+	attack_coefficient: f32,
+	/// Per-sample smoothing coefficient used while the gain is rising
+	release_coefficient: f32,
+	gate: Option<Gate>,
+	measurement: Measurement,
+}
+
+/// Silences the output while the level is below a threshold.
+#[derive(Debug)]
+struct Gate {
+	threshold: f32,
+	/// Gain applied by the gate to the last processed sample, 0 when closed
+	gain: f32,
+	/// Per-sample smoothing coefficient used while opening
+	attack_coefficient: f32,
+	/// Per-sample smoothing coefficient used while closing
+	release_coefficient: f32,
+}
+
+impl VolumeCap {
+	/// Create a new volume cap
+	///
+	/// Panics if `settings.window` or `settings.chunk_size` is zero.
+	pub fn new(settings: &VolumeCapSettings) -> Self {
+		assert!(settings.chunk_size != 0, "VolumeCap chunk size must be non-zero");
+
+		// samples are interleaved, so the envelope advances once per sample of each
+		// channel
+		let sample_rate = settings.rate * settings.channels as u32;
+
+		let delay = (settings.lookahead_ms / 1000.0 * settings.rate as f32).round() as usize
+			* settings.channels as usize;
+		let delay = match delay {
+			0 => None,
+			delay => Some(delay.max(settings.chunk_size)),
+		};
+
+		Self {
+			volume_cap: settings.volume_cap,
+			ceiling: settings.ceiling,
+			knee_db: settings.knee_db,
+			allow_boost: settings.allow_boost,
+			gain_floor: settings.gain_floor,
+			detector: settings.detector,
+			true_peak: settings
+				.true_peak
+				.then(|| TruePeak::new(settings.channels as usize)),
+			average: Average::new(
+				settings.weighting,
+				settings.window,
+				settings.prefill.then_some(settings.volume_cap),
+			),
+			prefill: settings.prefill,
+			chunk_size: settings.chunk_size,
+			delay_line: delay.map(RingBuffer::new),
+			lookahead_levels: delay
+				.map(|delay| RingBuffer::new(delay.div_ceil(settings.chunk_size) + 1)),
+			gain: 1.0,
+			attack_coefficient: coeff_from_ms(settings.attack_ms, sample_rate),
+			release_coefficient: coeff_from_ms(settings.release_ms, sample_rate),
+			gate: settings.gate_threshold.map(|threshold| Gate {
+				threshold,
+				gain: 0.0,
+				attack_coefficient: coeff_from_ms(settings.gate_attack_ms, sample_rate),
+				release_coefficient: coeff_from_ms(settings.gate_release_ms, sample_rate),
+			}),
+			measurement: Measurement::default(),
+		}
+	}
+
+	/// Returns the values computed for the most recently processed chunk.
+	pub fn measurement(&self) -> Measurement {
+		self.measurement
+	}
+
+	/// Changes the linear volume cap, taking effect from the next chunk.
+	pub fn set_volume_cap(&mut self, volume_cap: f32) {
+		self.volume_cap = volume_cap;
+	}
+}
+
+impl Processor for VolumeCap {
+	fn process(&mut self, input: &[f32], output: &mut [f32]) {
+		assert_eq!(input.len(), output.len());
+
+		// Chunks are taken over interleaved samples with all channels linked,
+		// so a chunk size that isn't a multiple of the channel count only means
+		// a frame may straddle two chunks with slightly different gain.
+		let chunks = input
+			.chunks(self.chunk_size)
+			.zip(output.chunks_mut(self.chunk_size));
+		for (chunk, output) in chunks {
+			let chunk_level = match &mut self.true_peak {
+				Some(true_peak) => true_peak.level(chunk),
+				None => self.detector.level(chunk),
+			};
+
+			let weighted_average = self.average.update(chunk_level);
+
+			// when looking ahead, the gain must also cover every delayed sample
+			let peak_level = match &mut self.lookahead_levels {
+				Some(levels) => {
+					levels.push(chunk_level);
+					levels.max().unwrap_or(chunk_level)
+				}
+				None => chunk_level,
+			};
+
+			let mut target = gain(weighted_average.max(peak_level), self.volume_cap, self.knee_db);
+			if let Some(ceiling) = self.ceiling {
+				target = target.min(gain(peak_level, ceiling, 0.0));
+			}
+			let volume_multiplier = clamp_gain(target, self.allow_boost);
+			let gate_open = self
+				.gate
+				.as_ref()
+				.is_some_and(|gate| peak_level >= gate.threshold);
+
+			// Ramp linearly from the gain at the end of the last chunk to this chunk's
+			// target so the gain never steps at chunk boundaries, then move the
+			// applied gain toward the ramp one sample at a time.
+			let ramp_start = self.gain;
+			for (i, (&sample, output)) in chunk.iter().zip(output).enumerate() {
+				let ramp = ramp_start
+					+ (volume_multiplier - ramp_start) * (i + 1) as f32 / chunk.len() as f32;
+
+				let coefficient = if ramp < self.gain {
+					self.attack_coefficient
+				} else {
+					self.release_coefficient
+				};
+
+				self.gain = (ramp + (self.gain - ramp) * coefficient).max(self.gain_floor);
+
+				let sample = match &mut self.delay_line {
+					Some(delay_line) => {
+						// silence until the delay line first fills
+						let delayed = if delay_line.is_full() {
+							delay_line[0]
+						} else {
+							0.0
+						};
+						delay_line.push(sample);
+						delayed
+					}
+					None => sample,
+				};
+
+				let gate_gain = match &mut self.gate {
+					Some(gate) => {
+						let (target, coefficient) = match gate_open {
+							true => (1.0, gate.attack_coefficient),
+							false => (0.0, gate.release_coefficient),
+						};
+
+						gate.gain = target + (gate.gain - target) * coefficient;
+						gate.gain
+					}
+					None => 1.0,
+				};
+
+				*output = sample * self.gain * gate_gain;
+			}
+
+			self.measurement = Measurement {
+				level: chunk_level,
+				average: weighted_average,
+				gain: self.gain * self.gate.as_ref().map_or(1.0, |gate| gate.gain),
+				boost_clamped: volume_multiplier < target,
+			};
+		}
+	}
+
+	fn reset(&mut self) {
+		self.average.reset(self.prefill.then_some(self.volume_cap));
+		if let Some(true_peak) = &mut self.true_peak {
+			true_peak.reset();
+		}
+		if let Some(delay_line) = &mut self.delay_line {
+			delay_line.clear();
+		}
+		if let Some(levels) = &mut self.lookahead_levels {
+			levels.clear();
+		}
+		if let Some(gate) = &mut self.gate {
+			gate.gain = 0.0;
+		}
+		self.gain = 1.0;
+	}
+}
+
+/// Returns the gain that limits `level` to `cap`, both linear.
+///
+/// With a `knee_db` of 0 the gain is `cap / level` once `level` exceeds `cap`.
+/// Otherwise gain reduction eases in over a knee of that width centred on the
+/// cap, starting `knee_db / 2` below it. The output never exceeds the cap.
+pub fn gain(level: f32, cap: f32, knee_db: f32) -> f32 {
+	if knee_db <= 0.0 {
+		return cap / level.max(cap)
+	}
+
+	if level <= 0.0 {
+		return 1.0
+	}
+
+	let level_db = 20.0 * level.log10();
+	let cap_db = 20.0 * cap.log10();
+	let over = level_db - cap_db + knee_db / 2.0;
+
+	let gain_db = if over <= 0.0 {
+		0.0
+	} else if over < knee_db {
+		-over * over / (2.0 * knee_db)
+	} else {
+		cap_db - level_db
+	};
+
+	10.0f32.powf(gain_db / 20.0)
+}
+
+/// Clamps a gain target to unity unless `allow_boost` is set.
+///
+/// `gain` never exceeds unity, so this only enforces the invariant against a
+/// computation that overshoots it; any makeup gain is applied separately.
+pub fn clamp_gain(gain: f32, allow_boost: bool) -> f32 {
+	if allow_boost {
+		gain
+	} else {
+		gain.min(1.0)
+	}
+}
+
+/// Average of the recent chunk levels.
+#[derive(Debug)]
+enum Average {
+	/// Weighted average of a window of levels
+	Window {
+		weights: WindowWeights,
+		history: RingBuffer<f32>,
+	},
+	/// Unweighted mean of a window of levels, from a running sum instead of a
+	/// pass over the window
+	Flat { history: SummedRingBuffer },
+	/// Exponential moving average, `None` until the first level
+	Ema { alpha: f32, average: Option<f32> },
+}
+
+impl Average {
+	/// Creates an average starting at `prefill`, or empty if `None`.
+	fn new(weighting: Weighting, window: usize, prefill: Option<f32>) -> Self {
+		match weighting {
+			Weighting::Ema => Self::Ema {
+				alpha: 1.0 - Weighting::ema_decay(window),
+				average: prefill,
+			},
+			Weighting::Flat => Self::Flat {
+				history: match prefill {
+					Some(level) => SummedRingBuffer::filled(window, level),
+					None => SummedRingBuffer::new(window),
+				},
+			},
+			weighting => Self::Window {
+				weights: WindowWeights::new(weighting, window),
+				history: match prefill {
+					Some(level) => RingBuffer::filled(window, level),
+					None => RingBuffer::new(window),
+				},
+			},
+		}
+	}
+
+	/// Adds a chunk level, returning the new average.
+	fn update(&mut self, level: f32) -> f32 {
+		match self {
+			Self::Window { weights, history } => {
+				history.push(level);
+				weights.average(history)
+			}
+			Self::Flat { history } => {
+				history.push(level);
+				history.mean().unwrap_or(level)
+			}
+			Self::Ema { alpha, average } => {
+				// starting from the first level avoids a warm-up bias toward 0
+				let updated = match *average {
+					Some(average) => *alpha * level + (1.0 - *alpha) * average,
+					None => level,
+				};
+				*average = Some(updated);
+				updated
+			}
+		}
+	}
+
+	/// Discards the history, starting again from `prefill` like `new`.
+	fn reset(&mut self, prefill: Option<f32>) {
+		match (self, prefill) {
+			(Self::Window { history, .. }, Some(level)) => history.fill(level),
+			(Self::Window { history, .. }, None) => history.clear(),
+			(Self::Flat { history }, Some(level)) => history.fill(level),
+			(Self::Flat { history }, None) => history.clear(),
+			(Self::Ema { average, .. }, prefill) => *average = prefill,
+		}
+	}
+}
+
+/// Precomputed weights for averaging a volume history.
+#[derive(Debug)]
+struct WindowWeights {
+	/// Normalized weight of each entry of a full window, oldest first
+	weights: Vec<f32>,
+	/// Sum of the newest `i` weights at index `i`
+	newest_sums: Vec<f32>,
+}
+
+impl WindowWeights {
+	fn new(weighting: Weighting, window: usize) -> Self {
+		let weights = weighting.table(window);
+		let newest_sums = [0.0]
+			.into_iter()
+			.chain(weights.iter().rev().scan(0.0, |sum, weight| {
+				*sum += weight;
+				Some(*sum)
+			}))
+			.collect();
+
+		Self {
+			weights,
+			newest_sums,
+		}
+	}
+
+	/// Returns the weighted average of `history`, the newest entry taking the
+	/// newest weight.
+	///
+	/// Until the history fills, only the newest weights are used and the result
+	/// is scaled up to make up for the missing ones, so a constant input
+	/// averages the same during warm-up as once full.
+	///
+	/// Runs once per chunk, so it walks the two contiguous halves of the ring
+	/// directly instead of going through its chained iterator.
+	fn average(&self, history: &RingBuffer<f32>) -> f32 {
+		let len = history.len();
+		if len == 0 {
+			return 0.0
+		}
+
+		let (older, newer) = history.as_slices();
+		let weights = &self.weights[self.weights.len() - len..];
+		let (older_weights, newer_weights) = weights.split_at(older.len());
+		let sum = dot(older, older_weights) + dot(newer, newer_weights);
+
+		if len == self.weights.len() {
+			sum
+		} else {
+			sum * self.newest_sums[self.weights.len()] / self.newest_sums[len]
+		}
+	}
+}
+
+fn dot(a: &[f32], b: &[f32]) -> f32 {
+	a.iter().zip(b).map(|(a, b)| a * b).sum()
+}
+
+/// Returns the one-pole smoothing coefficient reaching ~63% of a step after
+/// `ms` when applied `rate` times a second, or 0 (no smoothing) when `ms` is 0.
+///
+/// Deriving it from the runtime rate keeps a time constant the same length at
+/// any sample rate.
+pub(crate) fn coeff_from_ms(ms: f32, rate: u32) -> f32 {
+	if ms <= 0.0 {
+		0.0
+	} else {
+		(-1.0 / (ms / 1000.0 * rate as f32)).exp()
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::{
+		clamp_gain,
+		coeff_from_ms,
+		gain,
+		Average,
+		Processor,
+		VolumeCap,
+		VolumeCapSettings,
+		WindowWeights,
+	};
+	use crate::{ringbuffer::RingBuffer, test_support::process, weighting::Weighting};
+
+	fn settings() -> VolumeCapSettings {
+		VolumeCapSettings {
+			volume_cap: 0.5,
+			..Default::default()
+		}
+	}
+
+	#[test]
+	fn quiet_input_passes_at_unity() {
+		let mut volume_cap = VolumeCap::new(&settings());
+
+		let input = [0.25, -0.25].repeat(64 * 8);
+		assert_eq!(input, process(&mut volume_cap, &input));
+	}
+
+	#[test]
+	fn loud_input_is_capped() {
+		let mut volume_cap = VolumeCap::new(&settings());
+
+		let input = [1.0, -1.0].repeat(64 * 256);
+		let output = process(&mut volume_cap, &input);
+
+		// once ramped in, the output settles at the cap
+		for sample in &output[64..] {
+			assert!((sample.abs() - 0.5).abs() < 1e-6, "sample {sample}");
+		}
+	}
+
+	#[test]
+	fn single_chunk_window() {
+		let mut volume_cap = VolumeCap::new(&VolumeCapSettings {
+			window: 1,
+			..settings()
+		});
+
+		let input = [1.0, -1.0].repeat(64 * 8);
+		let output = process(&mut volume_cap, &input);
+		assert!((output.last().unwrap().abs() - 0.5).abs() < 1e-6);
+	}
+
+	#[test]
+	fn chunk_sizes_preserve_length() {
+		let input = [1.0, -0.5].repeat(1000);
+
+		for chunk_size in [1, 2, 3, 64, 100, 4096] {
+			let mut volume_cap = VolumeCap::new(&VolumeCapSettings {
+				chunk_size,
+				..settings()
+			});
+
+			let output = process(&mut volume_cap, &input);
+			assert_eq!(input.len(), output.len(), "chunk size {chunk_size}");
+			assert!(output.iter().all(|v| v.abs() <= 1.0));
+		}
+	}
+
+	#[test]
+	fn lookahead_catches_clicks() {
+		let mut volume_cap = VolumeCap::new(&VolumeCapSettings {
+			lookahead_ms: 2.0,
+			..settings()
+		});
+
+		// clicks at varying offsets into the chunks, separated by long silences
+		let mut input = vec![0.0; 64 * 512];
+		for (i, offset) in [0, 1, 31, 63, 64, 127].iter().enumerate() {
+			input[(i + 1) * 4096 + offset] = 1.0;
+		}
+
+		let output = process(&mut volume_cap, &input);
+		assert!(output.iter().all(|v| v.abs() <= 0.5 + 1e-6));
+
+		// the clicks are delayed, not dropped
+		let delay = 88 * 2;
+		assert!((output[4096 + delay].abs() - 0.5).abs() < 1e-6);
+	}
+
+	#[test]
+	fn prefill_starts_at_unity() {
+		for weighting in [Weighting::Linear, Weighting::Ema] {
+			let mut volume_cap = VolumeCap::new(&VolumeCapSettings {
+				prefill: true,
+				weighting,
+				..settings()
+			});
+
+			// the first chunk passes at unity, and so does the first after a gap
+			let chunk = [0.45, -0.45].repeat(32);
+			assert_eq!(chunk, process(&mut volume_cap, &chunk), "{weighting:?}");
+			assert_eq!(1.0, volume_cap.measurement().gain);
+			assert!((volume_cap.measurement().average - 0.5).abs() < 0.01);
+
+			volume_cap.reset();
+			let quiet = [0.1, -0.1].repeat(32);
+			assert_eq!(quiet, process(&mut volume_cap, &quiet), "{weighting:?}");
+			assert!(volume_cap.measurement().average > 0.45, "{weighting:?}");
+
+			// loud input is still capped from its first chunk on
+			let loud = [1.0, -1.0].repeat(64);
+			let output = process(&mut volume_cap, &loud);
+			assert!((output.last().unwrap().abs() - 0.5).abs() < 1e-6);
+		}
+	}
+
+	#[test]
+	fn prefill_avoids_startup_dip() {
+		let loud = [1.0, -1.0].repeat(32);
+		let chunk = [0.45, -0.45].repeat(32);
+
+		// a loud first chunk followed by material just under the cap
+		let gain_after = |prefill| {
+			let mut volume_cap = VolumeCap::new(&VolumeCapSettings {
+				prefill,
+				..settings()
+			});
+			process(&mut volume_cap, &loud);
+			process(&mut volume_cap, &chunk);
+			volume_cap.measurement().gain
+		};
+
+		// an empty average is dominated by the first chunk, a prefilled one
+		// barely moves
+		assert!(gain_after(false) < 0.75, "{}", gain_after(false));
+		assert!(gain_after(true) > 0.95, "{}", gain_after(true));
+	}
+
+	#[test]
+	fn ceiling_holds_peaks() {
+		// a cap at full scale with peaks kept 6 dB below it
+		let mut volume_cap = VolumeCap::new(&VolumeCapSettings {
+			volume_cap: 1.0,
+			ceiling: Some(0.5),
+			lookahead_ms: 2.0,
+			..settings()
+		});
+
+		// a quiet tone with loud bursts, then a sustained loud tone
+		let input = (0..64 * 1024)
+			.map(|i| {
+				let level = match i / 4096 {
+					3 | 7 | 8 => 1.5,
+					_ if i >= 64 * 768 => 0.9,
+					_ => 0.2,
+				};
+				(i as f32 * 0.07).sin() * level
+			})
+			.collect::<Vec<f32>>();
+		let output = process(&mut volume_cap, &input);
+
+		assert!(output.iter().all(|v| v.abs() <= 0.5 + 1e-6));
+		// quiet passages are untouched, only delayed
+		let delay = 88 * 2;
+		assert_eq!(input[1000], output[1000 + delay]);
+	}
+
+	#[test]
+	fn hard_knee() {
+		assert_eq!(1.0, gain(0.25, 0.5, 0.0));
+		assert_eq!(1.0, gain(0.5, 0.5, 0.0));
+		assert_eq!(0.5, gain(1.0, 0.5, 0.0));
+	}
+
+	#[test]
+	fn clamps_boost() {
+		assert_eq!(1.0, clamp_gain(1.5, false));
+		assert_eq!(1.5, clamp_gain(1.5, true));
+		assert_eq!(0.5, clamp_gain(0.5, false));
+
+		// the gain never needs clamping, even with a cap over full scale
+		for knee_db in [0.0, 6.0] {
+			for i in 0..=100 {
+				let level = i as f32 / 50.0;
+				assert!(gain(level, 1.5, knee_db) <= 1.0, "boost at {level}");
+			}
+		}
+	}
+
+	#[test]
+	fn gain_floor() {
+		let floor = 0.1;
+		let mut volume_cap = VolumeCap::new(&VolumeCapSettings {
+			gain_floor: floor,
+			release_ms: 50.0,
+			..settings()
+		});
+
+		// far louder than any real input, then back down to below the cap
+		let input = [1000.0, 5.0, 0.25]
+			.iter()
+			.flat_map(|&level| [level, -level].repeat(64 * 64))
+			.collect::<Vec<f32>>();
+		let output = process(&mut volume_cap, &input);
+
+		for (i, (input, output)) in input.iter().zip(&output).enumerate() {
+			let gain = output / input;
+			assert!(gain >= floor, "sample {i}: gain {gain}");
+		}
+
+		// the floor is reached, so the loudest input is let through above the cap
+		assert!((output[64 * 64].abs() - 100.0).abs() < 1e-3);
+	}
+
+	#[test]
+	fn soft_knee() {
+		let cap = 0.5;
+		let knee_db = 6.0;
+
+		// well below and above the knee it matches the hard knee
+		assert_eq!(1.0, gain(0.1, cap, knee_db));
+		assert!((gain(2.0, cap, knee_db) - 0.25).abs() < 1e-5);
+
+		// sweep through the knee in small steps
+		let mut previous_gain = 1.0;
+		let mut previous_output = 0.0;
+		for i in 0..=600 {
+			let level = 10.0f32.powf((i as f32 / 100.0 - 12.0) / 20.0) * cap * 2.0;
+			let gain = gain(level, cap, knee_db);
+
+			assert!(gain <= previous_gain, "gain rises at {level}");
+			assert!(previous_gain - gain < 0.01, "gain jumps at {level}");
+			assert!(level * gain >= previous_output - 1e-6, "output falls at {level}");
+			assert!(level * gain <= cap + 1e-6, "output exceeds cap at {level}");
+
+			previous_gain = gain;
+			previous_output = level * gain;
+		}
+	}
+
+	#[test]
+	fn gate() {
+		let mut volume_cap = VolumeCap::new(&VolumeCapSettings {
+			// -40 dB
+			gate_threshold: Some(0.01),
+			..settings()
+		});
+
+		// -60 dB hiss, then a -12 dB tone, then hiss again
+		let hiss = [0.001, -0.001].repeat(64 * 256);
+		let tone = [0.25, -0.25].repeat(64 * 64);
+
+		let output = process(&mut volume_cap, &hiss);
+		assert!(output[output.len() / 2..].iter().all(|v| v.abs() < 1e-6));
+
+		let output = process(&mut volume_cap, &tone);
+		assert!(output[output.len() / 2..]
+			.iter()
+			.all(|v| (v.abs() - 0.25).abs() < 1e-4));
+
+		// closing takes a few release times
+		let output = process(&mut volume_cap, &hiss.repeat(4));
+		assert!(output[output.len() / 2..].iter().all(|v| v.abs() < 1e-6));
+	}
+
+	#[test]
+	fn gain_ramps_across_chunks() {
+		let mut volume_cap = VolumeCap::new(&settings());
+
+		// a constant tone that gets louder, then quieter
+		let input = [1.0, 2.0, 0.25]
+			.iter()
+			.flat_map(|&level| [level; 64 * 4])
+			.collect::<Vec<f32>>();
+		let output = process(&mut volume_cap, &input);
+
+		let gains = input
+			.iter()
+			.zip(&output)
+			.map(|(input, output)| output / input)
+			.collect::<Vec<_>>();
+
+		// without ramping the gain would step by up to 0.5 at chunk boundaries
+		let largest_step = gains
+			.windows(2)
+			.map(|pair| (pair[1] - pair[0]).abs())
+			.fold(0.0, f32::max);
+		assert!(largest_step < 0.5 / 32.0, "largest gain step {largest_step}");
+	}
+
+	/// The closure based fold the weight table replaced.
+	fn reference_average(history: &RingBuffer<f32>, weighting: Weighting) -> f32 {
+		let window = history.size();
+		history
+			.iter()
+			.enumerate()
+			.map(|(i, v)| v * weighting.weight(i, window))
+			.sum::<f32>()
+			/ weighting.normalization(window)
+	}
+
+	#[test]
+	fn warm_up_average() {
+		for weighting in [
+			Weighting::Linear,
+			Weighting::Exponential { decay: 0.9 },
+			Weighting::Flat,
+		] {
+			let weights = WindowWeights::new(weighting, 16);
+			let mut history = RingBuffer::new(16);
+			history.extend([0.5; 16]);
+			let steady = weights.average(&history);
+
+			history.clear();
+			for len in 1..=16 {
+				history.push(0.5);
+				let average = weights.average(&history);
+				assert!((average - steady).abs() < 1e-6, "{weighting:?} at {len}: {average}");
+			}
+		}
+	}
+
+	#[test]
+	fn warm_up_matches_steady_state() {
+		let mut volume_cap = VolumeCap::new(&settings());
+		let chunk = [0.8, -0.8].repeat(32);
+
+		let mut measurements = Vec::new();
+		for _ in 0..256 {
+			process(&mut volume_cap, &chunk);
+			measurements.push(volume_cap.measurement());
+		}
+
+		// from the very first chunk, a constant input measures as when warmed up
+		let steady = *measurements.last().unwrap();
+		for (i, measurement) in measurements.iter().enumerate() {
+			assert!(
+				(measurement.average - steady.average).abs() < 1e-5,
+				"chunk {i}: {measurement:?}"
+			);
+			assert!((measurement.gain - steady.gain).abs() < 1e-5, "chunk {i}: {measurement:?}");
+		}
+	}
+
+	#[test]
+	fn coefficient_follows_rate() {
+		assert_eq!(0.0, coeff_from_ms(0.0, 44100));
+		assert!((coeff_from_ms(5.0, 44100) - 0.995475).abs() < 1e-6);
+		assert!((coeff_from_ms(5.0, 48000) - 0.995842).abs() < 1e-6);
+
+		// a step reaches ~63% after the same time at either rate
+		for rate in [44100, 48000, 96000] {
+			let steps = (0.005 * rate as f32) as i32;
+			let remaining = coeff_from_ms(5.0, rate).powi(steps);
+			assert!((remaining - (-1.0f32).exp()).abs() < 1e-3, "{remaining} at {rate}");
+		}
+	}
+
+	#[test]
+	fn constant_input_averages_to_its_level() {
+		for weighting in [
+			Weighting::Linear,
+			Weighting::Exponential { decay: 0.9 },
+			Weighting::Flat,
+			Weighting::Ema,
+		] {
+			for window in [1, 16] {
+				let mut volume_cap = VolumeCap::new(&VolumeCapSettings {
+					weighting,
+					window,
+					..settings()
+				});
+				let chunk = [0.3, -0.3].repeat(32);
+
+				// through warm-up and well past the window filling
+				for i in 0..window * 3 {
+					process(&mut volume_cap, &chunk);
+					let average = volume_cap.measurement().average;
+					assert!(
+						(average - 0.3).abs() < 1e-5,
+						"{weighting:?}, window {window}, chunk {i}: {average}"
+					);
+				}
+			}
+		}
+	}
+
+	#[test]
+	fn ema_matches_long_exponential_window() {
+		// the same decay over a window long enough that the cut off weight is
+		// negligible
+		let decay = Weighting::ema_decay(16);
+		let mut ema = VolumeCap::new(&VolumeCapSettings {
+			weighting: Weighting::Ema,
+			window: 16,
+			..settings()
+		});
+		let mut window = VolumeCap::new(&VolumeCapSettings {
+			weighting: Weighting::Exponential { decay },
+			window: 16 * 16,
+			..settings()
+		});
+
+		let input = [0.4, 0.2, 1.0, 0.6, 0.1]
+			.iter()
+			.flat_map(|&level| [level, -level].repeat(32 * 256))
+			.collect::<Vec<f32>>();
+
+		let ema_output = process(&mut ema, &input);
+		let window_output = process(&mut window, &input);
+		// warm-up corrections differ, so compare once the window is full
+		let full = 64 * 16 * 16;
+		let outputs = ema_output.iter().zip(&window_output).enumerate();
+		for (i, (a, b)) in outputs.skip(full) {
+			assert!((a - b).abs() < 1e-3, "sample {i}: {a} != {b}");
+		}
+	}
+
+	#[test]
+	fn weighted_average_matches_fold() {
+		let weighting = Weighting::Exponential { decay: 0.97 };
+		let weights = WindowWeights::new(weighting, 128);
+		let mut history = RingBuffer::new(128);
+		history.extend((0..128).map(|i| i as f32 / 128.0));
+
+		// wrapped at several offsets
+		for i in 0..300 {
+			history.push((i % 17) as f32 / 17.0);
+
+			let average = weights.average(&history);
+			let expected = reference_average(&history, weighting);
+			assert!((average - expected).abs() < 1e-5, "after {i}: {average} != {expected}");
+		}
+	}
+
+	#[test]
+	fn flat_average_matches_window_weights() {
+		let window = WindowWeights::new(Weighting::Flat, 16);
+		let mut history = RingBuffer::new(16);
+		let mut flat = Average::new(Weighting::Flat, 16, None);
+
+		// through warm-up and wrapped at several offsets
+		for i in 0..50 {
+			let level = (i % 7) as f32 / 7.0;
+			history.push(level);
+
+			let average = flat.update(level);
+			let expected = window.average(&history);
+			assert!((average - expected).abs() < 1e-6, "after {i}: {average} != {expected}");
+		}
+	}
+}
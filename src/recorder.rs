@@ -0,0 +1,99 @@
+//! Copies of the processed audio, exactly as played back.
+
+use std::{
+	fs::File,
+	io::{self, BufWriter, Write},
+};
+
+use crate::{wav::WavWriter, Config};
+
+/// Writes everything played back to the files given with `--record` and
+/// `--raw-out`.
+pub struct Recorder {
+	wav: Option<WavWriter>,
+	/// Encoded samples with no header, for byte-level comparisons
+	raw: Option<BufWriter<File>>,
+}
+
+impl Recorder {
+	/// Creates the files given in `config`, or returns `None` if there are
+	/// none.
+	pub fn create(config: &Config) -> io::Result<Option<Self>> {
+		let wav = match &config.record {
+			Some(path) => Some(WavWriter::create(
+				path,
+				config.format,
+				config.spec.rate,
+				config.output_channels(),
+			)?),
+			None => None,
+		};
+
+		let raw = match &config.raw_out {
+			Some(path) => Some(BufWriter::new(File::create(path)?)),
+			None => None,
+		};
+
+		if wav.is_none() && raw.is_none() {
+			return Ok(None)
+		}
+
+		Ok(Some(Self { wav, raw }))
+	}
+
+	/// Appends encoded sample data to every file.
+	pub fn write(&mut self, data: &[u8]) -> io::Result<()> {
+		if let Some(wav) = &mut self.wav {
+			wav.write(data)?;
+		}
+		if let Some(raw) = &mut self.raw {
+			raw.write_all(data)?;
+		}
+
+		Ok(())
+	}
+
+	/// Finalizes the WAV header and flushes every file.
+	pub fn finalize(&mut self) -> io::Result<()> {
+		if let Some(wav) = &mut self.wav {
+			wav.finalize()?;
+		}
+		if let Some(raw) = &mut self.raw {
+			raw.flush()?;
+		}
+
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use std::{env, fs, io::BufWriter, process};
+
+	use super::Recorder;
+	use crate::{sample::SampleFormat, wav::WavWriter};
+
+	#[test]
+	fn raw_matches_wav_data() {
+		let wav_path = env::temp_dir().join(format!("shoosh-test-recorder-{}.wav", process::id()));
+		let raw_path = wav_path.with_extension("raw");
+		let data = (0..=255).collect::<Vec<u8>>();
+
+		let mut recorder = Recorder {
+			wav: Some(WavWriter::create(&wav_path, SampleFormat::F32le, 44100, 2).unwrap()),
+			raw: Some(BufWriter::new(fs::File::create(&raw_path).unwrap())),
+		};
+		recorder.write(&data[..100]).unwrap();
+		recorder.write(&data[100..]).unwrap();
+		recorder.finalize().unwrap();
+		drop(recorder);
+
+		let wav = fs::read(&wav_path).unwrap();
+		let raw = fs::read(&raw_path).unwrap();
+		fs::remove_file(&wav_path).unwrap();
+		fs::remove_file(&raw_path).unwrap();
+
+		assert_eq!(data, raw);
+		assert_eq!(&wav[44..], raw.as_slice());
+	}
+}
@@ -1,35 +1,260 @@
+use alloc::{boxed::Box, sync::Arc, vec::Vec};
+use core::{
+	cell::UnsafeCell,
+	fmt,
+	iter::FusedIterator,
+	mem::{self, MaybeUninit},
+	ops::{Deref, Index},
+	slice,
+	sync::atomic::{AtomicUsize, Ordering},
+};
+
 /// A fixed length buffer that reuses old element memory to insert new elements.
 ///
-/// Only contains functionality required for shoosh.
-#[derive(Debug)]
+/// Iterating over a reference yields elements from oldest to newest:
+///
+/// ```
+/// use shoosh::ringbuffer::RingBuffer;
+///
+/// let mut buffer = RingBuffer::new(3);
+/// buffer.append(&[1, 2, 3, 4]);
+///
+/// let mut elements = Vec::new();
+/// for element in &buffer {
+///     elements.push(*element);
+/// }
+///
+/// assert_eq!(vec![2, 3, 4], elements);
+/// ```
 pub struct RingBuffer<T: Clone> {
 	buffer: Vec<T>,
 	size: usize,
 	index: usize,
+	/// Called with each stored element right before it is overwritten
+	on_evict: Option<EvictCallback<T>>,
 }
 
+type EvictCallback<T> = Box<dyn FnMut(&T) + Send>;
+
 impl<T: Clone> RingBuffer<T> {
 	/// Create a new ring buffer
+	///
+	/// Panics if `size` is zero.
 	pub fn new(size: usize) -> Self {
+		assert!(size != 0, "RingBuffer size must be non-zero");
+
 		Self {
 			buffer: Vec::with_capacity(size),
 			size,
 			index: 0,
+			on_evict: None,
+		}
+	}
+
+	/// Create a new ring buffer that is already full of `value`
+	///
+	/// Panics if `size` is zero.
+	pub fn filled(size: usize, value: T) -> Self {
+		let mut buffer = Self::new(size);
+		buffer.fill(value);
+		buffer
+	}
+
+	/// Sets a callback run with each stored element, oldest first, right
+	/// before `push` or `append` overwrites it, replacing any earlier one.
+	///
+	/// Appending more elements than fit only stores the newest, so the
+	/// skipped ones are never evicted.
+	///
+	/// Elements removed by `clear` or `drain` aren't evicted. Clones of the
+	/// ring buffer don't share the callback.
+	///
+	/// The callback is only required to be `Send`, so the ring buffer can
+	/// still be moved to another thread.
+	pub fn set_on_evict(&mut self, on_evict: impl FnMut(&T) + Send + 'static) {
+		self.on_evict = Some(Box::new(on_evict));
+	}
+
+	/// Runs the evict callback on the oldest `count` stored elements.
+	fn evict(&mut self, count: usize) {
+		if let Some(on_evict) = &mut self.on_evict {
+			let (older, newer) = if self.buffer.len() == self.size {
+				(&self.buffer[self.index..], &self.buffer[..self.index])
+			} else {
+				(&self.buffer[..], &[][..])
+			};
+
+			older.iter().chain(newer).take(count).for_each(on_evict);
 		}
 	}
 
+	/// Returns the number of elements currently stored.
+	///
+	/// This is less than the ring size until the ring first fills.
+	pub fn len(&self) -> usize {
+		self.buffer.len()
+	}
+
+	/// Returns true if the ring buffer contains no elements.
+	pub fn is_empty(&self) -> bool {
+		self.buffer.is_empty()
+	}
+
+	/// Returns the number of elements the ring holds once full.
+	pub fn size(&self) -> usize {
+		self.size
+	}
+
+	/// Returns true once the ring buffer has been filled to its size.
+	pub fn is_full(&self) -> bool {
+		self.buffer.len() == self.size
+	}
+
+	/// Removes all elements from the ring buffer, keeping its allocation.
+	pub fn clear(&mut self) {
+		self.index = 0;
+		self.buffer.clear();
+	}
+
+	/// Replaces every slot with `value`, leaving the ring buffer full. The
+	/// replaced elements aren't evicted.
+	pub fn fill(&mut self, value: T) {
+		self.index = 0;
+		self.buffer.clear();
+		self.buffer.resize(self.size, value);
+	}
+
+	/// Returns the element at a logical index, where 0 is the oldest element,
+	/// or `None` if fewer than `index + 1` elements are stored.
+	pub fn get(&self, index: usize) -> Option<&T> {
+		(index < self.buffer.len()).then(|| &self.buffer[self.physical_index(index)])
+	}
+
 	/// Returns an iterator over all ring buffer elements.
-	pub fn iter(&self) -> impl Iterator<Item = &T> {
-		self.buffer[self.index..]
-			.iter()
-			.chain(&self.buffer[..self.index])
+	pub fn iter(&self) -> RingBufferIter<'_, T> {
+		let (older, newer) = self.as_slices();
+		RingBufferIter::new(older, newer)
+	}
+
+	/// Returns a copy of the stored elements, oldest first.
+	pub fn to_vec(&self) -> Vec<T> {
+		let (older, newer) = self.as_slices();
+		[older, newer].concat()
+	}
+
+	/// Removes and returns the stored elements, oldest first, leaving the ring
+	/// buffer empty.
+	pub fn drain(&mut self) -> Vec<T> {
+		if self.is_full() {
+			self.buffer.rotate_left(self.index);
+		}
+
+		self.index = 0;
+		mem::replace(&mut self.buffer, Vec::with_capacity(self.size))
+	}
+
+	/// Changes the number of elements the ring holds, keeping the newest
+	/// elements that still fit in order. Shrinking drops the oldest elements
+	/// without evicting them.
+	///
+	/// Panics if `size` is zero.
+	pub fn resize(&mut self, size: usize) {
+		assert!(size != 0, "RingBuffer size must be non-zero");
+
+		let mut elements = self.drain();
+		let dropped = elements.len().saturating_sub(size);
+		elements.drain(..dropped);
+		elements.reserve_exact(size - elements.len());
+
+		self.index = elements.len() % size;
+		self.buffer = elements;
+		self.size = size;
+	}
+
+	/// Returns an iterator over the newest `n` elements, oldest first.
+	///
+	/// Yields every element if `n` is larger than the number stored.
+	pub fn peek_recent(&self, n: usize) -> RingBufferIter<'_, T> {
+		let (older, newer) = self.as_slices();
+		let newer = &newer[newer.len().saturating_sub(n)..];
+		let older = &older[older.len().saturating_sub(n - newer.len())..];
+		RingBufferIter::new(older, newer)
+	}
+
+	/// Returns the stored elements as two contiguous slices, oldest first.
+	///
+	/// The second slice is empty until the ring first wraps.
+	pub fn as_slices(&self) -> (&[T], &[T]) {
+		if self.is_full() {
+			(&self.buffer[self.index..], &self.buffer[..self.index])
+		} else {
+			(&self.buffer, &[])
+		}
+	}
+
+	/// Returns an iterator over all ring buffer elements that allows modifying
+	/// each value.
+	pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut T> {
+		let (head, tail) = self.buffer.split_at_mut(self.index);
+		tail.iter_mut().chain(head)
+	}
+
+	/// Returns the largest stored element, or `None` if empty.
+	///
+	/// Elements that are not comparable (such as NaN) are skipped.
+	pub fn max(&self) -> Option<T>
+	where
+		T: PartialOrd,
+	{
+		self.iter()
+			.filter(|element| element.partial_cmp(element).is_some())
+			.fold(None, |max: Option<&T>, element| match max {
+				Some(max) if max >= element => Some(max),
+				_ => Some(element),
+			})
+			.cloned()
+	}
+
+	/// Returns true if any stored element equals `x`.
+	pub fn contains(&self, x: &T) -> bool
+	where
+		T: PartialEq,
+	{
+		self.iter().any(|element| element == x)
+	}
+
+	/// Returns the logical index of the oldest element matching `predicate`,
+	/// where 0 is the oldest element, usable with `get`.
+	pub fn position<P: FnMut(&T) -> bool>(&self, predicate: P) -> Option<usize> {
+		self.iter().position(predicate)
+	}
+
+	/// Pushes a single value into the ring buffer, overwriting the oldest
+	/// element if the ring is full.
+	pub fn push(&mut self, element: T) {
+		if self.buffer.len() < self.size {
+			self.buffer.push(element);
+		} else {
+			self.evict(1);
+			self.buffer[self.index] = element;
+		}
+
+		self.index = (self.index + 1) % self.size;
 	}
 
 	/// Appends a slice of values into the ring buffer.
 	/// Only the last <size of ring> elements are kept.
+	///
+	/// `clone_from_slice` is specialized by the standard library to a plain
+	/// memory copy when `T: Copy`, so no separate path is needed for samples.
 	pub fn append(&mut self, mut elements: &[T]) {
+		let evicted = (self.buffer.len() + elements.len())
+			.saturating_sub(self.size)
+			.min(self.buffer.len());
+		self.evict(evicted);
+
 		// only insert elements that can fit in the buffer
-		let count = elements.len().checked_sub(self.size).unwrap_or(0);
+		let count = elements.len().saturating_sub(self.size);
 		elements = &elements[count..];
 
 		let (tail_elements, head_elements) =
@@ -50,16 +275,855 @@ impl<T: Clone> RingBuffer<T> {
 
 		self.index = (self.index + elements.len()) % self.size;
 	}
+
+	/// Maps a logical index (0 being the oldest element) to an index into
+	/// `buffer`.
+	fn physical_index(&self, index: usize) -> usize {
+		// While the ring is not yet full `index == buffer.len()`, making this a no-op.
+		(self.index + index) % self.buffer.len()
+	}
+}
+
+impl<T: Clone> Clone for RingBuffer<T> {
+	fn clone(&self) -> Self {
+		Self {
+			buffer: self.buffer.clone(),
+			size: self.size,
+			index: self.index,
+			on_evict: None,
+		}
+	}
+}
+
+impl<T: Clone + fmt::Debug> fmt::Debug for RingBuffer<T> {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("RingBuffer")
+			.field("buffer", &self.buffer)
+			.field("size", &self.size)
+			.field("index", &self.index)
+			.field("on_evict", &self.on_evict.is_some())
+			.finish()
+	}
+}
+
+impl<'a, T: Clone> IntoIterator for &'a RingBuffer<T> {
+	type IntoIter = RingBufferIter<'a, T>;
+	type Item = &'a T;
+
+	fn into_iter(self) -> Self::IntoIter {
+		self.iter()
+	}
+}
+
+/// Iterator over the elements of a ring buffer, oldest first.
+///
+/// Walks the older and newer contiguous parts of the ring in turn, from
+/// either end.
+#[derive(Debug, Clone)]
+pub struct RingBufferIter<'a, T> {
+	older: slice::Iter<'a, T>,
+	newer: slice::Iter<'a, T>,
+}
+
+impl<'a, T> RingBufferIter<'a, T> {
+	fn new(older: &'a [T], newer: &'a [T]) -> Self {
+		Self {
+			older: older.iter(),
+			newer: newer.iter(),
+		}
+	}
+}
+
+impl<'a, T> Iterator for RingBufferIter<'a, T> {
+	type Item = &'a T;
+
+	fn next(&mut self) -> Option<&'a T> {
+		self.older.next().or_else(|| self.newer.next())
+	}
+
+	fn size_hint(&self) -> (usize, Option<usize>) {
+		let len = self.len();
+		(len, Some(len))
+	}
+
+	fn fold<B, F: FnMut(B, &'a T) -> B>(self, init: B, mut f: F) -> B {
+		let accumulator = self.older.fold(init, &mut f);
+		self.newer.fold(accumulator, f)
+	}
+}
+
+impl<T> DoubleEndedIterator for RingBufferIter<'_, T> {
+	fn next_back(&mut self) -> Option<Self::Item> {
+		self.newer.next_back().or_else(|| self.older.next_back())
+	}
+}
+
+impl<T> ExactSizeIterator for RingBufferIter<'_, T> {
+	fn len(&self) -> usize {
+		self.older.len() + self.newer.len()
+	}
+}
+
+impl<T> FusedIterator for RingBufferIter<'_, T> {}
+
+/// Collects into a ring buffer sized to hold exactly the collected elements.
+///
+/// The size is fixed from then on, so later pushes overwrite the oldest
+/// elements. Use `RingBuffer::new` and `extend` to collect into a ring of a
+/// different size.
+///
+/// Panics if the iterator is empty, as a ring buffer can't have a size of
+/// zero.
+impl<T: Clone> FromIterator<T> for RingBuffer<T> {
+	fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+		let buffer = iter.into_iter().collect::<Vec<_>>();
+		assert!(!buffer.is_empty(), "RingBuffer size must be non-zero");
+
+		Self {
+			size: buffer.len(),
+			buffer,
+			index: 0,
+			on_evict: None,
+		}
+	}
+}
+
+/// Pushes every element in turn, like `append`, so only the last <size of
+/// ring> elements are kept.
+impl<T: Clone> Extend<T> for RingBuffer<T> {
+	fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+		for element in iter {
+			self.push(element);
+		}
+	}
+}
+
+/// Ring buffers are equal if they have the same size and contain the same
+/// elements in the same logical order, regardless of how they are laid out
+/// internally.
+impl<T: Clone + PartialEq> PartialEq for RingBuffer<T> {
+	fn eq(&self, other: &Self) -> bool {
+		self.size == other.size && self.iter().eq(other.iter())
+	}
+}
+
+impl<T: Clone + Eq> Eq for RingBuffer<T> {}
+
+impl<T: Clone> Index<usize> for RingBuffer<T> {
+	type Output = T;
+
+	/// Returns the element at a logical index, where 0 is the oldest element.
+	///
+	/// Panics if `index` is out of bounds.
+	fn index(&self, index: usize) -> &T {
+		let len = self.buffer.len();
+		if index >= len {
+			panic!("index out of bounds: the len is {len} but the index is {index}");
+		}
+
+		&self.buffer[self.physical_index(index)]
+	}
+}
+
+/// Serializes the size and the elements oldest first, independent of how
+/// they are laid out internally. The evict callback isn't saved.
+#[cfg(feature = "serde")]
+impl<T: Clone + serde::Serialize> serde::Serialize for RingBuffer<T> {
+	fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		use serde::ser::SerializeStruct;
+
+		/// The elements as a sequence, without collecting them first.
+		struct Elements<'a, T: Clone>(&'a RingBuffer<T>);
+
+		impl<T: Clone + serde::Serialize> serde::Serialize for Elements<'_, T> {
+			fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+				serializer.collect_seq(self.0.iter())
+			}
+		}
+
+		let mut state = serializer.serialize_struct("RingBuffer", 2)?;
+		state.serialize_field("size", &self.size)?;
+		state.serialize_field("elements", &Elements(self))?;
+		state.end()
+	}
+}
+
+/// Rebuilds a ring buffer saved by `Serialize`, rejecting a size of zero or
+/// more elements than fit.
+#[cfg(feature = "serde")]
+impl<'de, T: Clone + serde::Deserialize<'de>> serde::Deserialize<'de> for RingBuffer<T> {
+	fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		use serde::de::{Error, Unexpected};
+
+		#[derive(serde::Deserialize)]
+		#[serde(rename = "RingBuffer")]
+		struct Saved<T> {
+			size: usize,
+			elements: Vec<T>,
+		}
+
+		let saved = Saved::<T>::deserialize(deserializer)?;
+		if saved.size == 0 {
+			return Err(D::Error::invalid_value(Unexpected::Unsigned(0), &"a non-zero size"))
+		}
+		if saved.elements.len() > saved.size {
+			return Err(D::Error::invalid_length(saved.elements.len(), &"at most size elements"))
+		}
+
+		let mut buffer = Self::new(saved.size);
+		buffer.extend(saved.elements);
+		Ok(buffer)
+	}
+}
+
+/// A `RingBuffer<f32>` that incrementally maintains the sum of its elements.
+///
+/// Read-only access to the underlying ring buffer is available through `Deref`.
+#[derive(Debug)]
+pub struct SummedRingBuffer {
+	buffer: RingBuffer<f32>,
+	// accumulated as f64 to limit drift from repeated add/subtract
+	sum: f64,
+}
+
+impl SummedRingBuffer {
+	/// Create a new summed ring buffer
+	pub fn new(size: usize) -> Self {
+		Self {
+			buffer: RingBuffer::new(size),
+			sum: 0.0,
+		}
+	}
+
+	/// Create a new summed ring buffer that is already full of `value`
+	pub fn filled(size: usize, value: f32) -> Self {
+		Self {
+			buffer: RingBuffer::filled(size, value),
+			sum: value as f64 * size as f64,
+		}
+	}
+
+	/// Pushes a single value into the ring buffer, overwriting the oldest
+	/// element if the ring is full.
+	pub fn push(&mut self, element: f32) {
+		if self.buffer.is_full() {
+			self.sum -= self.buffer[0] as f64;
+		}
+
+		self.sum += element as f64;
+		self.buffer.push(element);
+	}
+
+	/// Appends a slice of values into the ring buffer.
+	/// Only the last <size of ring> elements are kept.
+	pub fn append(&mut self, elements: &[f32]) {
+		for &element in elements {
+			self.push(element);
+		}
+	}
+
+	/// Removes all elements from the ring buffer, keeping its allocation.
+	pub fn clear(&mut self) {
+		self.buffer.clear();
+		self.sum = 0.0;
+	}
+
+	/// Replaces every slot with `value`, leaving the ring buffer full.
+	pub fn fill(&mut self, value: f32) {
+		self.buffer.fill(value);
+		self.sum = value as f64 * self.buffer.size() as f64;
+	}
+
+	/// Returns the sum of all stored elements.
+	pub fn sum(&self) -> f32 {
+		self.sum as f32
+	}
+
+	/// Returns the mean of all stored elements, or `None` if empty.
+	pub fn mean(&self) -> Option<f32> {
+		match self.buffer.len() {
+			0 => None,
+			len => Some((self.sum / len as f64) as f32),
+		}
+	}
+}
+
+impl Deref for SummedRingBuffer {
+	type Target = RingBuffer<f32>;
+
+	fn deref(&self) -> &RingBuffer<f32> {
+		&self.buffer
+	}
+}
+
+/// A fixed length ring buffer backed by an array instead of a heap allocation.
+///
+/// Behaves like `RingBuffer`, but its size is fixed at compile time.
+#[derive(Debug)]
+pub struct ArrayRingBuffer<T: Copy + Default, const N: usize> {
+	buffer: [T; N],
+	len: usize,
+	index: usize,
+}
+
+impl<T: Copy + Default, const N: usize> ArrayRingBuffer<T, N> {
+	/// Create a new array ring buffer
+	///
+	/// Panics if `N` is zero.
+	pub fn new() -> Self {
+		assert!(N != 0, "ArrayRingBuffer size must be non-zero");
+
+		Self {
+			buffer: [T::default(); N],
+			len: 0,
+			index: 0,
+		}
+	}
+
+	/// Returns the number of elements currently stored.
+	pub fn len(&self) -> usize {
+		self.len
+	}
+
+	/// Returns true if the ring buffer contains no elements.
+	pub fn is_empty(&self) -> bool {
+		self.len == 0
+	}
+
+	/// Returns true once the ring buffer has been filled to its size.
+	pub fn is_full(&self) -> bool {
+		self.len == N
+	}
+
+	/// Removes all elements from the ring buffer.
+	pub fn clear(&mut self) {
+		self.len = 0;
+		self.index = 0;
+	}
+
+	/// Returns an iterator over all ring buffer elements.
+	pub fn iter(&self) -> RingBufferIter<'_, T> {
+		// While the ring is not yet full `index == len`, leaving the first slice empty.
+		RingBufferIter::new(&self.buffer[self.index..self.len], &self.buffer[..self.index])
+	}
+
+	/// Pushes a single value into the ring buffer, overwriting the oldest
+	/// element if the ring is full.
+	pub fn push(&mut self, element: T) {
+		self.buffer[self.index] = element;
+		self.len = (self.len + 1).min(N);
+		self.index = (self.index + 1) % N;
+	}
+
+	/// Appends a slice of values into the ring buffer.
+	/// Only the last <size of ring> elements are kept.
+	pub fn append(&mut self, mut elements: &[T]) {
+		// only insert elements that can fit in the buffer
+		let count = elements.len().saturating_sub(N);
+		elements = &elements[count..];
+
+		let (tail_elements, head_elements) =
+			elements.split_at((N - self.index).min(elements.len()));
+		self.buffer[self.index..(self.index + tail_elements.len())].copy_from_slice(tail_elements);
+		self.buffer[..head_elements.len()].copy_from_slice(head_elements);
+
+		self.len = (self.len + elements.len()).min(N);
+		self.index = (self.index + elements.len()) % N;
+	}
+}
+
+impl<T: Copy + Default, const N: usize> Default for ArrayRingBuffer<T, N> {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+/// A fixed length queue shared by one producing and one consuming thread,
+/// without locking.
+///
+/// Unlike `RingBuffer`, elements are never overwritten: pushing to a full
+/// buffer fails until the consumer catches up.
+///
+/// ```
+/// use shoosh::ringbuffer::SpscRingBuffer;
+///
+/// let (mut producer, mut consumer) = SpscRingBuffer::new(2);
+/// assert_eq!(Ok(()), producer.try_push(1));
+/// assert_eq!(Ok(()), producer.try_push(2));
+/// assert_eq!(Err(3), producer.try_push(3));
+///
+/// assert_eq!(Some(1), consumer.try_pop());
+/// ```
+pub struct SpscRingBuffer<T> {
+	/// A power of two number of slots, so indexing by the wrapping counters
+	/// below stays consistent when they overflow
+	slots: Box<[UnsafeCell<MaybeUninit<T>>]>,
+	/// Number of elements the buffer holds once full, at most `slots.len()`
+	size: usize,
+	/// Number of elements popped so far, only written by the consumer
+	read: AtomicUsize,
+	/// Number of elements pushed so far, only written by the producer
+	write: AtomicUsize,
+}
+
+// SAFETY: each slot is only accessed by the producer before it publishes it
+// through `write`, and by the consumer after, until it releases it through
+// `read`, so elements only need to be sendable between the two threads.
+unsafe impl<T: Send> Sync for SpscRingBuffer<T> {}
+
+impl<T> SpscRingBuffer<T> {
+	/// Create a new SPSC ring buffer, returning its two ends
+	///
+	/// Slots are allocated for `size` rounded up to a power of two. Panics if
+	/// `size` is zero or above the largest power of two a `usize` can hold.
+	#[allow(clippy::new_ret_no_self)]
+	pub fn new(size: usize) -> (SpscProducer<T>, SpscConsumer<T>) {
+		Self::with_counters(size, 0)
+	}
+
+	/// Create a new SPSC ring buffer with both counters starting at `start`
+	fn with_counters(size: usize, start: usize) -> (SpscProducer<T>, SpscConsumer<T>) {
+		assert!(size != 0, "SpscRingBuffer size must be non-zero");
+		let slots = size
+			.checked_next_power_of_two()
+			.expect("SpscRingBuffer size is too large to round up to a power of two");
+
+		let buffer = Arc::new(Self {
+			slots: (0..slots)
+				.map(|_| UnsafeCell::new(MaybeUninit::uninit()))
+				.collect(),
+			size,
+			read: AtomicUsize::new(start),
+			write: AtomicUsize::new(start),
+		});
+
+		(SpscProducer(Arc::clone(&buffer)), SpscConsumer(buffer))
+	}
+
+	/// Returns the number of elements pushed but not yet popped.
+	pub fn len(&self) -> usize {
+		let read = self.read.load(Ordering::Acquire);
+		self.write.load(Ordering::Acquire).wrapping_sub(read)
+	}
+
+	/// Returns true if there are no elements waiting to be popped.
+	pub fn is_empty(&self) -> bool {
+		self.len() == 0
+	}
+
+	/// Returns the number of elements the buffer holds once full.
+	pub fn size(&self) -> usize {
+		self.size
+	}
+
+	fn slot(&self, count: usize) -> *mut MaybeUninit<T> {
+		self.slots[count & (self.slots.len() - 1)].get()
+	}
+}
+
+impl<T> Drop for SpscRingBuffer<T> {
+	fn drop(&mut self) {
+		let write = *self.write.get_mut();
+		let mut read = *self.read.get_mut();
+
+		while read != write {
+			// SAFETY: slots between `read` and `write` hold pushed elements
+			unsafe { (*self.slot(read)).assume_init_drop() };
+			read = read.wrapping_add(1);
+		}
+	}
+}
+
+/// The pushing end of an `SpscRingBuffer`.
+pub struct SpscProducer<T>(Arc<SpscRingBuffer<T>>);
+
+impl<T> SpscProducer<T> {
+	/// Pushes `element`, or returns it if the buffer is full.
+	pub fn try_push(&mut self, element: T) -> Result<(), T> {
+		let buffer = &*self.0;
+		let write = buffer.write.load(Ordering::Relaxed);
+		let read = buffer.read.load(Ordering::Acquire);
+
+		if write.wrapping_sub(read) == buffer.size() {
+			return Err(element)
+		}
+
+		// SAFETY: the slot was released by the consumer, or never used, and the
+		// consumer can't read it until `write` is published below
+		unsafe { (*buffer.slot(write)).write(element) };
+		buffer.write.store(write.wrapping_add(1), Ordering::Release);
+
+		Ok(())
+	}
+}
+
+impl<T> Deref for SpscProducer<T> {
+	type Target = SpscRingBuffer<T>;
+
+	fn deref(&self) -> &SpscRingBuffer<T> {
+		&self.0
+	}
+}
+
+/// The popping end of an `SpscRingBuffer`.
+pub struct SpscConsumer<T>(Arc<SpscRingBuffer<T>>);
+
+impl<T> SpscConsumer<T> {
+	/// Pops the oldest element, or returns `None` if the buffer is empty.
+	pub fn try_pop(&mut self) -> Option<T> {
+		let buffer = &*self.0;
+		let read = buffer.read.load(Ordering::Relaxed);
+		let write = buffer.write.load(Ordering::Acquire);
+
+		if read == write {
+			return None
+		}
+
+		// SAFETY: the producer published the slot through `write` and won't
+		// touch it again until `read` is released below
+		let element = unsafe { (*buffer.slot(read)).assume_init_read() };
+		buffer.read.store(read.wrapping_add(1), Ordering::Release);
+
+		Some(element)
+	}
+}
+
+impl<T> Deref for SpscConsumer<T> {
+	type Target = SpscRingBuffer<T>;
+
+	fn deref(&self) -> &SpscRingBuffer<T> {
+		&self.0
+	}
 }
 
 #[cfg(test)]
 mod test {
-	use super::RingBuffer;
+	use std::{
+		sync::{Arc, Mutex},
+		thread,
+	};
+
+	use super::{ArrayRingBuffer, RingBuffer, SpscRingBuffer, SummedRingBuffer};
 
 	fn collect_buffer<T: Clone>(buffer: &RingBuffer<T>) -> Vec<T> {
 		buffer.iter().cloned().collect::<Vec<_>>()
 	}
 
+	#[test]
+	#[should_panic(expected = "RingBuffer size must be non-zero")]
+	fn zero_size() {
+		RingBuffer::<f32>::new(0);
+	}
+
+	#[test]
+	fn filled() {
+		let buffer = RingBuffer::filled(4, 0.5);
+		assert!(buffer.is_full());
+		assert_eq!(vec![0.5; 4], buffer.iter().copied().collect::<Vec<_>>());
+
+		// refilling a wrapped buffer starts over from the first slot
+		let mut buffer = RingBuffer::new(3);
+		buffer.append(&[1, 2, 3, 4]);
+		buffer.fill(7);
+		assert_eq!(vec![7, 7, 7], collect_buffer(&buffer));
+		buffer.push(8);
+		assert_eq!(vec![7, 7, 8], collect_buffer(&buffer));
+	}
+
+	#[test]
+	fn on_evict() {
+		let evicted = Arc::new(Mutex::new(Vec::new()));
+		let mut buffer = RingBuffer::new(3);
+		{
+			let evicted = Arc::clone(&evicted);
+			buffer.set_on_evict(move |&element| evicted.lock().unwrap().push(element));
+		}
+
+		// nothing is overwritten until the ring is full
+		buffer.append(&[1, 2]);
+		buffer.push(3);
+		assert!(evicted.lock().unwrap().is_empty());
+
+		buffer.push(4);
+		buffer.append(&[5, 6]);
+		// wraps partway through
+		buffer.append(&[7, 8]);
+		// replaces everything, 9 and 10 are never stored so never evicted
+		buffer.append(&[9, 10, 11, 12, 13]);
+		buffer.push(14);
+
+		assert_eq!(vec![1, 2, 3, 4, 5, 6, 7, 8, 11], *evicted.lock().unwrap());
+		assert_eq!(vec![12, 13, 14], buffer.to_vec());
+
+		// cleared elements aren't evicted
+		buffer.clear();
+		buffer.append(&[15, 16]);
+		assert_eq!(9, evicted.lock().unwrap().len());
+	}
+
+	#[test]
+	fn len() {
+		let mut buffer = RingBuffer::new(5);
+		assert!(buffer.is_empty());
+
+		buffer.append(&[1, 2]);
+		assert_eq!(2, buffer.len());
+		assert_eq!(5, buffer.size());
+		assert!(!buffer.is_empty());
+
+		buffer.append(&[3, 4, 5, 6]);
+		assert_eq!(5, buffer.len());
+	}
+
+	#[test]
+	fn is_full() {
+		let mut buffer = RingBuffer::new(5);
+
+		buffer.append(&[1, 2, 3]);
+		assert!(!buffer.is_full());
+
+		buffer.append(&[4, 5]);
+		assert!(buffer.is_full());
+
+		// stays full after wrapping
+		buffer.append(&[6]);
+		assert!(buffer.is_full());
+	}
+
+	#[test]
+	fn clear() {
+		let mut buffer = RingBuffer::new(5);
+		buffer.append(&[1, 2, 3, 4, 5, 6, 7]);
+
+		buffer.clear();
+		assert!(buffer.is_empty());
+		assert_eq!(0, buffer.iter().count());
+
+		// behaves like a fresh buffer
+		buffer.append(&[8, 9]);
+		assert_eq!(&[8, 9], collect_buffer(&buffer).as_slice());
+
+		buffer.append(&[10, 11, 12, 13]);
+		assert_eq!(&[9, 10, 11, 12, 13], collect_buffer(&buffer).as_slice());
+	}
+
+	#[test]
+	fn get() {
+		let mut buffer = RingBuffer::new(5);
+		assert_eq!(None, buffer.get(0));
+
+		// not yet full, only the stored elements are in range
+		buffer.append(&[1, 2, 3]);
+		assert_eq!(Some(&1), buffer.get(0));
+		assert_eq!(Some(&3), buffer.get(2));
+		assert_eq!(None, buffer.get(3));
+
+		// wrapped, indices stay logical
+		buffer.append(&[4, 5, 6, 7]);
+		for i in 0..5 {
+			assert_eq!(Some(&buffer[i]), buffer.get(i));
+		}
+		assert_eq!(Some(&3), buffer.get(0));
+		assert_eq!(None, buffer.get(5));
+		assert_eq!(None, buffer.get(usize::MAX));
+	}
+
+	#[test]
+	fn iterate_wrapped_from_both_ends() {
+		let mut buffer = RingBuffer::new(5);
+		buffer.append(&[1, 2, 3, 4, 5, 6, 7]);
+
+		let mut iter = buffer.iter();
+		assert_eq!(5, iter.len());
+		assert_eq!(Some(&3), iter.next());
+		assert_eq!(Some(&7), iter.next_back());
+		assert_eq!(3, iter.len());
+
+		// meet in the middle, across the wrap
+		assert_eq!(Some(&6), iter.next_back());
+		assert_eq!(Some(&4), iter.next());
+		assert_eq!(Some(&5), iter.next_back());
+		assert_eq!(0, iter.len());
+		assert_eq!(None, iter.next());
+		assert_eq!(None, iter.next_back());
+
+		assert_eq!(vec![7, 6, 5, 4, 3], buffer.iter().rev().copied().collect::<Vec<_>>());
+		assert_eq!(25, buffer.iter().sum::<i32>());
+
+		// before the ring fills
+		let mut buffer = RingBuffer::new(4);
+		buffer.append(&[1, 2]);
+		assert_eq!(2, buffer.iter().len());
+		assert_eq!(vec![2, 1], buffer.iter().rev().copied().collect::<Vec<_>>());
+
+		let mut buffer = ArrayRingBuffer::<i32, 3>::new();
+		buffer.append(&[1, 2, 3, 4]);
+		assert_eq!(3, buffer.iter().len());
+		assert_eq!(vec![4, 3, 2], buffer.iter().rev().copied().collect::<Vec<_>>());
+	}
+
+	#[test]
+	fn to_vec() {
+		let mut buffer = RingBuffer::new(5);
+		assert!(buffer.to_vec().is_empty());
+
+		buffer.append(&[1, 2, 3]);
+		assert_eq!(vec![1, 2, 3], buffer.to_vec());
+
+		// wrapped
+		buffer.append(&[4, 5, 6, 7]);
+		assert_eq!(buffer.iter().cloned().collect::<Vec<_>>(), buffer.to_vec());
+		assert_eq!(vec![3, 4, 5, 6, 7], buffer.to_vec());
+	}
+
+	#[test]
+	fn drain() {
+		let mut buffer = RingBuffer::new(5);
+		buffer.append(&[1, 2, 3, 4, 5, 6, 7]);
+
+		assert_eq!(vec![3, 4, 5, 6, 7], buffer.drain());
+		assert!(buffer.is_empty());
+		assert_eq!(5, buffer.size());
+
+		// behaves like a fresh buffer
+		buffer.append(&[8, 9]);
+		assert_eq!(vec![8, 9], buffer.drain());
+		assert!(buffer.drain().is_empty());
+
+		buffer.append(&[10, 11, 12, 13, 14, 15]);
+		assert_eq!(vec![11, 12, 13, 14, 15], buffer.to_vec());
+	}
+
+	#[test]
+	fn resize() {
+		// grow a partially filled buffer
+		let mut buffer = RingBuffer::new(3);
+		buffer.append(&[1, 2]);
+		buffer.resize(5);
+		assert_eq!(5, buffer.size());
+		assert_eq!(vec![1, 2], buffer.to_vec());
+		buffer.append(&[3, 4, 5, 6]);
+		assert_eq!(vec![2, 3, 4, 5, 6], buffer.to_vec());
+
+		// grow a wrapped buffer, keeping everything
+		buffer.resize(7);
+		assert_eq!(vec![2, 3, 4, 5, 6], buffer.to_vec());
+		assert!(!buffer.is_full());
+		buffer.append(&[7, 8, 9]);
+		assert_eq!(vec![3, 4, 5, 6, 7, 8, 9], buffer.to_vec());
+
+		// shrink a wrapped buffer, dropping the oldest
+		buffer.append(&[10, 11]);
+		buffer.resize(3);
+		assert_eq!(vec![9, 10, 11], buffer.to_vec());
+		assert!(buffer.is_full());
+		buffer.push(12);
+		assert_eq!(vec![10, 11, 12], buffer.to_vec());
+
+		// shrink to exactly the stored length
+		let mut buffer = RingBuffer::new(5);
+		buffer.append(&[1, 2, 3]);
+		buffer.resize(3);
+		assert!(buffer.is_full());
+		buffer.push(4);
+		assert_eq!(vec![2, 3, 4], buffer.to_vec());
+	}
+
+	#[test]
+	fn peek_recent() {
+		let mut buffer = RingBuffer::new(5);
+		buffer.append(&[1, 2, 3]);
+
+		assert_eq!(vec![1, 2, 3], buffer.peek_recent(10).cloned().collect::<Vec<_>>());
+		assert_eq!(0, buffer.peek_recent(0).count());
+
+		// wrapped
+		buffer.append(&[4, 5, 6, 7]);
+		assert_eq!(vec![5, 6, 7], buffer.peek_recent(3).cloned().collect::<Vec<_>>());
+		assert_eq!(vec![7], buffer.peek_recent(1).cloned().collect::<Vec<_>>());
+		assert_eq!(5, buffer.peek_recent(10).len());
+	}
+
+	#[test]
+	fn as_slices() {
+		let mut buffer = RingBuffer::new(5);
+
+		buffer.append(&[1, 2, 3]);
+		let (older, newer) = buffer.as_slices();
+		assert_eq!(&[1, 2, 3], older);
+		assert!(newer.is_empty());
+
+		buffer.append(&[4, 5, 6, 7]);
+		let (older, newer) = buffer.as_slices();
+		assert_eq!(collect_buffer(&buffer), [older, newer].concat());
+	}
+
+	#[test]
+	fn iter_mut() {
+		let mut buffer = RingBuffer::new(5);
+		buffer.append(&[1, 2, 3, 4, 5, 6, 7]);
+
+		for (i, element) in buffer.iter_mut().enumerate() {
+			*element *= 10 + i;
+		}
+
+		assert_eq!(&[30, 44, 60, 78, 98], collect_buffer(&buffer).as_slice());
+	}
+
+	#[test]
+	fn max() {
+		let mut buffer = RingBuffer::<f32>::new(5);
+		assert_eq!(None, buffer.max());
+
+		// partially filled
+		buffer.append(&[0.2, 0.8, 0.4]);
+		assert_eq!(Some(0.8), buffer.max());
+
+		// wrapped, the previous max has been evicted
+		buffer.append(&[0.1, 0.3, 0.5, 0.6]);
+		assert_eq!(Some(0.6), buffer.max());
+
+		buffer.push(f32::NAN);
+		assert_eq!(Some(0.6), buffer.max());
+	}
+
+	#[test]
+	fn search_wrapped() {
+		let mut buffer = RingBuffer::new(4);
+		buffer.append(&[1, 2, 3, 4, 5, 6]);
+
+		assert!(buffer.contains(&6));
+		assert!(buffer.contains(&3));
+		assert!(!buffer.contains(&2));
+
+		// logical indices, counting from the oldest element across the wrap
+		assert_eq!(Some(0), buffer.position(|&x| x == 3));
+		assert_eq!(Some(3), buffer.position(|&x| x == 6));
+		assert_eq!(Some(2), buffer.position(|&x| x > 4));
+		assert_eq!(None, buffer.position(|&x| x > 6));
+		let index = buffer.position(|&x| x == 5).unwrap();
+		assert_eq!(Some(&5), buffer.get(index));
+	}
+
+	#[test]
+	fn push_elements() {
+		let mut buffer = RingBuffer::new(5);
+
+		buffer.push(1);
+		buffer.append(&[2, 3]);
+		buffer.push(4);
+		assert_eq!(&[1, 2, 3, 4], collect_buffer(&buffer).as_slice());
+
+		// wrap ring with push
+		buffer.push(5);
+		buffer.push(6);
+		assert_eq!(&[2, 3, 4, 5, 6], collect_buffer(&buffer).as_slice());
+
+		buffer.append(&[7, 8]);
+		buffer.push(9);
+		assert_eq!(&[5, 6, 7, 8, 9], collect_buffer(&buffer).as_slice());
+	}
+
 	#[test]
 	fn append_elements() {
 		let mut buffer = RingBuffer::new(5);
@@ -84,4 +1148,270 @@ mod test {
 		buffer.append(&[12, 13, 14, 15, 16, 17, 18, 19, 20]);
 		assert_eq!(&[16, 17, 18, 19, 20], collect_buffer(&buffer).as_slice());
 	}
+
+	#[test]
+	fn from_iter() {
+		let mut buffer = (1..=4).collect::<RingBuffer<_>>();
+		assert_eq!(4, buffer.size());
+		assert!(buffer.is_full());
+		assert_eq!(&[1, 2, 3, 4], collect_buffer(&buffer).as_slice());
+
+		// the size stays that of the collected elements
+		buffer.push(5);
+		assert_eq!(&[2, 3, 4, 5], collect_buffer(&buffer).as_slice());
+	}
+
+	#[test]
+	#[should_panic(expected = "RingBuffer size must be non-zero")]
+	fn from_empty_iter() {
+		let _ = std::iter::empty::<f32>().collect::<RingBuffer<_>>();
+	}
+
+	#[test]
+	fn extend() {
+		let mut buffer = RingBuffer::new(5);
+
+		buffer.extend([1, 2]);
+		assert_eq!(&[1, 2], collect_buffer(&buffer).as_slice());
+
+		// matches append, keeping only the newest elements
+		buffer.extend(3..=8);
+		let mut appended = RingBuffer::new(5);
+		appended.append(&[1, 2, 3, 4, 5, 6, 7, 8]);
+		assert_eq!(appended, buffer);
+	}
+
+	#[test]
+	fn logical_equality() {
+		let mut a = RingBuffer::new(5);
+		a.append(&[1, 2, 3, 4, 5, 6, 7]);
+
+		let mut b = RingBuffer::new(5);
+		b.append(&[3, 4, 5, 6, 7]);
+
+		// same contents, different rotation
+		assert_eq!(a, b);
+
+		let mut c = a.clone();
+		assert_eq!(a, c);
+		c.push(8);
+		assert_ne!(a, c);
+
+		// same contents, different size
+		let mut d = RingBuffer::new(6);
+		d.append(&[3, 4, 5, 6, 7]);
+		assert_ne!(b, d);
+	}
+
+	#[test]
+	fn index() {
+		let mut buffer = RingBuffer::new(5);
+
+		buffer.append(&[1, 2, 3]);
+		assert_eq!(1, buffer[0]);
+		assert_eq!(3, buffer[2]);
+
+		buffer.append(&[4, 5, 6, 7]);
+		let elements = collect_buffer(&buffer);
+		for (i, element) in elements.iter().enumerate() {
+			assert_eq!(*element, buffer[i]);
+		}
+	}
+
+	#[test]
+	#[should_panic(expected = "index out of bounds: the len is 2 but the index is 2")]
+	fn index_out_of_bounds() {
+		let mut buffer = RingBuffer::new(5);
+		buffer.append(&[1, 2]);
+		let _ = buffer[2];
+	}
+
+	#[cfg(feature = "serde")]
+	#[test]
+	fn serde_round_trip() {
+		let mut buffer = RingBuffer::new(4);
+		buffer.append(&[1, 2, 3, 4, 5, 6]);
+
+		let json = serde_json::to_string(&buffer).unwrap();
+		assert_eq!(r#"{"size":4,"elements":[3,4,5,6]}"#, json);
+
+		let restored = serde_json::from_str::<RingBuffer<i32>>(&json).unwrap();
+		assert_eq!(vec![3, 4, 5, 6], collect_buffer(&restored));
+		assert_eq!(buffer, restored);
+
+		// a partly filled buffer keeps its size
+		let restored = serde_json::from_str::<RingBuffer<i32>>(r#"{"size":4,"elements":[1]}"#);
+		assert_eq!(4, restored.unwrap().size());
+
+		assert!(serde_json::from_str::<RingBuffer<i32>>(r#"{"size":0,"elements":[]}"#).is_err());
+		assert!(serde_json::from_str::<RingBuffer<i32>>(r#"{"size":1,"elements":[1,2]}"#).is_err());
+	}
+
+	#[test]
+	fn cached_sum() {
+		let mut buffer = SummedRingBuffer::new(128);
+		assert_eq!(None, buffer.mean());
+
+		// simple LCG for reproducible pseudo-random values
+		let mut state = 0x2545_f491_u32;
+		let mut next = || {
+			state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+			(state >> 8) as f32 / (1 << 24) as f32
+		};
+
+		for i in 0..10_000 {
+			if i % 7 == 0 {
+				let elements = (0..i % 300).map(|_| next()).collect::<Vec<_>>();
+				buffer.append(&elements);
+			} else {
+				buffer.push(next());
+			}
+
+			let expected = buffer.iter().sum::<f32>();
+			assert!((buffer.sum() - expected).abs() < 1e-3);
+		}
+
+		let expected_mean = buffer.iter().sum::<f32>() / buffer.len() as f32;
+		assert!((buffer.mean().unwrap() - expected_mean).abs() < 1e-5);
+
+		buffer.clear();
+		assert_eq!(0.0, buffer.sum());
+
+		// refilling keeps the sum in step with the pushes after it
+		buffer.fill(0.5);
+		assert_eq!(Some(0.5), buffer.mean());
+		buffer.push(1.5);
+		assert_eq!(65.0, buffer.sum());
+	}
+
+	#[test]
+	#[should_panic(expected = "ArrayRingBuffer size must be non-zero")]
+	fn array_zero_size() {
+		ArrayRingBuffer::<f32, 0>::new();
+	}
+
+	#[test]
+	fn array_append_elements() {
+		let mut buffer = ArrayRingBuffer::<_, 5>::new();
+		let collect_buffer =
+			|buffer: &ArrayRingBuffer<_, 5>| buffer.iter().cloned().collect::<Vec<_>>();
+
+		// partial insert
+		buffer.append(&[1, 2]);
+		assert_eq!(&[1, 2], collect_buffer(&buffer).as_slice());
+
+		// wrap ring before full
+		buffer.append(&[3, 4, 5, 6]);
+		assert_eq!(&[2, 3, 4, 5, 6], collect_buffer(&buffer).as_slice());
+
+		// insert elements in the middle
+		buffer.append(&[7, 8]);
+		assert_eq!(&[4, 5, 6, 7, 8], collect_buffer(&buffer).as_slice());
+
+		// wrap ring while full
+		buffer.append(&[9, 10, 11]);
+		assert_eq!(&[7, 8, 9, 10, 11], collect_buffer(&buffer).as_slice());
+
+		// insert more elements than ring holds
+		buffer.append(&[12, 13, 14, 15, 16, 17, 18, 19, 20]);
+		assert_eq!(&[16, 17, 18, 19, 20], collect_buffer(&buffer).as_slice());
+
+		// single element pushes
+		buffer.clear();
+		buffer.push(21);
+		buffer.append(&[22, 23, 24, 25]);
+		buffer.push(26);
+		assert_eq!(&[22, 23, 24, 25, 26], collect_buffer(&buffer).as_slice());
+	}
+
+	#[test]
+	#[should_panic(expected = "SpscRingBuffer size is too large")]
+	fn spsc_size_too_large() {
+		SpscRingBuffer::<u8>::new(usize::MAX / 2 + 2);
+	}
+
+	#[test]
+	fn spsc_push_pop() {
+		let (mut producer, mut consumer) = SpscRingBuffer::new(3);
+		assert_eq!(None, consumer.try_pop());
+
+		assert_eq!(Ok(()), producer.try_push(1));
+		assert_eq!(Ok(()), producer.try_push(2));
+		assert_eq!(Ok(()), producer.try_push(3));
+		assert_eq!(Err(4), producer.try_push(4));
+		assert_eq!(3, consumer.len());
+
+		// wraps around the slots
+		assert_eq!(Some(1), consumer.try_pop());
+		assert_eq!(Ok(()), producer.try_push(4));
+		assert_eq!(Some(2), consumer.try_pop());
+		assert_eq!(Some(3), consumer.try_pop());
+		assert_eq!(Some(4), consumer.try_pop());
+		assert_eq!(None, consumer.try_pop());
+		assert!(producer.is_empty());
+	}
+
+	#[test]
+	fn spsc_counters_overflow() {
+		// a size that isn't a power of two, with the counters wrapping early on
+		let (mut producer, mut consumer) = SpscRingBuffer::with_counters(3, usize::MAX - 1);
+
+		for element in 0..20 {
+			assert_eq!(Ok(()), producer.try_push(element));
+			if element % 2 == 1 {
+				assert_eq!(Ok(()), producer.try_push(element + 100));
+				assert_eq!(Err(0), producer.try_push(0));
+				assert_eq!(Some(element - 1), consumer.try_pop());
+				assert_eq!(Some(element), consumer.try_pop());
+				assert_eq!(Some(element + 100), consumer.try_pop());
+				assert!(consumer.is_empty());
+			}
+		}
+	}
+
+	#[test]
+	fn spsc_drops_remaining() {
+		let element = Arc::new(());
+		let (mut producer, mut consumer) = SpscRingBuffer::new(4);
+
+		for _ in 0..3 {
+			producer.try_push(Arc::clone(&element)).unwrap();
+		}
+		drop(consumer.try_pop());
+		assert_eq!(3, Arc::strong_count(&element));
+
+		drop(producer);
+		drop(consumer);
+		assert_eq!(1, Arc::strong_count(&element));
+	}
+
+	#[test]
+	fn spsc_threads() {
+		const ELEMENTS: u32 = 1_000_000;
+		let (mut producer, mut consumer) = SpscRingBuffer::new(64);
+
+		let producing = thread::spawn(move || {
+			for mut element in 0..ELEMENTS {
+				while let Err(rejected) = producer.try_push(element) {
+					element = rejected;
+					thread::yield_now();
+				}
+			}
+		});
+
+		// every element arrives exactly once, in order
+		let mut expected = 0;
+		while expected < ELEMENTS {
+			match consumer.try_pop() {
+				Some(element) => {
+					assert_eq!(expected, element);
+					expected += 1;
+				}
+				None => thread::yield_now(),
+			}
+		}
+
+		producing.join().unwrap();
+		assert_eq!(None, consumer.try_pop());
+	}
 }
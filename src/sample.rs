@@ -0,0 +1,267 @@
+use std::str::FromStr;
+
+use pulse::sample::Format;
+
+/// Sample formats shoosh can read and write.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SampleFormat {
+	F32le,
+	S16le,
+}
+
+impl SampleFormat {
+	/// Names accepted by `from_str`.
+	pub const NAMES: [&'static str; 2] = ["f32le", "s16le"];
+
+	/// Returns the name `from_str` accepts for this format.
+	pub fn name(self) -> &'static str {
+		match self {
+			Self::F32le => "f32le",
+			Self::S16le => "s16le",
+		}
+	}
+
+	/// Returns the matching pulseaudio format.
+	pub fn pulse_format(self) -> Format {
+		match self {
+			Self::F32le => Format::F32le,
+			Self::S16le => Format::S16le,
+		}
+	}
+
+	/// Returns the matching PipeWire format.
+	#[cfg(feature = "pipewire")]
+	pub fn pipewire_format(self) -> pw::spa::param::audio::AudioFormat {
+		use pw::spa::param::audio::AudioFormat;
+
+		match self {
+			Self::F32le => AudioFormat::F32LE,
+			Self::S16le => AudioFormat::S16LE,
+		}
+	}
+
+	/// Returns the size of a single sample in bytes.
+	pub fn size(self) -> usize {
+		match self {
+			Self::F32le => 4,
+			Self::S16le => 2,
+		}
+	}
+
+	/// Decodes raw sample bytes into floats in the range `[-1.0, 1.0]`,
+	/// replacing the contents of `samples` so its allocation can be reused.
+	///
+	/// A trailing partial sample is ignored, see `FrameAssembler`.
+	pub fn decode_into(self, data: &[u8], samples: &mut Vec<f32>) {
+		samples.clear();
+		match self {
+			Self::F32le => samples.extend(
+				data.chunks_exact(4)
+					.map(|chunk| f32::from_le_bytes(<[u8; 4]>::try_from(chunk).unwrap())),
+			),
+			Self::S16le => samples.extend(data.chunks_exact(2).map(|chunk| {
+				i16::from_le_bytes(<[u8; 2]>::try_from(chunk).unwrap()) as f32 / 32768.0
+			})),
+		}
+	}
+
+	/// Encodes floats into raw sample bytes, clamping to the format's range,
+	/// replacing the contents of `data` so its allocation can be reused.
+	pub fn encode_into(self, samples: &[f32], data: &mut Vec<u8>) {
+		data.clear();
+		match self {
+			Self::F32le => data.extend(samples.iter().flat_map(|f| f.to_le_bytes())),
+			Self::S16le => data.extend(samples.iter().flat_map(|f| {
+				((f * 32768.0).round().clamp(-32768.0, 32767.0) as i16).to_le_bytes()
+			})),
+		}
+	}
+}
+
+impl FromStr for SampleFormat {
+	type Err = String;
+
+	fn from_str(name: &str) -> Result<Self, String> {
+		match name {
+			"f32le" => Ok(Self::F32le),
+			"s16le" => Ok(Self::S16le),
+			_ =>
+				Err(format!("invalid format '{name}', expected one of: {}", Self::NAMES.join(", "))),
+		}
+	}
+}
+
+/// Adds triangular (TPDF) dither of one S16 step before quantization, so the
+/// rounding error is noise instead of distortion that follows the signal.
+pub struct Dither {
+	/// xorshift state, never zero
+	state: u32,
+}
+
+impl Dither {
+	pub fn new() -> Self {
+		Self { state: 0x9e37_79b9 }
+	}
+
+	/// Adds dither to every sample.
+	pub fn apply(&mut self, samples: &mut [f32]) {
+		for sample in samples {
+			*sample += (self.next() - self.next()) / 32768.0;
+		}
+	}
+
+	/// Returns a uniformly distributed value in `[0, 1)`.
+	fn next(&mut self) -> f32 {
+		self.state ^= self.state << 13;
+		self.state ^= self.state >> 17;
+		self.state ^= self.state << 5;
+		(self.state >> 8) as f32 / (1 << 24) as f32
+	}
+}
+
+/// Joins blocks of bytes that may end partway through a frame, handing out
+/// only whole frames and keeping the rest for the next block.
+pub struct FrameAssembler {
+	frame_size: usize,
+	/// Bytes of an incomplete frame at the end of the last block
+	partial: Vec<u8>,
+	joined: Vec<u8>,
+}
+
+impl FrameAssembler {
+	pub fn new(frame_size: usize) -> Self {
+		Self {
+			frame_size,
+			partial: Vec::with_capacity(frame_size),
+			joined: Vec::new(),
+		}
+	}
+
+	/// Returns the whole frames made up of any held back bytes followed by
+	/// `data`, holding back a trailing partial frame.
+	pub fn push<'a>(&'a mut self, data: &'a [u8]) -> &'a [u8] {
+		// the usual case, no copy needed
+		if self.partial.is_empty() && data.len() % self.frame_size == 0 {
+			return data
+		}
+
+		self.joined.clear();
+		self.joined.extend_from_slice(&self.partial);
+		self.joined.extend_from_slice(data);
+
+		let whole = self.joined.len() - self.joined.len() % self.frame_size;
+		self.partial.clear();
+		self.partial.extend_from_slice(&self.joined[whole..]);
+
+		&self.joined[..whole]
+	}
+
+	/// Drops any held back bytes, for example after a gap in the input.
+	pub fn clear(&mut self) {
+		self.partial.clear();
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::{Dither, FrameAssembler, SampleFormat};
+
+	fn decode(format: SampleFormat, data: &[u8]) -> Vec<f32> {
+		let mut samples = Vec::new();
+		format.decode_into(data, &mut samples);
+		samples
+	}
+
+	fn encode(format: SampleFormat, samples: &[f32]) -> Vec<u8> {
+		let mut data = Vec::new();
+		format.encode_into(samples, &mut data);
+		data
+	}
+
+	#[test]
+	fn parse() {
+		assert_eq!(Ok(SampleFormat::F32le), "f32le".parse());
+		assert_eq!(Ok(SampleFormat::S16le), "s16le".parse());
+		assert_eq!(
+			Err("invalid format 'foo', expected one of: f32le, s16le".to_string()),
+			"foo".parse::<SampleFormat>()
+		);
+	}
+
+	#[test]
+	fn f32le_round_trip() {
+		let samples = [0.0, 0.5, -0.25, 1.0, -1.0];
+		let bytes = encode(SampleFormat::F32le, &samples);
+		assert_eq!(samples.len() * 4, bytes.len());
+		assert_eq!(&samples, decode(SampleFormat::F32le, &bytes).as_slice());
+	}
+
+	#[test]
+	fn s16le_round_trip() {
+		let bytes = [i16::MIN, -1, 0, 1, 12345, i16::MAX]
+			.iter()
+			.flat_map(|v| v.to_le_bytes())
+			.collect::<Vec<_>>();
+
+		let samples = decode(SampleFormat::S16le, &bytes);
+		assert_eq!(-1.0, samples[0]);
+		assert!(samples.iter().all(|v| (-1.0..1.0).contains(v)));
+		assert_eq!(bytes, encode(SampleFormat::S16le, &samples));
+	}
+
+	#[test]
+	fn s16le_clamps() {
+		let bytes = encode(SampleFormat::S16le, &[1.5, -1.5]);
+		assert_eq!(i16::MAX.to_le_bytes(), bytes[..2]);
+		assert_eq!(i16::MIN.to_le_bytes(), bytes[2..]);
+	}
+
+	#[test]
+	fn dither_preserves_quiet_signals() {
+		// a quarter of a step always rounds to silence without dither
+		let input = vec![0.25 / 32768.0; 100_000];
+		let plain = decode(SampleFormat::S16le, &encode(SampleFormat::S16le, &input));
+		assert!(plain.iter().all(|&v| v == 0.0));
+
+		// with dither it survives on average, as noise within a step or two
+		let mut dithered = input.clone();
+		Dither::new().apply(&mut dithered);
+		let output = decode(SampleFormat::S16le, &encode(SampleFormat::S16le, &dithered));
+		let mean = output.iter().sum::<f32>() / output.len() as f32 * 32768.0;
+		assert!((mean - 0.25).abs() < 0.02, "mean {mean} steps");
+		assert!(output.iter().all(|v| (v * 32768.0).abs() <= 2.0));
+
+		// full scale stays in range
+		let mut loud = [1.0, -1.0].repeat(1000);
+		Dither::new().apply(&mut loud);
+		let output = decode(SampleFormat::S16le, &encode(SampleFormat::S16le, &loud));
+		assert!(output
+			.iter()
+			.all(|v| (-1.0..1.0).contains(v) && v.abs() > 0.999));
+	}
+
+	#[test]
+	fn reassembles_split_frames() {
+		let samples = (0..60).map(|i| i as f32 / 60.0).collect::<Vec<_>>();
+		let bytes = encode(SampleFormat::F32le, &samples);
+
+		// stereo f32 frames are 8 bytes, split anywhere but at frame boundaries
+		for split in [1, 3, 5, 7, 13] {
+			let mut assembler = FrameAssembler::new(8);
+			let mut decoded = Vec::new();
+
+			for block in bytes.chunks(split) {
+				let frames = assembler.push(block);
+				assert_eq!(0, frames.len() % 8, "split {split}");
+				decoded.extend(decode(SampleFormat::F32le, frames));
+			}
+
+			assert_eq!(samples, decoded, "split {split}");
+		}
+	}
+
+	#[test]
+	fn partial_sample_is_ignored() {
+		assert_eq!(vec![0.5], decode(SampleFormat::F32le, &[0, 0, 0, 63, 1, 2]));
+	}
+}
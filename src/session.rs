@@ -0,0 +1,1173 @@
+//! Connection to the pulseaudio server, driven by a threaded mainloop.
+//!
+//! All pulseaudio objects are shared with callbacks running on the mainloop
+//! thread, so every access from the main thread must hold the mainloop lock.
+
+use std::{
+	cell::{Cell, RefCell},
+	rc::Rc,
+	str::FromStr,
+	sync::Arc,
+	thread,
+	time::{Duration, Instant},
+};
+
+use pulse::{
+	callbacks::ListResult,
+	context::{self, Context},
+	def::BufferAttr,
+	mainloop::threaded::Mainloop,
+	operation::{self, Operation},
+	proplist::{self, Proplist},
+	sample::Spec,
+	stream::{self, Latency, PeekResult, Stream},
+	time::MicroSeconds,
+};
+use shoosh::duck::Ducker;
+
+use crate::{
+	backend::{Capture, Playback},
+	backlog::Backlog,
+	calibrate::Calibration,
+	control::Control,
+	error::ShooshError,
+	pipeline::Pipeline,
+	recorder::Recorder,
+	sample::SampleFormat,
+	shutdown,
+	stats::Stats,
+	wav::WavReader,
+	Config,
+};
+
+pub type Shared<T> = Rc<RefCell<T>>;
+
+/// Most output held back while the playback buffer is full, before the oldest
+/// is dropped to keep the latency down.
+pub const BACKLOG_LIMIT: Duration = Duration::from_secs(1);
+
+/// How often `--print-latency` prints the stream latency.
+const LATENCY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Holds the mainloop lock until dropped.
+struct MainloopLock<'a>(&'a Shared<Mainloop>);
+
+impl<'a> MainloopLock<'a> {
+	fn new(mainloop: &'a Shared<Mainloop>) -> Self {
+		mainloop.borrow_mut().lock();
+		Self(mainloop)
+	}
+}
+
+impl Drop for MainloopLock<'_> {
+	fn drop(&mut self) {
+		self.0.borrow_mut().unlock();
+	}
+}
+
+/// Returns a callback that wakes up the thread blocked in `Mainloop::wait`.
+fn signaller(mainloop: &Shared<Mainloop>) -> impl FnMut() + 'static {
+	let mainloop = Rc::clone(mainloop);
+
+	// SAFETY: this runs on the mainloop thread while the waiting thread holds a
+	// `RefCell` borrow across `wait()`, so it cannot go through `borrow_mut`.
+	// The mainloop lock is held whenever callbacks run.
+	move || unsafe { (*mainloop.as_ptr()).signal(false) }
+}
+
+/// Creates and starts a threaded mainloop.
+fn start_mainloop() -> Result<Shared<Mainloop>, ShooshError> {
+	let mainloop = Rc::new(RefCell::new(Mainloop::new().ok_or(ShooshError::MainloopCreate)?));
+	mainloop
+		.borrow_mut()
+		.start()
+		.map_err(ShooshError::MainloopStart)?;
+
+	Ok(mainloop)
+}
+
+/// Waits until `operation` is no longer running.
+///
+/// The operation's callback must signal the mainloop. Must be called with the
+/// mainloop lock held.
+fn wait_for_operation<T: ?Sized>(mainloop: &Shared<Mainloop>, operation: &Operation<T>) {
+	while operation.get_state() == operation::State::Running {
+		mainloop.borrow_mut().wait();
+	}
+}
+
+/// Creates a context and waits for it to connect to the server.
+///
+/// Connects to `server`, or the default server if `None`. Must be called with
+/// the mainloop lock held.
+fn connect_context(
+	mainloop: &Shared<Mainloop>,
+	server: Option<&str>,
+) -> Result<Shared<Context>, ShooshError> {
+	let mut proplist = Proplist::new().ok_or(ShooshError::ContextCreate)?;
+	proplist
+		.set_str(proplist::properties::APPLICATION_NAME, "Shoosh")
+		.map_err(|_| ShooshError::ContextCreate)?;
+
+	let context = Rc::new(RefCell::new(
+		Context::new_with_proplist(&*mainloop.borrow(), "Shoosh", &proplist)
+			.ok_or(ShooshError::ContextCreate)?,
+	));
+
+	context
+		.borrow_mut()
+		.set_state_callback(Some(Box::new(signaller(mainloop))));
+
+	context
+		.borrow_mut()
+		.connect(server, context::FlagSet::NOFLAGS, None)
+		.map_err(ShooshError::ContextConnect)?;
+
+	// wait for context
+	loop {
+		let state = context.borrow().get_state();
+		match state {
+			context::State::Ready => {
+				debug!("connected to {}", server.unwrap_or("the default server"));
+				return Ok(context)
+			}
+			context::State::Failed | context::State::Terminated => {
+				let error = context.borrow().errno();
+				context.borrow_mut().set_state_callback(None);
+				return Err(ShooshError::ContextConnect(error))
+			}
+			_ => mainloop.borrow_mut().wait(),
+		}
+	}
+}
+
+/// Prints the name and description of every source and sink on the server.
+pub fn list_devices(server: Option<&str>) -> Result<(), ShooshError> {
+	let mainloop = start_mainloop()?;
+
+	let result = {
+		let _lock = MainloopLock::new(&mainloop);
+		list_devices_locked(&mainloop, server)
+	};
+
+	mainloop.borrow_mut().stop();
+	result
+}
+
+fn list_devices_locked(
+	mainloop: &Shared<Mainloop>,
+	server: Option<&str>,
+) -> Result<(), ShooshError> {
+	let context = connect_context(mainloop, server)?;
+	let introspect = context.borrow().introspect();
+
+	println!("sources:");
+	let mut signal = signaller(mainloop);
+	let operation = introspect.get_source_info_list(move |result| match result {
+		ListResult::Item(info) => print_device(info.name.as_deref(), info.description.as_deref()),
+		ListResult::Error => {
+			error!("Failed to list sources");
+			signal();
+		}
+		ListResult::End => signal(),
+	});
+	wait_for_operation(mainloop, &operation);
+
+	println!("sinks:");
+	let mut signal = signaller(mainloop);
+	let operation = introspect.get_sink_info_list(move |result| match result {
+		ListResult::Item(info) => print_device(info.name.as_deref(), info.description.as_deref()),
+		ListResult::Error => {
+			error!("Failed to list sinks");
+			signal();
+		}
+		ListResult::End => signal(),
+	});
+	wait_for_operation(mainloop, &operation);
+
+	context.borrow_mut().set_state_callback(None);
+	context.borrow_mut().disconnect();
+	Ok(())
+}
+
+fn print_device(name: Option<&str>, description: Option<&str>) {
+	println!("  {}\t{}", name.unwrap_or("<unnamed>"), description.unwrap_or(""));
+}
+
+/// Returns true if `recording_stream` records the monitor of the sink
+/// `playback_stream` plays to, feeding the output straight back into the
+/// input.
+///
+/// Must be called with the mainloop lock held.
+fn is_feedback_loop(
+	mainloop: &Shared<Mainloop>,
+	context: &Shared<Context>,
+	playback_stream: &Stream,
+	recording_stream: &Stream,
+) -> bool {
+	let (Some(sink), Some(source)) =
+		(playback_stream.get_device_index(), recording_stream.get_device_index())
+	else {
+		return false
+	};
+
+	let monitor_of = Rc::new(Cell::new(None));
+	let introspect = context.borrow().introspect();
+
+	let mut signal = signaller(mainloop);
+	let result = Rc::clone(&monitor_of);
+	let operation = introspect.get_source_info_by_index(source, move |item| match item {
+		ListResult::Item(info) => result.set(info.monitor_of_sink),
+		ListResult::Error => {
+			warn!("Failed to look up the source, skipping the feedback check");
+			signal();
+		}
+		ListResult::End => signal(),
+	});
+	wait_for_operation(mainloop, &operation);
+
+	monitor_of.get() == Some(sink)
+}
+
+/// What to do when the server reports a hole in the recorded data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HoleMode {
+	/// Play back silence for the length of the hole, keeping playback aligned
+	/// with capture
+	Silence,
+	/// Drop the hole
+	Skip,
+}
+
+impl HoleMode {
+	/// Names accepted by `from_str`.
+	pub const NAMES: [&'static str; 2] = ["silence", "skip"];
+
+	/// Returns the name `from_str` accepts for this mode.
+	pub fn name(self) -> &'static str {
+		match self {
+			Self::Silence => "silence",
+			Self::Skip => "skip",
+		}
+	}
+}
+
+impl FromStr for HoleMode {
+	type Err = String;
+
+	fn from_str(name: &str) -> Result<Self, String> {
+		match name {
+			"silence" => Ok(Self::Silence),
+			"skip" => Ok(Self::Skip),
+			_ => Err(format!(
+				"invalid hole mode '{name}', expected one of: {}",
+				Self::NAMES.join(", ")
+			)),
+		}
+	}
+}
+
+/// A connection to the server with ready playback and recording streams.
+///
+/// There is no recording stream when the input is read from a file, and a
+/// sidechain stream only with `--sidechain`.
+pub struct Session {
+	playback_stream: Shared<Stream>,
+	recording_stream: Option<Shared<Stream>>,
+	sidechain_stream: Option<Shared<Stream>>,
+	context: Shared<Context>,
+	mainloop: Shared<Mainloop>,
+}
+
+impl Session {
+	/// Connects to the server and waits for both streams to become ready.
+	pub fn connect(config: &Config) -> Result<Self, ShooshError> {
+		let mainloop = start_mainloop()?;
+
+		let result = {
+			let _lock = MainloopLock::new(&mainloop);
+			Self::connect_locked(&mainloop, config)
+		};
+
+		match result {
+			Ok(session) => Ok(session),
+			Err(e) => {
+				mainloop.borrow_mut().stop();
+				Err(e)
+			}
+		}
+	}
+
+	fn connect_locked(mainloop: &Shared<Mainloop>, config: &Config) -> Result<Self, ShooshError> {
+		let Config { spec, .. } = *config;
+		let (playback_buffer, recording_buffer) = config.buffer_sizes();
+		let playback_spec = Spec {
+			channels: config.output_channels(),
+			..spec
+		};
+
+		let context = connect_context(mainloop, config.server.as_deref())?;
+
+		let playback_stream = Rc::new(RefCell::new(
+			Stream::new(&mut context.borrow_mut(), "Shoosh sink", &playback_spec, None)
+				.ok_or(ShooshError::StreamCreate("playback"))?,
+		));
+
+		let recording_stream = match config.input_file {
+			Some(_) => None,
+			None => Some(Rc::new(RefCell::new(
+				Stream::new(&mut context.borrow_mut(), "Shoosh source", &spec, None)
+					.ok_or(ShooshError::StreamCreate("recording"))?,
+			))),
+		};
+
+		let sidechain_stream = match config.sidechain {
+			Some(_) => Some(Rc::new(RefCell::new(
+				Stream::new(&mut context.borrow_mut(), "Shoosh sidechain", &spec, None)
+					.ok_or(ShooshError::StreamCreate("sidechain"))?,
+			))),
+			None => None,
+		};
+
+		let streams = [(&playback_stream, "playback")]
+			.into_iter()
+			.chain(
+				recording_stream
+					.as_ref()
+					.map(|stream| (stream, "recording")),
+			)
+			.chain(
+				sidechain_stream
+					.as_ref()
+					.map(|stream| (stream, "sidechain")),
+			)
+			.collect::<Vec<_>>();
+
+		for (stream, _) in &streams {
+			stream
+				.borrow_mut()
+				.set_state_callback(Some(Box::new(signaller(mainloop))));
+		}
+
+		// latency can only be queried once the server sends timing updates
+		let flags = if config.print_latency {
+			stream::FlagSet::AUTO_TIMING_UPDATE | stream::FlagSet::INTERPOLATE_TIMING
+		} else {
+			stream::FlagSet::empty()
+		};
+
+		// When a device disappears the server moves its streams to the default
+		// device. When reconnecting, fail instead so the next session waits for
+		// a named device to come back.
+		let dont_move = |device: &Option<String>| {
+			if config.reconnect && device.is_some() {
+				stream::FlagSet::DONT_MOVE
+			} else {
+				stream::FlagSet::empty()
+			}
+		};
+
+		for &(stream, name) in &streams {
+			stream
+				.borrow_mut()
+				.set_moved_callback(Some(Box::new(move || {
+					info!("{name} stream moved to another device");
+				})));
+		}
+
+		playback_stream
+			.borrow_mut()
+			.connect_playback(
+				config.sink.as_deref(),
+				Some(&BufferAttr {
+					maxlength: u32::MAX,
+					tlength: playback_buffer,
+					prebuf: u32::MAX,
+					minreq: u32::MAX,
+					fragsize: 0,
+				}),
+				flags | dont_move(&config.sink),
+				None,
+				None,
+			)
+			.map_err(|e| ShooshError::StreamConnect("playback", e))?;
+
+		if let Some(recording_stream) = &recording_stream {
+			recording_stream
+				.borrow_mut()
+				.connect_record(
+					config.source.as_deref(),
+					Some(&BufferAttr {
+						maxlength: u32::MAX,
+						tlength: 0,
+						prebuf: 0,
+						minreq: 0,
+						fragsize: recording_buffer,
+					}),
+					flags | dont_move(&config.source),
+				)
+				.map_err(|e| ShooshError::StreamConnect("recording", e))?;
+		}
+
+		if let Some(sidechain_stream) = &sidechain_stream {
+			sidechain_stream
+				.borrow_mut()
+				.connect_record(
+					config.sidechain.as_deref(),
+					Some(&BufferAttr {
+						maxlength: u32::MAX,
+						tlength: 0,
+						prebuf: 0,
+						minreq: 0,
+						fragsize: recording_buffer,
+					}),
+					flags | dont_move(&config.sidechain),
+				)
+				.map_err(|e| ShooshError::StreamConnect("sidechain", e))?;
+		}
+
+		// wait for streams
+		'wait_streams: loop {
+			for &(stream, name) in &streams {
+				let state = stream.borrow().get_state();
+				match state {
+					stream::State::Ready => {}
+					stream::State::Failed | stream::State::Terminated =>
+						return Err(ShooshError::StreamConnect(name, context.borrow().errno())),
+					_ => {
+						mainloop.borrow_mut().wait();
+						continue 'wait_streams
+					}
+				}
+			}
+
+			break
+		}
+
+		debug!("streams ready");
+		let stream = recording_stream.as_ref().unwrap_or(&playback_stream);
+		if let Some(spec) = stream.borrow_mut().get_sample_spec() {
+			info!("channels: {}", spec.channels);
+		}
+		drop(streams);
+
+		if let Some(recording_stream) = &recording_stream {
+			let feedback = is_feedback_loop(
+				mainloop,
+				&context,
+				&playback_stream.borrow(),
+				&recording_stream.borrow(),
+			);
+
+			match feedback {
+				false => {}
+				true if config.force => warn!("the source monitors the sink, expect feedback"),
+				true => return Err(ShooshError::Feedback),
+			}
+		}
+
+		if let Some(sidechain_stream) = &sidechain_stream {
+			let feedback = is_feedback_loop(
+				mainloop,
+				&context,
+				&playback_stream.borrow(),
+				&sidechain_stream.borrow(),
+			);
+
+			if feedback {
+				warn!("the sidechain monitors the sink, the output will duck itself");
+			}
+		}
+
+		Ok(Self {
+			playback_stream,
+			recording_stream,
+			sidechain_stream,
+			context,
+			mainloop: Rc::clone(mainloop),
+		})
+	}
+
+	/// Processes audio until shutdown is requested, the input file ends or
+	/// the connection fails.
+	///
+	/// Processing happens in the recording stream's read callback, or the
+	/// playback stream's write callback when reading `input`, on the mainloop
+	/// thread while this thread watches for failures. The sidechain stream's
+	/// read callback, if any, drives the pipeline's ducker. Everything played
+	/// back is also written to `recorder`, if given, and `control` can change
+	/// the volume cap while running.
+	pub fn process(
+		&mut self,
+		config: &Config,
+		recorder: Option<Shared<Recorder>>,
+		input: Option<Shared<WavReader>>,
+		control: Option<Arc<Control>>,
+	) -> Result<(), ShooshError> {
+		let error = Rc::new(RefCell::new(None));
+		let finished = Rc::new(Cell::new(false));
+		let underruns = Rc::new(Cell::new(0));
+		let overruns = Rc::new(Cell::new(0));
+		let stats = config
+			.stats
+			.map(|interval| Rc::new(RefCell::new(Stats::new(interval))));
+
+		{
+			let _lock = MainloopLock::new(&self.mainloop);
+
+			let underruns = Rc::clone(&underruns);
+			let underrun_stats = stats.clone();
+			let underrun_control = control.clone();
+			self.playback_stream
+				.borrow_mut()
+				.set_underflow_callback(Some(Box::new(move || {
+					underruns.set(underruns.get() + 1);
+					warn!("playback underrun");
+					if let Some(stats) = &underrun_stats {
+						stats.borrow_mut().underrun();
+					}
+					if let Some(control) = &underrun_control {
+						control.count_underrun();
+					}
+				})));
+
+			if let Some(recording_stream) = &self.recording_stream {
+				let overruns = Rc::clone(&overruns);
+				let overrun_stats = stats.clone();
+				let overrun_control = control.clone();
+				recording_stream
+					.borrow_mut()
+					.set_overflow_callback(Some(Box::new(move || {
+						overruns.set(overruns.get() + 1);
+						warn!("recording overrun");
+						if let Some(stats) = &overrun_stats {
+							stats.borrow_mut().overrun();
+						}
+						if let Some(control) = &overrun_control {
+							control.count_overrun();
+						}
+					})));
+			}
+
+			let mut pipeline = Pipeline::new(config, recorder, stats.clone(), control);
+
+			if let (Some(sidechain_stream), Some(ducker)) =
+				(&self.sidechain_stream, pipeline.ducker())
+			{
+				let format = config.format;
+				let sidechain = Rc::clone(sidechain_stream);
+				let error = Rc::clone(&error);
+				let mut samples = Vec::new();
+
+				sidechain_stream
+					.borrow_mut()
+					.set_read_callback(Some(Box::new(move |_| {
+						let result = listen_sidechain(
+							format,
+							&mut samples,
+							&mut ducker.borrow_mut(),
+							&mut *sidechain.borrow_mut(),
+						);
+
+						if let Err(e) = result {
+							error.borrow_mut().get_or_insert(e);
+						}
+					})));
+			}
+
+			let playback_stream = Rc::clone(&self.playback_stream);
+			let error = Rc::clone(&error);
+
+			match (&self.recording_stream, input) {
+				(Some(recording_stream), _) => {
+					let on_hole = config.on_hole;
+					let recording = Rc::clone(recording_stream);
+					let frame_size = config.format.size() * config.output_channels() as usize;
+					let limit = (BACKLOG_LIMIT.as_secs_f32() * config.spec.rate as f32) as usize
+						* frame_size;
+					let mut backlog = Backlog::new(frame_size, limit);
+					let read_stats = stats.clone();
+
+					recording_stream
+						.borrow_mut()
+						.set_read_callback(Some(Box::new(move |_| {
+							let result = process_recorded(
+								&mut pipeline,
+								&mut backlog,
+								on_hole,
+								&mut *recording.borrow_mut(),
+								&mut *playback_stream.borrow_mut(),
+							);
+
+							match result {
+								Ok(true) => {}
+								Ok(false) =>
+									if let Some(stats) = &read_stats {
+										stats.borrow_mut().empty_read();
+									},
+								Err(e) => {
+									error.borrow_mut().get_or_insert(e);
+								}
+							}
+						})));
+				}
+				(None, Some(input)) => {
+					let finished = Rc::clone(&finished);
+					let mut buffer = Vec::new();
+					let playback = Rc::clone(&playback_stream);
+
+					playback_stream
+						.borrow_mut()
+						.set_write_callback(Some(Box::new(move |size| {
+							if finished.get() {
+								return
+							}
+
+							let result = process_file(
+								&mut pipeline,
+								&mut input.borrow_mut(),
+								&mut buffer,
+								size,
+								&mut *playback.borrow_mut(),
+							);
+
+							match result {
+								Ok(more) => finished.set(!more),
+								Err(e) => {
+									error.borrow_mut().get_or_insert(e);
+								}
+							}
+						})));
+				}
+				(None, None) => unreachable!("session without a recording stream or input file"),
+			}
+		}
+
+		let mut latency_reported = Instant::now();
+
+		while !shutdown::requested() {
+			thread::sleep(config.idle_sleep);
+			let _lock = MainloopLock::new(&self.mainloop);
+
+			if let Some(e) = error.borrow_mut().take() {
+				return Err(e)
+			}
+
+			if finished.get() {
+				break
+			}
+
+			if let Some(stats) = &stats {
+				let mut stats = stats.borrow_mut();
+				stats.wakeup();
+				stats.report_if_due();
+			}
+
+			if config.print_latency && latency_reported.elapsed() >= LATENCY_INTERVAL {
+				self.print_latency(config);
+				latency_reported = Instant::now();
+			}
+
+			if let Some(e) = self.failure() {
+				return Err(e)
+			}
+		}
+
+		let _lock = MainloopLock::new(&self.mainloop);
+
+		// stop reading input and let queued audio play out before disconnecting
+		if let Some(recording_stream) = &self.recording_stream {
+			let mut recording_stream = recording_stream.borrow_mut();
+			recording_stream.set_read_callback(None);
+			recording_stream.set_overflow_callback(None);
+			recording_stream.disconnect()?;
+		}
+		self.disconnect_sidechain()?;
+		self.playback_stream.borrow_mut().set_write_callback(None);
+		// the buffer running empty while draining is expected
+		self.playback_stream
+			.borrow_mut()
+			.set_underflow_callback(None);
+
+		if underruns.get() > 0 || overruns.get() > 0 {
+			warn!(
+				"{} underruns and {} overruns this session, frequent ones usually mean --latency \
+				 is too low",
+				underruns.get(),
+				overruns.get(),
+			);
+		}
+
+		debug!("draining playback");
+		let mut signal = signaller(&self.mainloop);
+		let drain = self
+			.playback_stream
+			.borrow_mut()
+			.drain(Some(Box::new(move |_| signal())));
+		wait_for_operation(&self.mainloop, &drain);
+
+		self.playback_stream.borrow_mut().disconnect()?;
+		self.context.borrow_mut().disconnect();
+
+		Ok(())
+	}
+
+	/// Returns the error if the context or a stream has failed, as a stream
+	/// does when its device disappears and it can't be moved. Must be called
+	/// with the mainloop lock held.
+	fn failure(&self) -> Option<ShooshError> {
+		let state = self.context.borrow().get_state();
+		if let context::State::Failed | context::State::Terminated = state {
+			return Some(ShooshError::ContextConnect(self.context.borrow().errno()))
+		}
+
+		let streams = [
+			(Some(&self.playback_stream), "playback"),
+			(self.recording_stream.as_ref(), "recording"),
+			(self.sidechain_stream.as_ref(), "sidechain"),
+		];
+		for (stream, name) in streams {
+			let state = stream.map(|stream| stream.borrow().get_state());
+			if let Some(stream::State::Failed | stream::State::Terminated) = state {
+				return Some(ShooshError::StreamFailed(name, self.context.borrow().errno()))
+			}
+		}
+
+		None
+	}
+
+	/// Prints the time from audio being recorded to it being played back,
+	/// once both streams have timing information.
+	fn print_latency(&self, config: &Config) {
+		let playback = stream_latency(&self.playback_stream.borrow());
+		let recording = match &self.recording_stream {
+			Some(recording_stream) => stream_latency(&recording_stream.borrow()),
+			None => Some(Duration::ZERO),
+		};
+		let lookahead = Duration::from_secs_f32(config.limiter.lookahead_ms / 1000.0);
+
+		if let (Some(playback), Some(recording)) = (playback, recording) {
+			let ms = |duration: Duration| duration.as_secs_f32() * 1000.0;
+			println!(
+				"latency {:.1} ms | recording {:.1} ms | lookahead {:.1} ms | playback {:.1} ms",
+				ms(recording + lookahead + playback),
+				ms(recording),
+				ms(lookahead),
+				ms(playback),
+			);
+		}
+	}
+
+	/// Feeds the recording stream into `calibration` for `duration`, or until
+	/// shutdown is requested. Nothing is played back.
+	pub fn calibrate(
+		&mut self,
+		config: &Config,
+		calibration: &Shared<Calibration>,
+		duration: Duration,
+	) -> Result<(), ShooshError> {
+		let Some(recording_stream) = &self.recording_stream else {
+			unreachable!("calibrating a session without a recording stream")
+		};
+
+		let error = Rc::new(RefCell::new(None));
+		let started = Instant::now();
+
+		{
+			let _lock = MainloopLock::new(&self.mainloop);
+
+			let format = config.format;
+			let calibration = Rc::clone(calibration);
+			let recording = Rc::clone(recording_stream);
+			let error = Rc::clone(&error);
+			let mut samples = Vec::new();
+
+			recording_stream
+				.borrow_mut()
+				.set_read_callback(Some(Box::new(move |_| {
+					let result = calibrate_recorded(
+						format,
+						&mut samples,
+						&mut calibration.borrow_mut(),
+						&mut *recording.borrow_mut(),
+					);
+
+					if let Err(e) = result {
+						error.borrow_mut().get_or_insert(e);
+					}
+				})));
+		}
+
+		while !shutdown::requested() && started.elapsed() < duration {
+			thread::sleep(config.idle_sleep);
+			let _lock = MainloopLock::new(&self.mainloop);
+
+			if let Some(e) = error.borrow_mut().take() {
+				return Err(e)
+			}
+
+			if let Some(e) = self.failure() {
+				return Err(e)
+			}
+		}
+
+		let _lock = MainloopLock::new(&self.mainloop);
+
+		let mut recording_stream = recording_stream.borrow_mut();
+		recording_stream.set_read_callback(None);
+		recording_stream.disconnect()?;
+		self.disconnect_sidechain()?;
+		self.playback_stream.borrow_mut().disconnect()?;
+		self.context.borrow_mut().disconnect();
+
+		Ok(())
+	}
+
+	/// Stops listening to the sidechain, if there is one. Must be called with
+	/// the mainloop lock held.
+	fn disconnect_sidechain(&self) -> Result<(), ShooshError> {
+		if let Some(sidechain_stream) = &self.sidechain_stream {
+			let mut sidechain_stream = sidechain_stream.borrow_mut();
+			sidechain_stream.set_read_callback(None);
+			sidechain_stream.disconnect()?;
+		}
+
+		Ok(())
+	}
+}
+
+impl Drop for Session {
+	fn drop(&mut self) {
+		// the mainloop thread must not touch anything while it is torn down
+		self.mainloop.borrow_mut().stop();
+
+		// callbacks hold references to the mainloop and streams
+		self.playback_stream.borrow_mut().set_write_callback(None);
+		self.playback_stream
+			.borrow_mut()
+			.set_underflow_callback(None);
+		self.playback_stream.borrow_mut().set_state_callback(None);
+		if let Some(recording_stream) = &self.recording_stream {
+			recording_stream.borrow_mut().set_read_callback(None);
+			recording_stream.borrow_mut().set_overflow_callback(None);
+			recording_stream.borrow_mut().set_state_callback(None);
+		}
+		if let Some(sidechain_stream) = &self.sidechain_stream {
+			sidechain_stream.borrow_mut().set_read_callback(None);
+			sidechain_stream.borrow_mut().set_state_callback(None);
+		}
+		self.context.borrow_mut().set_state_callback(None);
+	}
+}
+
+/// Processes all data available on `recording_stream`, writing the result
+/// to `playback_stream` through `backlog`, and returns false if there was
+/// none.
+///
+/// This runs from the read callback, so running out of data returns to the
+/// mainloop, which sleeps in `poll` until the server sends more. An idle
+/// session only wakes for the main thread's `--idle-sleep` checks, and for
+/// any callbacks that find nothing to read, both of which `--stats` counts.
+pub fn process_recorded(
+	pipeline: &mut Pipeline,
+	backlog: &mut Backlog,
+	on_hole: HoleMode,
+	recording_stream: &mut impl Capture,
+	playback_stream: &mut impl Playback,
+) -> Result<bool, ShooshError> {
+	let mut read = false;
+
+	loop {
+		match recording_stream.peek()? {
+			// there may be room for held back output by now
+			PeekResult::Empty => {
+				play(backlog, &[], playback_stream)?;
+				return Ok(read)
+			}
+			PeekResult::Hole(size) => {
+				if on_hole == HoleMode::Silence {
+					let silence = pipeline.silence(size)?;
+					play(backlog, silence, playback_stream)?;
+				}
+
+				recording_stream.discard()?;
+				// the gap makes the volume history stale
+				pipeline.reset();
+			}
+			PeekResult::Data(data) => {
+				let output = pipeline.process(data)?;
+				play(backlog, output, playback_stream)?;
+				recording_stream.discard()?;
+			}
+		}
+
+		read = true;
+	}
+}
+
+/// Writes `data` to `playback_stream` behind any held back output, holding
+/// back whatever doesn't fit in its buffer.
+fn play(
+	backlog: &mut Backlog,
+	data: &[u8],
+	playback_stream: &mut impl Playback,
+) -> Result<(), ShooshError> {
+	// unknown only if the stream failed, which the main thread picks up
+	let writable = playback_stream.writable_size().unwrap_or(0);
+	let dropped = backlog.write(data, writable, |data| playback_stream.write(data))?;
+
+	if dropped > 0 {
+		warn!("playback can't keep up, dropped {dropped} bytes of output");
+	}
+	if backlog.len() > 0 {
+		trace!("playback buffer full, holding back {} bytes", backlog.len());
+	}
+
+	Ok(())
+}
+
+/// Returns the latency of `stream`, or `None` if no timing information has
+/// arrived yet.
+fn stream_latency(stream: &Stream) -> Option<Duration> {
+	match stream.get_latency() {
+		Ok(Latency::Positive(MicroSeconds(us))) => Some(Duration::from_micros(us)),
+		// a recording stream reads ahead of the source while catching up
+		Ok(Latency::Negative(_)) => Some(Duration::ZERO),
+		Ok(Latency::None) | Err(_) => None,
+	}
+}
+
+/// Adds all data available on `recording_stream` to `calibration`.
+fn calibrate_recorded(
+	format: SampleFormat,
+	samples: &mut Vec<f32>,
+	calibration: &mut Calibration,
+	recording_stream: &mut impl Capture,
+) -> Result<(), ShooshError> {
+	loop {
+		match recording_stream.peek()? {
+			PeekResult::Empty => return Ok(()),
+			PeekResult::Hole(_) => recording_stream.discard()?,
+			PeekResult::Data(data) => {
+				format.decode_into(data, samples);
+				calibration.add(samples);
+				recording_stream.discard()?;
+			}
+		}
+	}
+}
+
+/// Feeds all data available on `sidechain_stream` into `ducker`.
+fn listen_sidechain(
+	format: SampleFormat,
+	samples: &mut Vec<f32>,
+	ducker: &mut Ducker,
+	sidechain_stream: &mut impl Capture,
+) -> Result<(), ShooshError> {
+	loop {
+		match sidechain_stream.peek()? {
+			PeekResult::Empty => return Ok(()),
+			PeekResult::Hole(_) => sidechain_stream.discard()?,
+			PeekResult::Data(data) => {
+				format.decode_into(data, samples);
+				ducker.listen(samples);
+				sidechain_stream.discard()?;
+			}
+		}
+	}
+}
+
+/// Reads enough of `input` for `size` bytes of output and writes the processed
+/// result to `playback_stream`, returning false once the input is exhausted.
+fn process_file(
+	pipeline: &mut Pipeline,
+	input: &mut WavReader,
+	buffer: &mut Vec<u8>,
+	size: usize,
+	playback_stream: &mut impl Playback,
+) -> Result<bool, ShooshError> {
+	buffer.resize(pipeline.input_size(size), 0);
+	let read = input.read(buffer).map_err(ShooshError::InputFile)?;
+	if read == 0 {
+		return Ok(false)
+	}
+
+	let output = pipeline.process(&buffer[..read])?;
+	playback_stream.write(output)?;
+
+	Ok(true)
+}
+
+#[cfg(test)]
+mod test {
+	use shoosh::processor::VolumeCapSettings;
+
+	use super::{process_recorded, HoleMode};
+	use crate::{
+		backend::mock::{Fragment, MockCapture, MockPlayback},
+		backlog::Backlog,
+		pipeline::Pipeline,
+		sample::SampleFormat,
+		Config,
+	};
+
+	fn config(on_hole: HoleMode) -> Config {
+		Config {
+			on_hole,
+			limiter: VolumeCapSettings {
+				volume_cap: 0.5,
+				..Default::default()
+			},
+			..Default::default()
+		}
+	}
+
+	/// Encodes a stereo block of `frames` frames at a constant `level`.
+	fn block(level: f32, frames: usize) -> Vec<u8> {
+		let mut data = Vec::new();
+		SampleFormat::F32le.encode_into(&[level, -level].repeat(frames), &mut data);
+		data
+	}
+
+	fn decode(data: &[u8]) -> Vec<f32> {
+		let mut samples = Vec::new();
+		SampleFormat::F32le.decode_into(data, &mut samples);
+		samples
+	}
+
+	/// Runs the read callback's loop until the capture is drained.
+	fn run(
+		config: &Config,
+		capture: &mut MockCapture,
+		playback: &mut MockPlayback,
+		backlog: &mut Backlog,
+	) -> Vec<f32> {
+		let mut pipeline = Pipeline::new(config, None, None, None);
+		process_recorded(&mut pipeline, backlog, config.on_hole, capture, playback).unwrap();
+		decode(&playback.written)
+	}
+
+	#[test]
+	fn limits_from_the_first_block() {
+		let config = config(HoleMode::Silence);
+		let mut capture = MockCapture::new((0..8).map(|_| Fragment::Data(block(1.0, 512))));
+		let mut playback = MockPlayback::new(usize::MAX);
+		let output = run(&config, &mut capture, &mut playback, &mut Backlog::new(8, 1 << 20));
+
+		assert_eq!(8 * 512 * 2, output.len());
+		// the gain ramps down over the first chunk, then the output sits at the cap
+		let first_chunk = config.limiter.chunk_size;
+		assert!(output[..first_chunk].iter().any(|v| v.abs() > 0.5));
+		assert!(output[first_chunk..]
+			.iter()
+			.all(|v| (v.abs() - 0.5).abs() < 1e-6));
+	}
+
+	#[test]
+	fn holes() {
+		let fragments = || {
+			[
+				Fragment::Data(block(0.25, 64)),
+				Fragment::Hole(100 * 8),
+				Fragment::Data(block(0.25, 64)),
+			]
+		};
+
+		let silence = config(HoleMode::Silence);
+		let mut playback = MockPlayback::new(usize::MAX);
+		let backlog = &mut Backlog::new(8, 1 << 20);
+		let output = run(&silence, &mut MockCapture::new(fragments()), &mut playback, backlog);
+		assert_eq!((64 + 100 + 64) * 2, output.len());
+		assert!(output[128..328].iter().all(|&v| v == 0.0));
+		assert_eq!([0.25, -0.25].repeat(64), output[328..]);
+
+		let skip = config(HoleMode::Skip);
+		let mut playback = MockPlayback::new(usize::MAX);
+		let backlog = &mut Backlog::new(8, 1 << 20);
+		let output = run(&skip, &mut MockCapture::new(fragments()), &mut playback, backlog);
+		assert_eq!([0.25, -0.25].repeat(128), output);
+	}
+
+	#[test]
+	fn holes_clear_the_lookahead() {
+		// half a chunk of lookahead
+		let mut config = config(HoleMode::Skip);
+		config.limiter.lookahead_ms = 1000.0 * 32.0 / 44100.0;
+		let fragments = [
+			Fragment::Data(block(1.0, 64)),
+			Fragment::Hole(100 * 8),
+			Fragment::Data(block(0.25, 128)),
+		];
+
+		let mut playback = MockPlayback::new(usize::MAX);
+		let backlog = &mut Backlog::new(8, 1 << 20);
+		let output = run(&config, &mut MockCapture::new(fragments), &mut playback, backlog);
+		assert_eq!((64 + 128) * 2, output.len());
+		// nothing from before the hole is delayed past it, and the quiet block
+		// starts over at unity gain
+		assert!(output[128..192].iter().all(|&v| v == 0.0));
+		assert_eq!([0.25, -0.25].repeat(96), output[192..]);
+	}
+
+	#[test]
+	fn backpressure() {
+		let config = config(HoleMode::Silence);
+		let mut pipeline = Pipeline::new(&config, None, None, None);
+		let mut backlog = Backlog::new(8, 1 << 20);
+
+		// room for half a block, nothing is written past it
+		let mut capture = MockCapture::new([Fragment::Data(block(0.25, 64))]);
+		let mut playback = MockPlayback::new(32 * 8);
+		process_recorded(&mut pipeline, &mut backlog, config.on_hole, &mut capture, &mut playback)
+			.unwrap();
+		assert_eq!(32 * 8, playback.written.len());
+		assert_eq!(32 * 8, backlog.len());
+
+		// once it plays, the rest goes out ahead of the next block
+		playback.play(usize::MAX / 2);
+		capture.push(Fragment::Data(block(0.125, 64)));
+		process_recorded(&mut pipeline, &mut backlog, config.on_hole, &mut capture, &mut playback)
+			.unwrap();
+		let output = decode(&playback.written);
+		assert_eq!([0.25, -0.25].repeat(64), output[..128]);
+		assert_eq!([0.125, -0.125].repeat(64), output[128..]);
+	}
+
+	#[test]
+	fn parse_hole_mode() {
+		assert_eq!(Ok(HoleMode::Silence), "silence".parse());
+		assert_eq!(Ok(HoleMode::Skip), "skip".parse());
+		assert_eq!(
+			Err("invalid hole mode 'drop', expected one of: silence, skip".to_string()),
+			"drop".parse::<HoleMode>()
+		);
+	}
+
+	#[test]
+	fn reports_empty_reads() {
+		let config = config(HoleMode::Silence);
+		let mut pipeline = Pipeline::new(&config, None, None, None);
+		let mut backlog = Backlog::new(8, 1 << 20);
+		let mut playback = MockPlayback::new(usize::MAX);
+
+		let mut capture = MockCapture::new([]);
+		let read = process_recorded(
+			&mut pipeline,
+			&mut backlog,
+			config.on_hole,
+			&mut capture,
+			&mut playback,
+		);
+		assert!(!read.unwrap());
+
+		capture.push(Fragment::Hole(8));
+		let read = process_recorded(
+			&mut pipeline,
+			&mut backlog,
+			config.on_hole,
+			&mut capture,
+			&mut playback,
+		);
+		assert!(read.unwrap());
+	}
+}
@@ -0,0 +1,64 @@
+//! Shutdown requests from `SIGINT`/`SIGTERM`.
+//!
+//! The handler only sets a flag. When the processing loop sees it, shoosh stops
+//! reading from the recording stream, drains the playback stream so queued
+//! audio plays out, disconnects both streams and the context, then returns from
+//! `run()`.
+//!
+//! A deadline set with `--duration` requests shutdown the same way once it
+//! passes.
+
+use std::{
+	sync::{
+		atomic::{AtomicBool, Ordering},
+		OnceLock,
+	},
+	time::{Duration, Instant},
+};
+
+static SHUTDOWN_REQUESTED: AtomicBool = AtomicBool::new(false);
+static DEADLINE: OnceLock<Instant> = OnceLock::new();
+
+extern "C" fn handle_signal(_: libc::c_int) {
+	SHUTDOWN_REQUESTED.store(true, Ordering::Relaxed);
+}
+
+/// Installs the `SIGINT` and `SIGTERM` handlers.
+pub fn install_handler() {
+	let handler = handle_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+
+	// SAFETY: the handler only performs an atomic store, which is
+	// async-signal-safe.
+	unsafe {
+		libc::signal(libc::SIGINT, handler);
+		libc::signal(libc::SIGTERM, handler);
+	}
+}
+
+/// Requests shutdown once `duration` has passed. Only the first deadline set
+/// takes effect.
+pub fn set_deadline(duration: Duration) {
+	let _ = DEADLINE.set(Instant::now() + duration);
+}
+
+/// Returns true once a shutdown signal has been received or the deadline has
+/// passed.
+pub fn requested() -> bool {
+	SHUTDOWN_REQUESTED.load(Ordering::Relaxed)
+		|| DEADLINE
+			.get()
+			.is_some_and(|&deadline| Instant::now() >= deadline)
+}
+
+#[cfg(test)]
+mod test {
+	#[test]
+	fn sigint_requests_shutdown() {
+		super::install_handler();
+		assert!(!super::requested());
+
+		// SAFETY: raising a signal we have installed a handler for.
+		unsafe { libc::raise(libc::SIGINT) };
+		assert!(super::requested());
+	}
+}
@@ -0,0 +1,163 @@
+use std::f32::consts::PI;
+
+use crate::{
+	per_channel::PerChannel,
+	processor::Processor,
+	true_peak::{interpolation_filters, OVERSAMPLING, TAPS},
+};
+
+/// Level up to which `shape` passes samples unchanged.
+const THRESHOLD: f32 = 0.5;
+
+/// Taps of the filter that brings the shaped signal back down to the input
+/// rate, centred on an original sample.
+const DECIMATION_TAPS: usize = 2 * (3 + 4 * OVERSAMPLING) + 1;
+
+/// Frames between a sample entering the soft clipper and leaving it.
+pub const DELAY: usize = TAPS - 1;
+
+/// The soft clipper's transfer function: linear up to half of full scale,
+/// then rounding off toward full scale along a `tanh` curve with a matching
+/// slope, so it is continuous and never reaches 1.
+pub fn shape(sample: f32) -> f32 {
+	let magnitude = sample.abs();
+	if magnitude <= THRESHOLD {
+		return sample
+	}
+
+	let over = (magnitude - THRESHOLD) / (1.0 - THRESHOLD);
+	(THRESHOLD + (1.0 - THRESHOLD) * over.tanh()).copysign(sample)
+}
+
+/// Rounds off peaks with `shape` instead of cutting them flat.
+///
+/// Shaping adds harmonics that would alias at the input rate, so only the
+/// part `shape` changes is computed at 4x the rate and filtered back down,
+/// then added to the input delayed by `DELAY` frames. Input that never
+/// exceeds the threshold comes out exactly as it went in. The filter can
+/// ring slightly past the shaped level, so the result is also held to full
+/// scale.
+#[derive(Debug, Clone)]
+pub struct SoftClip {
+	/// Interpolation filters for each fractional position after the first
+	phases: [[f32; TAPS]; OVERSAMPLING - 1],
+	/// Low-pass filter over the oversampled shaping residual
+	decimation: [f32; DECIMATION_TAPS],
+	/// Most recent `TAPS` samples and oversampled shaping residual of each
+	/// channel, oldest first
+	state: PerChannel<([f32; TAPS], [f32; DECIMATION_TAPS])>,
+}
+
+impl SoftClip {
+	pub fn new(channels: usize) -> Self {
+		let centre = (DECIMATION_TAPS / 2) as f32;
+		let mut decimation = [0.0; DECIMATION_TAPS];
+		for (i, tap) in decimation.iter_mut().enumerate() {
+			let distance = i as f32 - centre;
+			let x = PI * distance / OVERSAMPLING as f32;
+			let sinc = if x == 0.0 { 1.0 } else { x.sin() / x };
+			let window = 0.5 * (1.0 + (PI * distance / (centre + 1.0)).cos());
+			*tap = sinc * window;
+		}
+
+		// unity gain at DC
+		let sum = decimation.iter().sum::<f32>();
+		decimation.iter_mut().for_each(|tap| *tap /= sum);
+
+		Self {
+			phases: interpolation_filters(),
+			decimation,
+			state: PerChannel::new(channels, ([0.0; TAPS], [0.0; DECIMATION_TAPS])),
+		}
+	}
+}
+
+impl Processor for SoftClip {
+	fn process(&mut self, input: &[f32], output: &mut [f32]) {
+		assert_eq!(input.len(), output.len());
+
+		for (&sample, output) in input.iter().zip(output) {
+			let (history, residual) = self.state.next_mut();
+			history.copy_within(1.., 0);
+			history[TAPS - 1] = sample;
+
+			// the sample the interpolated positions follow, then each position
+			residual.copy_within(OVERSAMPLING.., 0);
+			let newest = &mut residual[DECIMATION_TAPS - OVERSAMPLING..];
+			let original = history[TAPS / 2 - 1];
+			newest[0] = shape(original) - original;
+			for (value, taps) in newest[1..].iter_mut().zip(&self.phases) {
+				let interpolated = taps
+					.iter()
+					.zip(history.iter())
+					.map(|(t, s)| t * s)
+					.sum::<f32>();
+				*value = shape(interpolated) - interpolated;
+			}
+
+			// the decimation filter is centred on the residual of `history[0]`
+			let correction = self
+				.decimation
+				.iter()
+				.zip(residual.iter())
+				.map(|(t, r)| t * r)
+				.sum::<f32>();
+			*output = (history[0] + correction).clamp(-1.0, 1.0);
+		}
+	}
+
+	fn reset(&mut self) {
+		self.state.reset();
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::{shape, SoftClip, DELAY, THRESHOLD};
+	use crate::{detector::Detector, processor::Processor, test_support::sine};
+
+	#[test]
+	fn transfer_function() {
+		let inputs = (-4000..=4000)
+			.map(|i| i as f32 / 1000.0)
+			.collect::<Vec<_>>();
+
+		for pair in inputs.windows(2) {
+			assert!(shape(pair[0]) <= shape(pair[1]), "falls at {}", pair[1]);
+		}
+
+		for &x in &inputs {
+			assert_eq!(-shape(x), shape(-x), "not odd at {x}");
+			assert!(shape(x).abs() <= 1.0, "unbounded at {x}");
+		}
+		assert!(shape(f32::MAX) <= 1.0 && shape(f32::MIN) >= -1.0);
+
+		// unchanged up to the threshold, rounded off and below full scale after
+		assert_eq!(0.25, shape(0.25));
+		assert_eq!(THRESHOLD, shape(THRESHOLD));
+		assert!(shape(1.0) < 1.0 && shape(1.0) > 0.8);
+	}
+
+	#[test]
+	fn quiet_input_is_only_delayed() {
+		let input = sine(200.0, 0.45, 0.1, 44100);
+		let mut output = vec![0.0; input.len()];
+		SoftClip::new(2).process(&input, &mut output);
+
+		assert!(output[..DELAY * 2].iter().all(|&v| v == 0.0));
+		assert_eq!(input[..input.len() - DELAY * 2], output[DELAY * 2..]);
+	}
+
+	#[test]
+	fn loud_input_is_bounded() {
+		let input = sine(200.0, 4.0, 0.1, 44100);
+		let mut output = vec![0.0; input.len()];
+		SoftClip::new(2).process(&input, &mut output);
+
+		assert!(output.iter().all(|v| v.abs() <= 1.0));
+		assert!(Detector::Peak.level(&output) > 0.9);
+		for frame in output.chunks(2) {
+			assert_eq!(frame[0], -frame[1]);
+		}
+	}
+}
@@ -0,0 +1,119 @@
+//! Processing statistics printed periodically with `--stats`.
+
+use std::time::{Duration, Instant};
+
+/// Counters accumulated over one reporting interval.
+pub struct Stats {
+	interval: Duration,
+	started: Instant,
+	blocks: u32,
+	processing_time: Duration,
+	max_processing_time: Duration,
+	/// Largest absolute input sample
+	peak: f32,
+	/// Sum of the gain applied at the end of each block
+	gain_sum: f32,
+	underruns: u32,
+	overruns: u32,
+	/// Times the main thread woke to check on the session
+	wakeups: u32,
+	/// Read callbacks that found no data
+	empty_reads: u32,
+}
+
+impl Stats {
+	pub fn new(interval: Duration) -> Self {
+		Self {
+			interval,
+			started: Instant::now(),
+			blocks: 0,
+			processing_time: Duration::ZERO,
+			max_processing_time: Duration::ZERO,
+			peak: 0.0,
+			gain_sum: 0.0,
+			underruns: 0,
+			overruns: 0,
+			wakeups: 0,
+			empty_reads: 0,
+		}
+	}
+
+	/// Records one processed block.
+	pub fn block(&mut self, processing_time: Duration, peak: f32, gain: f32) {
+		self.blocks += 1;
+		self.processing_time += processing_time;
+		self.max_processing_time = self.max_processing_time.max(processing_time);
+		self.peak = self.peak.max(peak);
+		self.gain_sum += gain;
+	}
+
+	pub fn underrun(&mut self) {
+		self.underruns += 1;
+	}
+
+	pub fn overrun(&mut self) {
+		self.overruns += 1;
+	}
+
+	pub fn wakeup(&mut self) {
+		self.wakeups += 1;
+	}
+
+	pub fn empty_read(&mut self) {
+		self.empty_reads += 1;
+	}
+
+	/// Prints and resets the counters if the interval has passed.
+	pub fn report_if_due(&mut self) {
+		if self.started.elapsed() >= self.interval {
+			println!("{}", self.summary());
+			*self = Self::new(self.interval);
+		}
+	}
+
+	fn summary(&self) -> String {
+		let (average_time, average_gain) = match self.blocks {
+			0 => (Duration::ZERO, 1.0),
+			blocks => (self.processing_time / blocks, self.gain_sum / blocks as f32),
+		};
+
+		format!(
+			"{} blocks | processing avg {:?} max {:?} | peak {:.1} dB | gain avg {:.3} | {} \
+			 underruns | {} overruns | {} wakeups | {} empty reads",
+			self.blocks,
+			average_time,
+			self.max_processing_time,
+			20.0 * self.peak.log10(),
+			average_gain,
+			self.underruns,
+			self.overruns,
+			self.wakeups,
+			self.empty_reads,
+		)
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use std::time::Duration;
+
+	use super::Stats;
+
+	#[test]
+	fn summary() {
+		let mut stats = Stats::new(Duration::from_secs(1));
+		stats.block(Duration::from_micros(10), 0.5, 1.0);
+		stats.block(Duration::from_micros(30), 0.1, 0.5);
+		stats.underrun();
+		stats.overrun();
+		stats.overrun();
+		stats.wakeup();
+		stats.empty_read();
+
+		assert_eq!(
+			"2 blocks | processing avg 20µs max 30µs | peak -6.0 dB | gain avg 0.750 | 1 \
+			 underruns | 2 overruns | 1 wakeups | 1 empty reads",
+			stats.summary()
+		);
+	}
+}
@@ -0,0 +1,104 @@
+/// Detects sustained silence so processing can be skipped, and fades the
+/// output back in once the input returns.
+#[derive(Debug, Clone)]
+pub struct SilenceSuspend {
+	/// Linear level that counts as input
+	threshold: f32,
+	/// Silent frames after which processing is suspended
+	hold_frames: usize,
+	/// Frames the output fades in over after resuming
+	ramp_frames: usize,
+	/// Consecutive silent frames so far
+	silent_frames: usize,
+	/// Frames of the fade in already output
+	ramp_position: usize,
+}
+
+impl SilenceSuspend {
+	pub fn new(threshold: f32, hold_frames: usize, ramp_frames: usize) -> Self {
+		Self {
+			threshold,
+			hold_frames,
+			ramp_frames,
+			silent_frames: 0,
+			ramp_position: ramp_frames,
+		}
+	}
+
+	/// Returns true once processing is suspended.
+	pub fn is_suspended(&self) -> bool {
+		self.silent_frames >= self.hold_frames
+	}
+
+	/// Updates the silence count with a block of interleaved input, returning
+	/// true if the block can be skipped. Resuming starts the fade in.
+	///
+	/// This stops at the first sample at or above the threshold, so it is much
+	/// cheaper than measuring the level.
+	pub fn update(&mut self, input: &[f32], channels: usize) -> bool {
+		if input.iter().any(|sample| sample.abs() >= self.threshold) {
+			if self.is_suspended() {
+				self.ramp_position = 0;
+			}
+
+			self.silent_frames = 0;
+			return false
+		}
+
+		self.silent_frames = self.silent_frames.saturating_add(input.len() / channels);
+		self.is_suspended()
+	}
+
+	/// Applies the fade in after resuming to interleaved `output`.
+	pub fn ramp(&mut self, output: &mut [f32], channels: usize) {
+		for frame in output.chunks_exact_mut(channels) {
+			if self.ramp_position >= self.ramp_frames {
+				break
+			}
+
+			self.ramp_position += 1;
+			let gain = self.ramp_position as f32 / self.ramp_frames as f32;
+			for sample in frame {
+				*sample *= gain;
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::SilenceSuspend;
+
+	#[test]
+	fn suspends_after_hold() {
+		let mut suspend = SilenceSuspend::new(0.01, 100, 10);
+		let silence = [0.001; 80];
+
+		// 40 stereo frames per block
+		assert!(!suspend.update(&silence, 2));
+		assert!(!suspend.update(&silence, 2));
+		assert!(suspend.update(&silence, 2));
+		assert!(suspend.is_suspended());
+
+		// a single loud sample resumes
+		let mut block = silence;
+		block[79] = 0.5;
+		assert!(!suspend.update(&block, 2));
+		assert!(!suspend.is_suspended());
+	}
+
+	#[test]
+	fn resume_fades_in() {
+		let mut suspend = SilenceSuspend::new(0.01, 10, 4);
+		let mut output = [1.0; 12];
+
+		// no fade before the first suspension
+		suspend.ramp(&mut output, 2);
+		assert_eq!([1.0; 12], output);
+
+		suspend.update(&[0.0; 20], 2);
+		assert!(!suspend.update(&[0.5; 12], 2));
+		suspend.ramp(&mut output, 2);
+		assert_eq!([0.25, 0.25, 0.5, 0.5, 0.75, 0.75, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0], output);
+	}
+}
@@ -0,0 +1,30 @@
+//! Signals and measurements shared by the tests.
+
+use std::f32::consts::TAU;
+
+use crate::processor::Processor;
+
+/// `seconds` of a stereo sine at `hz` with a peak of `level`, the right
+/// channel inverted so that anything mixing up the channels shows.
+pub(crate) fn sine(hz: f32, level: f32, seconds: f32, rate: u32) -> Vec<f32> {
+	(0..(seconds * rate as f32) as usize)
+		.flat_map(|i| {
+			let sample = level * (i as f32 * hz / rate as f32 * TAU).sin();
+			[sample, -sample]
+		})
+		.collect()
+}
+
+/// Runs `input` through `processor` in a single chunk.
+pub(crate) fn process(processor: &mut impl Processor, input: &[f32]) -> Vec<f32> {
+	let mut output = vec![0.0; input.len()];
+	processor.process(input, &mut output);
+	output
+}
+
+/// Returns the RMS level of the second half of `samples`, long after any
+/// filter has settled.
+pub(crate) fn settled_rms(samples: &[f32]) -> f32 {
+	let tail = &samples[samples.len() / 2..];
+	(tail.iter().map(|v| v * v).sum::<f32>() / tail.len() as f32).sqrt()
+}
@@ -0,0 +1,120 @@
+use std::f32::consts::PI;
+
+use crate::per_channel::PerChannel;
+
+/// Intermediate positions evaluated between each pair of samples.
+pub(crate) const OVERSAMPLING: usize = 4;
+
+/// Taps of the interpolation filter, half before and half after the
+/// interpolated position.
+pub(crate) const TAPS: usize = 8;
+
+/// Returns windowed sinc filters for each fractional position after the
+/// first, which interpolate between taps `TAPS / 2 - 1` and `TAPS / 2` of a
+/// window of samples.
+pub(crate) fn interpolation_filters() -> [[f32; TAPS]; OVERSAMPLING - 1] {
+	let mut phases = [[0.0; TAPS]; OVERSAMPLING - 1];
+	for (phase, taps) in phases.iter_mut().enumerate() {
+		let offset = (phase + 1) as f32 / OVERSAMPLING as f32;
+
+		for (i, tap) in taps.iter_mut().enumerate() {
+			// distance from the interpolated position to the tap's sample
+			let distance = offset - (i as f32 - (TAPS / 2 - 1) as f32);
+			let x = PI * distance;
+			let sinc = x.sin() / x;
+			let window = 0.5 * (1.0 + (PI * distance / (TAPS / 2) as f32).cos());
+			*tap = sinc * window;
+		}
+
+		// unity gain at DC
+		let sum = taps.iter().sum::<f32>();
+		taps.iter_mut().for_each(|tap| *tap /= sum);
+	}
+
+	phases
+}
+
+/// Estimates the peak of the reconstructed signal, including peaks between
+/// samples, by oversampling 4x with a windowed sinc interpolator.
+#[derive(Debug, Clone)]
+pub struct TruePeak {
+	/// Interpolation filters for each fractional position after the first
+	phases: [[f32; TAPS]; OVERSAMPLING - 1],
+	/// Most recent `TAPS` samples of each channel, oldest first
+	history: PerChannel<[f32; TAPS]>,
+}
+
+impl TruePeak {
+	pub fn new(channels: usize) -> Self {
+		Self {
+			phases: interpolation_filters(),
+			history: PerChannel::new(channels, [0.0; TAPS]),
+		}
+	}
+
+	/// Returns the estimated true peak of `chunk`.
+	///
+	/// Interpolated positions lag the input by half the filter length, so peaks
+	/// between the last few samples of a chunk are counted in the next one.
+	pub fn level(&mut self, chunk: &[f32]) -> f32 {
+		let mut peak = 0.0f32;
+
+		for &sample in chunk {
+			let history = self.history.next_mut();
+			history.copy_within(1.., 0);
+			history[TAPS - 1] = sample;
+
+			peak = peak.max(sample.abs());
+			for taps in &self.phases {
+				let value = taps
+					.iter()
+					.zip(history.iter())
+					.map(|(t, s)| t * s)
+					.sum::<f32>();
+				peak = peak.max(value.abs());
+			}
+		}
+
+		peak
+	}
+
+	/// Clears the filter state.
+	pub fn reset(&mut self) {
+		self.history.reset();
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use std::f32::consts::{FRAC_PI_2, FRAC_PI_4};
+
+	use super::TruePeak;
+	use crate::detector::Detector;
+
+	#[test]
+	fn inter_sample_peaks() {
+		// a quarter sample rate sine sampled 45 degrees off its peaks, so every
+		// sample is at 1/sqrt(2) of the true amplitude
+		let sine = (0..256)
+			.map(|i| (i as f32 * FRAC_PI_2 + FRAC_PI_4).sin())
+			.collect::<Vec<_>>();
+
+		let sample_peak = Detector::Peak.level(&sine);
+		let true_peak = TruePeak::new(1).level(&sine);
+
+		assert!((sample_peak - std::f32::consts::FRAC_1_SQRT_2).abs() < 1e-3);
+		assert!(true_peak > 0.95 && true_peak < 1.05, "true peak {true_peak}");
+	}
+
+	#[test]
+	fn channels_are_independent() {
+		let sine = (0..256)
+			.map(|i| (i as f32 * FRAC_PI_2 + FRAC_PI_4).sin())
+			.collect::<Vec<_>>();
+		let interleaved = sine.iter().flat_map(|&v| [v, 0.0]).collect::<Vec<_>>();
+
+		// a silent second channel doesn't change the first channel's estimate
+		let mono = TruePeak::new(1).level(&sine);
+		assert_eq!(mono, TruePeak::new(2).level(&interleaved));
+	}
+}
@@ -0,0 +1,129 @@
+use crate::processor::{Measurement, Processor, VolumeCap, VolumeCapSettings};
+
+/// Limits each channel of its input separately, so a loud channel doesn't
+/// reduce the gain of the others.
+#[derive(Debug)]
+pub struct UnlinkedVolumeCap {
+	/// One single channel volume cap per input channel
+	channels: Vec<VolumeCap>,
+	/// Scratch buffers for a single deinterleaved channel
+	input: Vec<f32>,
+	output: Vec<f32>,
+}
+
+impl UnlinkedVolumeCap {
+	/// Create a new unlinked volume cap
+	///
+	/// Each channel gets `settings.chunk_size / settings.channels` samples per
+	/// chunk, so chunks cover the same time as in a linked `VolumeCap`.
+	///
+	/// Panics if `settings.window` or `settings.channels` is zero.
+	pub fn new(settings: &VolumeCapSettings) -> Self {
+		assert!(settings.channels != 0, "UnlinkedVolumeCap channel count must be non-zero");
+
+		let channel_settings = VolumeCapSettings {
+			chunk_size: (settings.chunk_size / settings.channels as usize).max(1),
+			channels: 1,
+			..*settings
+		};
+
+		Self {
+			channels: (0..settings.channels)
+				.map(|_| VolumeCap::new(&channel_settings))
+				.collect(),
+			input: Vec::new(),
+			output: Vec::new(),
+		}
+	}
+
+	/// Returns the highest levels and the lowest gain of any channel for the
+	/// most recently processed chunk.
+	pub fn measurement(&self) -> Measurement {
+		self.channels
+			.iter()
+			.map(VolumeCap::measurement)
+			.reduce(|a, b| Measurement {
+				level: a.level.max(b.level),
+				average: a.average.max(b.average),
+				gain: a.gain.min(b.gain),
+				boost_clamped: a.boost_clamped || b.boost_clamped,
+			})
+			.unwrap_or_default()
+	}
+
+	/// Changes the linear volume cap of every channel, taking effect from the
+	/// next chunk.
+	pub fn set_volume_cap(&mut self, volume_cap: f32) {
+		for channel in &mut self.channels {
+			channel.set_volume_cap(volume_cap);
+		}
+	}
+}
+
+impl Processor for UnlinkedVolumeCap {
+	/// Panics if `input` isn't a whole number of frames.
+	fn process(&mut self, input: &[f32], output: &mut [f32]) {
+		assert_eq!(input.len(), output.len());
+		let channel_count = self.channels.len();
+		assert_eq!(0, input.len() % channel_count, "input must be whole frames");
+
+		for (i, channel) in self.channels.iter_mut().enumerate() {
+			self.input.clear();
+			self.input
+				.extend(input.iter().skip(i).step_by(channel_count));
+			self.output.resize(self.input.len(), 0.0);
+
+			channel.process(&self.input, &mut self.output);
+
+			for (output, &sample) in output
+				.iter_mut()
+				.skip(i)
+				.step_by(channel_count)
+				.zip(&self.output)
+			{
+				*output = sample;
+			}
+		}
+	}
+
+	fn reset(&mut self) {
+		for channel in &mut self.channels {
+			channel.reset();
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::UnlinkedVolumeCap;
+	use crate::{
+		processor::{VolumeCap, VolumeCapSettings},
+		test_support::process,
+	};
+
+	fn settings() -> VolumeCapSettings {
+		VolumeCapSettings {
+			volume_cap: 0.5,
+			..Default::default()
+		}
+	}
+
+	#[test]
+	fn channels_are_independent() {
+		// a loud left channel and a quiet right channel
+		let input = [1.0, 0.25].repeat(64 * 256);
+
+		let mut unlinked = UnlinkedVolumeCap::new(&settings());
+		let output = process(&mut unlinked, &input);
+		for frame in output[128..].chunks(2) {
+			assert!((frame[0] - 0.5).abs() < 1e-6, "left {}", frame[0]);
+			assert!((frame[1] - 0.25).abs() < 1e-6, "right {}", frame[1]);
+		}
+		assert!((unlinked.measurement().gain - 0.5).abs() < 1e-6);
+
+		// linked, the right channel is reduced along with the left
+		let mut linked = VolumeCap::new(&settings());
+		let output = process(&mut linked, &input);
+		assert!((output.last().unwrap() - 0.125).abs() < 1e-6);
+	}
+}
@@ -0,0 +1,257 @@
+//! Minimal WAV file support for recording processed audio and reading test
+//! input.
+
+use std::{
+	fs::File,
+	io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+	path::Path,
+};
+
+use crate::sample::SampleFormat;
+
+/// Size of the RIFF, fmt and data chunk headers written by `WavWriter`.
+const HEADER_SIZE: u32 = 44;
+
+const FORMAT_PCM: u16 = 1;
+const FORMAT_IEEE_FLOAT: u16 = 3;
+
+/// Writes interleaved samples to a WAV file.
+///
+/// The chunk sizes in the header are only correct after `finalize`.
+pub struct WavWriter {
+	file: BufWriter<File>,
+	/// Bytes of sample data written so far
+	data_size: u32,
+}
+
+impl WavWriter {
+	/// Creates `path` and writes a header for the given format.
+	pub fn create(path: &Path, format: SampleFormat, rate: u32, channels: u8) -> io::Result<Self> {
+		let mut file = BufWriter::new(File::create(path)?);
+
+		let format_tag = match format {
+			SampleFormat::F32le => FORMAT_IEEE_FLOAT,
+			SampleFormat::S16le => FORMAT_PCM,
+		};
+		let block_align = format.size() as u16 * channels as u16;
+
+		file.write_all(b"RIFF")?;
+		file.write_all(&(HEADER_SIZE - 8).to_le_bytes())?;
+		file.write_all(b"WAVE")?;
+
+		file.write_all(b"fmt ")?;
+		file.write_all(&16u32.to_le_bytes())?;
+		file.write_all(&format_tag.to_le_bytes())?;
+		file.write_all(&(channels as u16).to_le_bytes())?;
+		file.write_all(&rate.to_le_bytes())?;
+		file.write_all(&(rate * block_align as u32).to_le_bytes())?;
+		file.write_all(&block_align.to_le_bytes())?;
+		file.write_all(&(format.size() as u16 * 8).to_le_bytes())?;
+
+		file.write_all(b"data")?;
+		file.write_all(&0u32.to_le_bytes())?;
+
+		Ok(Self { file, data_size: 0 })
+	}
+
+	/// Appends encoded sample data.
+	pub fn write(&mut self, data: &[u8]) -> io::Result<()> {
+		// WAV sizes are 32 bit, drop anything past the largest representable file
+		let available = (u32::MAX - HEADER_SIZE - self.data_size) as usize;
+		let data = &data[..data.len().min(available)];
+
+		self.file.write_all(data)?;
+		self.data_size += data.len() as u32;
+		Ok(())
+	}
+
+	/// Writes the final chunk sizes into the header and flushes the file.
+	///
+	/// Writing may continue afterwards, but the file must be finalized again.
+	pub fn finalize(&mut self) -> io::Result<()> {
+		self.file.seek(SeekFrom::Start(4))?;
+		self.file
+			.write_all(&(HEADER_SIZE - 8 + self.data_size).to_le_bytes())?;
+		self.file.seek(SeekFrom::Start(HEADER_SIZE as u64 - 4))?;
+		self.file.write_all(&self.data_size.to_le_bytes())?;
+		self.file.seek(SeekFrom::End(0))?;
+		self.file.flush()
+	}
+}
+
+/// Reads interleaved samples from a WAV file.
+pub struct WavReader {
+	file: BufReader<File>,
+	format: SampleFormat,
+	rate: u32,
+	channels: u8,
+	/// Bytes of sample data left in the data chunk
+	remaining: u64,
+}
+
+impl WavReader {
+	/// Opens `path` and reads its header, leaving the reader at the start of
+	/// the sample data.
+	///
+	/// Only 32 bit float and 16 bit PCM files are supported.
+	pub fn open(path: &Path) -> io::Result<Self> {
+		let mut file = BufReader::new(File::open(path)?);
+
+		let mut riff = [0; 12];
+		file.read_exact(&mut riff)?;
+		if &riff[..4] != b"RIFF" || &riff[8..] != b"WAVE" {
+			return Err(invalid_data("not a WAV file"))
+		}
+
+		let mut spec = None;
+		loop {
+			let mut header = [0; 8];
+			file.read_exact(&mut header)?;
+			let size = u32::from_le_bytes(header[4..].try_into().unwrap());
+
+			match &header[..4] {
+				b"fmt " => {
+					let mut fmt = vec![0; size as usize];
+					file.read_exact(&mut fmt)?;
+					if fmt.len() < 16 {
+						return Err(invalid_data("truncated fmt chunk"))
+					}
+
+					let u16_at = |offset: usize| u16::from_le_bytes([fmt[offset], fmt[offset + 1]]);
+					let format = match (u16_at(0), u16_at(14)) {
+						(FORMAT_IEEE_FLOAT, 32) => SampleFormat::F32le,
+						(FORMAT_PCM, 16) => SampleFormat::S16le,
+						_ =>
+							return Err(invalid_data(
+								"only 32 bit float and 16 bit PCM are supported",
+							)),
+					};
+					let channels =
+						u8::try_from(u16_at(2)).map_err(|_| invalid_data("too many channels"))?;
+					let rate = u32::from_le_bytes(fmt[4..8].try_into().unwrap());
+
+					spec = Some((format, rate, channels));
+				}
+				b"data" => {
+					let (format, rate, channels) =
+						spec.ok_or_else(|| invalid_data("data chunk before fmt chunk"))?;
+
+					return Ok(Self {
+						file,
+						format,
+						rate,
+						channels,
+						remaining: size as u64,
+					})
+				}
+				_ => file.seek_relative(size as i64)?,
+			}
+
+			// chunks are padded to an even size
+			if size % 2 == 1 {
+				file.seek_relative(1)?;
+			}
+		}
+	}
+
+	pub fn format(&self) -> SampleFormat {
+		self.format
+	}
+
+	pub fn rate(&self) -> u32 {
+		self.rate
+	}
+
+	pub fn channels(&self) -> u8 {
+		self.channels
+	}
+
+	/// Reads whole frames of sample data into `buffer`, returning the number
+	/// of bytes read. Returns 0 at the end of the data.
+	pub fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+		let frame_size = self.format.size() * self.channels as usize;
+		let len = (buffer.len() as u64).min(self.remaining) as usize;
+		let len = len - len % frame_size;
+
+		let mut read = 0;
+		while read < len {
+			match self.file.read(&mut buffer[read..len])? {
+				0 => break,
+				n => read += n,
+			}
+		}
+
+		self.remaining -= read as u64;
+		if read < len {
+			// a truncated file ends at its last whole frame
+			self.remaining = 0;
+		}
+
+		Ok(read - read % frame_size)
+	}
+}
+
+fn invalid_data(message: &str) -> io::Error {
+	io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod test {
+	use std::{env, fs, process};
+
+	use super::{WavReader, WavWriter};
+	use crate::sample::SampleFormat;
+
+	#[test]
+	fn header_sizes() {
+		let path = env::temp_dir().join(format!("shoosh-test-{}.wav", process::id()));
+
+		let mut writer = WavWriter::create(&path, SampleFormat::S16le, 48000, 2).unwrap();
+		writer.write(&[0; 400]).unwrap();
+		writer.finalize().unwrap();
+		drop(writer);
+
+		let file = fs::read(&path).unwrap();
+		fs::remove_file(&path).unwrap();
+
+		let u32_at =
+			|offset: usize| u32::from_le_bytes(file[offset..offset + 4].try_into().unwrap());
+
+		assert_eq!(444, file.len());
+		assert_eq!(b"RIFF", &file[..4]);
+		assert_eq!(436, u32_at(4));
+		assert_eq!(48000, u32_at(24));
+		assert_eq!(48000 * 4, u32_at(28));
+		assert_eq!(b"data", &file[36..40]);
+		assert_eq!(400, u32_at(40));
+	}
+
+	#[test]
+	fn round_trip() {
+		let path = env::temp_dir().join(format!("shoosh-test-round-trip-{}.wav", process::id()));
+		let data = (0..=255).collect::<Vec<u8>>();
+
+		let mut writer = WavWriter::create(&path, SampleFormat::F32le, 44100, 2).unwrap();
+		writer.write(&data).unwrap();
+		writer.finalize().unwrap();
+		drop(writer);
+
+		let mut reader = WavReader::open(&path).unwrap();
+		assert_eq!(SampleFormat::F32le, reader.format());
+		assert_eq!(44100, reader.rate());
+		assert_eq!(2, reader.channels());
+
+		// reads stop at frame boundaries
+		let mut buffer = [0; 100];
+		assert_eq!(96, reader.read(&mut buffer).unwrap());
+		let mut read = buffer[..96].to_vec();
+
+		let mut buffer = [0; 1024];
+		let n = reader.read(&mut buffer).unwrap();
+		read.extend_from_slice(&buffer[..n]);
+		assert_eq!(0, reader.read(&mut buffer).unwrap());
+
+		fs::remove_file(&path).unwrap();
+		assert_eq!(data, read);
+	}
+}
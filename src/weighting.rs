@@ -0,0 +1,179 @@
+use std::str::FromStr;
+
+/// How much each entry of the volume history contributes to the average.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Weighting {
+	/// Weight grows linearly from the oldest entry to the newest
+	Linear,
+	/// Each entry weighs `decay` times the next newer one
+	Exponential { decay: f32 },
+	/// All entries weigh the same, giving the arithmetic mean
+	Flat,
+	/// Exponential moving average with a time constant of the window length
+	///
+	/// Weighs entries like `Exponential` with a decay of `ema_decay(n)`, but
+	/// over the whole history instead of a window, so a `VolumeCap` updates it
+	/// in constant time per chunk.
+	Ema,
+}
+
+impl Weighting {
+	/// Decay of `Exponential` when parsed.
+	pub const DEFAULT_DECAY: f32 = 0.95;
+	/// Names accepted by `from_str`.
+	pub const NAMES: [&'static str; 4] = ["linear", "exponential", "flat", "ema"];
+
+	/// Returns the name `from_str` accepts for this weighting, which doesn't
+	/// include the decay of `Exponential`.
+	pub fn name(self) -> &'static str {
+		match self {
+			Self::Linear => "linear",
+			Self::Exponential { .. } => "exponential",
+			Self::Flat => "flat",
+			Self::Ema => "ema",
+		}
+	}
+
+	/// Returns the weight of entry `i` of a window of `n` entries, where entry
+	/// 0 is the oldest.
+	pub fn weight(self, i: usize, n: usize) -> f32 {
+		match self {
+			Self::Linear => i as f32 / n as f32,
+			Self::Exponential { decay } => decay.powi((n - 1 - i) as i32),
+			Self::Flat => 1.0,
+			Self::Ema => Self::ema_decay(n).powi((n - 1 - i) as i32),
+		}
+	}
+
+	/// Returns the divisor that normalizes a weighted sum over a full window of
+	/// `n` entries, the sum of its weights.
+	pub fn normalization(self, n: usize) -> f32 {
+		(0..n).map(|i| self.weight(i, n)).sum()
+	}
+
+	/// Returns the per-entry decay of an exponential moving average with a
+	/// time constant of `n` entries.
+	pub fn ema_decay(n: usize) -> f32 {
+		(-1.0 / n as f32).exp()
+	}
+
+	/// Returns the normalized weight of every entry of a window of `n`
+	/// entries, oldest first, so a weighted average is a single dot product.
+	///
+	/// A window whose weights are all 0, as a linear window of one entry is,
+	/// is averaged flat instead.
+	pub fn table(self, n: usize) -> Vec<f32> {
+		let normalization = self.normalization(n);
+		if normalization == 0.0 {
+			return Self::Flat.table(n)
+		}
+
+		(0..n).map(|i| self.weight(i, n) / normalization).collect()
+	}
+}
+
+impl FromStr for Weighting {
+	type Err = String;
+
+	/// Parses a weighting name, giving `Exponential` a decay of
+	/// `DEFAULT_DECAY`.
+	fn from_str(name: &str) -> Result<Self, String> {
+		match name {
+			"linear" => Ok(Self::Linear),
+			"exponential" => Ok(Self::Exponential {
+				decay: Self::DEFAULT_DECAY,
+			}),
+			"flat" => Ok(Self::Flat),
+			"ema" => Ok(Self::Ema),
+			_ => Err(format!(
+				"invalid weighting '{name}', expected one of: {}",
+				Self::NAMES.join(", ")
+			)),
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::Weighting;
+
+	const WEIGHTINGS: [Weighting; 6] = [
+		Weighting::Linear,
+		Weighting::Exponential { decay: 0.5 },
+		Weighting::Exponential { decay: 0.95 },
+		Weighting::Exponential { decay: 1.0 },
+		Weighting::Flat,
+		Weighting::Ema,
+	];
+
+	#[test]
+	fn parse() {
+		for name in Weighting::NAMES {
+			assert!(name.parse::<Weighting>().is_ok(), "{name}");
+		}
+		assert_eq!(Ok(Weighting::Exponential { decay: 0.95 }), "exponential".parse());
+		assert_eq!(
+			Err("invalid weighting 'Linear', expected one of: linear, exponential, flat, ema"
+				.to_string()),
+			"Linear".parse::<Weighting>()
+		);
+	}
+
+	#[test]
+	fn weights_sum_to_one() {
+		for n in [1, 2, 16, 128] {
+			for weighting in WEIGHTINGS {
+				let sum = weighting.table(n).iter().sum::<f32>();
+				assert!((sum - 1.0).abs() < 1e-5, "{weighting:?}, n {n}: {sum}");
+			}
+		}
+	}
+
+	#[test]
+	fn constant_averages_to_itself() {
+		for n in [1, 2, 16, 128] {
+			for weighting in WEIGHTINGS {
+				let average = weighting
+					.table(n)
+					.iter()
+					.map(|weight| weight * 0.3)
+					.sum::<f32>();
+				assert!((average - 0.3).abs() < 1e-6, "{weighting:?}, n {n}: {average}");
+			}
+		}
+	}
+
+	#[test]
+	fn newer_entries_weigh_more() {
+		for weighting in [Weighting::Linear, Weighting::Exponential { decay: 0.9 }] {
+			assert!(weighting.weight(0, 8) < weighting.weight(7, 8));
+		}
+	}
+
+	#[test]
+	fn table_matches_weights() {
+		let weighting = Weighting::Exponential { decay: 0.9 };
+		let table = weighting.table(16);
+
+		assert_eq!(16, table.len());
+		for (i, weight) in table.iter().enumerate() {
+			let expected = weighting.weight(i, 16) / weighting.normalization(16);
+			assert!((weight - expected).abs() < 1e-7);
+		}
+	}
+
+	#[test]
+	fn flat_is_arithmetic_mean() {
+		let values = [0.1, 0.4, 0.2, 0.9, 0.6];
+		let n = values.len();
+
+		let average = values
+			.iter()
+			.enumerate()
+			.map(|(i, v)| v * Weighting::Flat.weight(i, n))
+			.sum::<f32>()
+			/ Weighting::Flat.normalization(n);
+
+		assert!((average - values.iter().sum::<f32>() / n as f32).abs() < 1e-6);
+	}
+}